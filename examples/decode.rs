@@ -18,7 +18,8 @@ fn main() {
 
     let input_file = File::open(input_path).expect("The specified input file could not be opened");
     let mut decoder = jpeg::Decoder::new(BufReader::new(input_file));
-    let mut data = decoder.decode().expect("Decoding failed. If other software can successfully decode the specified JPEG image, then it's likely that there is a bug in jpeg-decoder");
+    decoder.set_cmyk_to_rgb(true);
+    let data = decoder.decode().expect("Decoding failed. If other software can successfully decode the specified JPEG image, then it's likely that there is a bug in jpeg-decoder");
     let info = decoder.info().unwrap();
 
     eprintln!("{:?}", info);
@@ -38,15 +39,26 @@ fn main() {
             encoder.set_depth(png::BitDepth::Eight);
             encoder.set_color(png::ColorType::RGB);
         },
-        jpeg::PixelFormat::CMYK32 => {
-            data = cmyk_to_rgb(&mut data);
-            encoder.set_depth(png::BitDepth::Eight);
-            encoder.set_color(png::ColorType::RGB)
-        },
         jpeg::PixelFormat::L8 => {
             encoder.set_depth(png::BitDepth::Eight);
             encoder.set_color(png::ColorType::Grayscale);
         },
+        jpeg::PixelFormat::RGB48 => {
+            encoder.set_depth(png::BitDepth::Sixteen);
+            encoder.set_color(png::ColorType::RGB);
+        },
+        jpeg::PixelFormat::CMYK32 | jpeg::PixelFormat::CMYK64 => {
+            eprintln!("CMYK output can not be written as PNG");
+            process::exit(1)
+        },
+        jpeg::PixelFormat::Channels8(channels) | jpeg::PixelFormat::Channels16(channels) => {
+            eprintln!("{} channel output can not be written as PNG", channels);
+            process::exit(1)
+        },
+        pixel_format => {
+            eprintln!("{:?} output can not be written as PNG", pixel_format);
+            process::exit(1)
+        },
     }
     
     encoder.write_header()
@@ -54,31 +66,3 @@ fn main() {
            .write_image_data(&data)
            .expect("png encoding failed");
 }
-
-fn cmyk_to_rgb(input: &[u8]) -> Vec<u8> {
-    let size = input.len() - input.len() / 4;
-    let mut output = Vec::with_capacity(size);
-
-    for pixel in input.chunks(4) {
-        let c = pixel[0] as f32 / 255.0;
-        let m = pixel[1] as f32 / 255.0;
-        let y = pixel[2] as f32 / 255.0;
-        let k = pixel[3] as f32 / 255.0;
-
-        // CMYK -> CMY
-        let c = c * (1.0 - k) + k;
-        let m = m * (1.0 - k) + k;
-        let y = y * (1.0 - k) + k;
-
-        // CMY -> RGB
-        let r = (1.0 - c) * 255.0;
-        let g = (1.0 - m) * 255.0;
-        let b = (1.0 - y) * 255.0;
-
-        output.push(r as u8);
-        output.push(g as u8);
-        output.push(b as u8);
-    }
-
-    output
-}
@@ -4,14 +4,15 @@ mod neon;
 mod ssse3;
 mod wasm;
 
+use crate::decoder::OutputLayout;
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 use std::is_x86_feature_detected;
 
 /// Arch-specific implementation of YCbCr conversion. Returns the number of pixels that were
-/// converted.
+/// converted, which is none for the luma layout.
 #[allow(clippy::type_complexity)]
-pub fn get_color_convert_line_ycbcr() -> Option<unsafe fn(&[u8], &[u8], &[u8], &mut [u8]) -> usize>
-{
+pub fn get_color_convert_line_ycbcr(
+) -> Option<unsafe fn(&[u8], &[u8], &[u8], &mut [u8], OutputLayout) -> usize> {
     #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
     #[allow(unsafe_code)]
     {
@@ -32,6 +33,31 @@ pub fn get_color_convert_line_ycbcr() -> Option<unsafe fn(&[u8], &[u8], &[u8], &
     None
 }
 
+/// Arch-specific implementation of bg-sYCC conversion. Returns the number of pixels that were
+/// converted, which is none for the luma layout.
+#[allow(clippy::type_complexity)]
+pub fn get_color_convert_line_bg_ycc(
+) -> Option<unsafe fn(&[u8], &[u8], &[u8], &mut [u8], OutputLayout) -> usize> {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[allow(unsafe_code)]
+    {
+        if is_x86_feature_detected!("ssse3") {
+            return Some(ssse3::color_convert_line_bg_ycc);
+        }
+    }
+    // Runtime detection is not needed on aarch64.
+    #[cfg(all(feature = "nightly_aarch64_neon", target_arch = "aarch64"))]
+    {
+        return Some(neon::color_convert_line_bg_ycc);
+    }
+    #[cfg(all(target_feature = "simd128", target_arch = "wasm32"))]
+    {
+        return Some(wasm::color_convert_line_bg_ycc);
+    }
+    #[allow(unreachable_code)]
+    None
+}
+
 /// Arch-specific implementation of 8x8 IDCT.
 #[allow(clippy::type_complexity)]
 pub fn get_dequantize_and_idct_block_8x8(
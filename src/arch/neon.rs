@@ -1,6 +1,9 @@
 #[cfg(all(feature = "nightly_aarch64_neon", target_arch = "aarch64"))]
 use core::arch::aarch64::*;
 
+#[cfg(all(feature = "nightly_aarch64_neon", target_arch = "aarch64"))]
+use crate::decoder::OutputLayout;
+
 #[cfg(all(feature = "nightly_aarch64_neon", target_arch = "aarch64"))]
 #[target_feature(enable = "neon")]
 unsafe fn idct8(data: &mut [int16x8_t; 8]) {
@@ -168,9 +171,45 @@ pub unsafe fn dequantize_and_idct_block_8x8(
 
 #[cfg(all(feature = "nightly_aarch64_neon", target_arch = "aarch64"))]
 #[target_feature(enable = "neon")]
-pub unsafe fn color_convert_line_ycbcr(y: &[u8], cb: &[u8], cr: &[u8], output: &mut [u8]) -> usize {
-    assert!(output.len() % 3 == 0);
-    let num = output.len() / 3;
+pub unsafe fn color_convert_line_ycbcr(
+    y: &[u8],
+    cb: &[u8],
+    cr: &[u8],
+    output: &mut [u8],
+    layout: OutputLayout,
+) -> usize {
+    color_convert_line_ycc::<false>(y, cb, cr, output, layout)
+}
+
+#[cfg(all(feature = "nightly_aarch64_neon", target_arch = "aarch64"))]
+#[target_feature(enable = "neon")]
+pub unsafe fn color_convert_line_bg_ycc(
+    y: &[u8],
+    cb: &[u8],
+    cr: &[u8],
+    output: &mut [u8],
+    layout: OutputLayout,
+) -> usize {
+    color_convert_line_ycc::<true>(y, cb, cr, output, layout)
+}
+
+#[cfg(all(feature = "nightly_aarch64_neon", target_arch = "aarch64"))]
+#[target_feature(enable = "neon")]
+unsafe fn color_convert_line_ycc<const BIG_GAMUT: bool>(
+    y: &[u8],
+    cb: &[u8],
+    cr: &[u8],
+    output: &mut [u8],
+    layout: OutputLayout,
+) -> usize {
+    // The luma is left to the caller.
+    if layout == OutputLayout::Luma {
+        return 0;
+    }
+
+    let pixel_size = layout.samples_per_pixel(3);
+    assert!(output.len() % pixel_size == 0);
+    let num = output.len() / pixel_size;
     assert!(num <= y.len());
     assert!(num <= cb.len());
     assert!(num <= cr.len());
@@ -194,6 +233,13 @@ pub unsafe fn color_convert_line_ycbcr(y: &[u8], cb: &[u8], cr: &[u8], output: &
         let cb = vqsubq_s16(cb, c128);
         let cr = vqsubq_s16(cr, c128);
 
+        // bg-sYCC stores the chroma at half scale.
+        let (cb, cr) = if BIG_GAMUT {
+            (vqaddq_s16(cb, cb), vqaddq_s16(cr, cr))
+        } else {
+            (cb, cr)
+        };
+
         // Compute cr * 1.402, cb * 0.34414, cr * 0.71414, cb * 1.772
         let cr_140200 = vqaddq_s16(vqrdmulhq_n_s16(cr, 13173), cr);
         let cb_034414 = vqrdmulhq_n_s16(cb, 11276);
@@ -210,11 +256,27 @@ pub unsafe fn color_convert_line_ycbcr(y: &[u8], cb: &[u8], cr: &[u8], output: &
         let g = vqshrun_n_s16(g, SHIFT);
         let b = vqshrun_n_s16(b, SHIFT);
 
+        // The channels in the order of the layout, the fourth is only stored for layouts with
+        // alpha.
+        let opaque = vdup_n_u8(255);
+        let [c0, c1, c2, c3] = match layout {
+            OutputLayout::Bgr | OutputLayout::Bgra => [b, g, r, opaque],
+            OutputLayout::Argb => [opaque, r, g, b],
+            _ => [r, g, b, opaque],
+        };
+
         // Shuffle + store.
-        vst3_u8(
-            output.as_mut_ptr().wrapping_add(24 * i),
-            uint8x8x3_t(r, g, b),
-        );
+        if pixel_size == 4 {
+            vst4_u8(
+                output.as_mut_ptr().wrapping_add(32 * i),
+                uint8x8x4_t(c0, c1, c2, c3),
+            );
+        } else {
+            vst3_u8(
+                output.as_mut_ptr().wrapping_add(24 * i),
+                uint8x8x3_t(c0, c1, c2),
+            );
+        }
     }
 
     num_vecs * 8
@@ -3,6 +3,9 @@ use std::arch::x86::*;
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::*;
 
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::decoder::OutputLayout;
+
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 #[target_feature(enable = "ssse3")]
 unsafe fn idct8(data: &mut [__m128i; 8]) {
@@ -193,9 +196,45 @@ pub unsafe fn dequantize_and_idct_block_8x8(
 
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 #[target_feature(enable = "ssse3")]
-pub unsafe fn color_convert_line_ycbcr(y: &[u8], cb: &[u8], cr: &[u8], output: &mut [u8]) -> usize {
-    assert!(output.len() % 3 == 0);
-    let num = output.len() / 3;
+pub unsafe fn color_convert_line_ycbcr(
+    y: &[u8],
+    cb: &[u8],
+    cr: &[u8],
+    output: &mut [u8],
+    layout: OutputLayout,
+) -> usize {
+    color_convert_line_ycc::<false>(y, cb, cr, output, layout)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "ssse3")]
+pub unsafe fn color_convert_line_bg_ycc(
+    y: &[u8],
+    cb: &[u8],
+    cr: &[u8],
+    output: &mut [u8],
+    layout: OutputLayout,
+) -> usize {
+    color_convert_line_ycc::<true>(y, cb, cr, output, layout)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "ssse3")]
+unsafe fn color_convert_line_ycc<const BIG_GAMUT: bool>(
+    y: &[u8],
+    cb: &[u8],
+    cr: &[u8],
+    output: &mut [u8],
+    layout: OutputLayout,
+) -> usize {
+    // The luma is left to the caller.
+    if layout == OutputLayout::Luma {
+        return 0;
+    }
+
+    let pixel_size = layout.samples_per_pixel(3);
+    assert!(output.len() % pixel_size == 0);
+    let num = output.len() / pixel_size;
     assert!(num <= y.len());
     assert!(num <= cb.len());
     assert!(num <= cr.len());
@@ -226,6 +265,13 @@ pub unsafe fn color_convert_line_ycbcr(y: &[u8], cb: &[u8], cr: &[u8], output: &
         let cb = _mm_subs_epi16(cb, c128);
         let cr = _mm_subs_epi16(cr, c128);
 
+        // bg-sYCC stores the chroma at half scale.
+        let (cb, cr) = if BIG_GAMUT {
+            (_mm_adds_epi16(cb, cb), _mm_adds_epi16(cr, cr))
+        } else {
+            (cb, cr)
+        };
+
         // Compute cr * 1.402, cb * 0.34414, cr * 0.71414, cb * 1.772
         let cr_140200 = _mm_adds_epi16(_mm_mulhrs_epi16(cr, _mm_set1_epi16(13173)), cr);
         let cb_034414 = _mm_mulhrs_epi16(cb, _mm_set1_epi16(11276));
@@ -243,6 +289,28 @@ pub unsafe fn color_convert_line_ycbcr(y: &[u8], cb: &[u8], cr: &[u8], output: &
         let g = _mm_packus_epi16(_mm_srai_epi16(g, SHIFT), zero);
         let b = _mm_packus_epi16(_mm_srai_epi16(b, SHIFT), zero);
 
+        // The channels in the order of the layout, the fourth is only stored for layouts with
+        // alpha.
+        let opaque = _mm_set1_epi8(-1);
+        let [c0, c1, c2, c3] = match layout {
+            OutputLayout::Bgr | OutputLayout::Bgra => [b, g, r, opaque],
+            OutputLayout::Argb => [opaque, r, g, b],
+            _ => [r, g, b, opaque],
+        };
+
+        if pixel_size == 4 {
+            // Interleave rrrrrrrrggggggggbbbbbbbbaaaaaaaa to rgbargbargba...
+            let c01 = _mm_unpacklo_epi8(c0, c1);
+            let c23 = _mm_unpacklo_epi8(c2, c3);
+            let pixels = output.as_mut_ptr().wrapping_add(32 * i);
+            _mm_storeu_si128(pixels as *mut _, _mm_unpacklo_epi16(c01, c23));
+            _mm_storeu_si128(
+                pixels.wrapping_add(16) as *mut _,
+                _mm_unpackhi_epi16(c01, c23),
+            );
+            continue;
+        }
+
         // Shuffle rrrrrrrrggggggggbbbbbbbb to rgbrgbrgb...
 
         // Control vectors for _mm_shuffle_epi8. -0x7F is selected so that the resulting position
@@ -258,8 +326,8 @@ pub unsafe fn color_convert_line_ycbcr(y: &[u8], cb: &[u8], cr: &[u8], output: &
         let shufb = _mm_alignr_epi8(shufg, shufg, 15);
 
         let rgb_low = _mm_or_si128(
-            _mm_shuffle_epi8(r, shufr),
-            _mm_or_si128(_mm_shuffle_epi8(g, shufg), _mm_shuffle_epi8(b, shufb)),
+            _mm_shuffle_epi8(c0, shufr),
+            _mm_or_si128(_mm_shuffle_epi8(c1, shufg), _mm_shuffle_epi8(c2, shufb)),
         );
 
         // For the next part of the rgb vectors, we need to select R values from 6 up, G and B from
@@ -270,8 +338,8 @@ pub unsafe fn color_convert_line_ycbcr(y: &[u8], cb: &[u8], cr: &[u8], output: &
         let shufb1 = _mm_add_epi8(shufg, _mm_set1_epi8(5));
 
         let rgb_hi = _mm_or_si128(
-            _mm_shuffle_epi8(r, shufr1),
-            _mm_or_si128(_mm_shuffle_epi8(g, shufg1), _mm_shuffle_epi8(b, shufb1)),
+            _mm_shuffle_epi8(c0, shufr1),
+            _mm_or_si128(_mm_shuffle_epi8(c1, shufg1), _mm_shuffle_epi8(c2, shufb1)),
         );
 
         let mut data = [0u8; 32];
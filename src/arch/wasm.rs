@@ -1,6 +1,9 @@
 #[cfg(target_arch = "wasm32")]
 use std::arch::wasm32::*;
 
+#[cfg(target_arch = "wasm32")]
+use crate::decoder::OutputLayout;
+
 #[cfg(target_arch = "wasm32")]
 #[target_feature(enable = "simd128")]
 fn idct8(data: &mut [v128; 8]) {
@@ -188,10 +191,28 @@ pub fn dequantize_and_idct_block_8x8(
 
 #[cfg(target_arch = "wasm32")]
 #[target_feature(enable = "simd128")]
-pub fn color_convert_line_ycbcr(y_slice: &[u8], cb_slice: &[u8], cr_slice: &[u8], output: &mut [u8]) -> usize {
+pub fn color_convert_line_ycbcr(y_slice: &[u8], cb_slice: &[u8], cr_slice: &[u8], output: &mut [u8], layout: OutputLayout) -> usize {
+    color_convert_line_ycc::<false>(y_slice, cb_slice, cr_slice, output, layout)
+}
+
+#[cfg(target_arch = "wasm32")]
+#[target_feature(enable = "simd128")]
+pub fn color_convert_line_bg_ycc(y_slice: &[u8], cb_slice: &[u8], cr_slice: &[u8], output: &mut [u8], layout: OutputLayout) -> usize {
+    color_convert_line_ycc::<true>(y_slice, cb_slice, cr_slice, output, layout)
+}
 
-    assert!(output.len() % 3 == 0);
-    let num = output.len() / 3;
+#[cfg(target_arch = "wasm32")]
+#[target_feature(enable = "simd128")]
+fn color_convert_line_ycc<const BIG_GAMUT: bool>(y_slice: &[u8], cb_slice: &[u8], cr_slice: &[u8], output: &mut [u8], layout: OutputLayout) -> usize {
+
+    // The luma is left to the caller.
+    if layout == OutputLayout::Luma {
+        return 0;
+    }
+
+    let pixel_size = layout.samples_per_pixel(3);
+    assert!(output.len() % pixel_size == 0);
+    let num = output.len() / pixel_size;
     assert!(num <= y_slice.len());
     assert!(num <= cb_slice.len());
     assert!(num <= cr_slice.len());
@@ -223,6 +244,13 @@ pub fn color_convert_line_ycbcr(y_slice: &[u8], cb_slice: &[u8], cr_slice: &[u8]
         let cb = i16x8_sub_sat(cb, c128);
         let cr = i16x8_sub_sat(cr, c128);
 
+        // bg-sYCC stores the chroma at half scale.
+        let (cb, cr) = if BIG_GAMUT {
+            (i16x8_add_sat(cb, cb), i16x8_add_sat(cr, cr))
+        } else {
+            (cb, cr)
+        };
+
         // Compute cr * 1.402, cb * 0.34414, cr * 0.71414, cb * 1.772
         let cr_140200 = i16x8_add_sat(i16x8_q15mulr_sat(cr, i16x8_splat(13173)), cr);
         let cb_034414 = i16x8_q15mulr_sat(cb, i16x8_splat(11276));
@@ -240,6 +268,31 @@ pub fn color_convert_line_ycbcr(y_slice: &[u8], cb_slice: &[u8], cr_slice: &[u8]
         let g = u8x16_narrow_i16x8(i16x8_shr(g, SHIFT), zero);
         let b = u8x16_narrow_i16x8(i16x8_shr(b, SHIFT), zero);
 
+        // The channels in the order of the layout, the fourth is only stored for layouts with
+        // alpha.
+        let opaque = u8x16_splat(255);
+        let [c0, c1, c2, c3] = match layout {
+            OutputLayout::Bgr | OutputLayout::Bgra => [b, g, r, opaque],
+            OutputLayout::Argb => [opaque, r, g, b],
+            _ => [r, g, b, opaque],
+        };
+
+        if pixel_size == 4 {
+            // Interleave rrrrrrrrggggggggbbbbbbbbaaaaaaaa to rgbargbargba...
+            let c01 = i8x16_shuffle::<0, 16, 1, 17, 2, 18, 3, 19, 4, 20, 5, 21, 6, 22, 7, 23>(c0, c1);
+            let c23 = i8x16_shuffle::<0, 16, 1, 17, 2, 18, 3, 19, 4, 20, 5, 21, 6, 22, 7, 23>(c2, c3);
+            let rgba_low = i16x8_shuffle::<0, 8, 1, 9, 2, 10, 3, 11>(c01, c23);
+            let rgba_hi = i16x8_shuffle::<4, 12, 5, 13, 6, 14, 7, 15>(c01, c23);
+
+            // SAFETY: i is at most `output.len() / 32 - 1` so the highest possible write is to
+            // `output.len() - 1`.
+            unsafe {
+                v128_store(output.as_mut_ptr().wrapping_add(32 * i) as *mut _, rgba_low);
+                v128_store(output.as_mut_ptr().wrapping_add(32 * i + 16) as *mut _, rgba_hi);
+            }
+            continue;
+        }
+
         // Shuffle rrrrrrrrggggggggbbbbbbbb to rgbrgbrgb...
 
         let rg_lanes = i8x16_shuffle::<0, 16,
@@ -249,21 +302,21 @@ pub fn color_convert_line_ycbcr(y_slice: &[u8], cb_slice: &[u8], cr_slice: &[u8]
                                        4, 20,
                                        5, 21,
                                        6, 22,
-                                       7, 23>(r, g);
+                                       7, 23>(c0, c1);
 
-        let rgb_low = i8x16_shuffle::<0, 1, 16,         // r0, g0, b0
-                                      2, 3, 17,         // r1, g1, b1
-                                      4, 5, 18,         // r2, g2, b2
-                                      6, 7, 19,         // r3, g3, b3
-                                      8, 9, 20,         // r4, g4, b4
-                                      10>(rg_lanes, b); // r5
+        let rgb_low = i8x16_shuffle::<0, 1, 16,          // r0, g0, b0
+                                      2, 3, 17,          // r1, g1, b1
+                                      4, 5, 18,          // r2, g2, b2
+                                      6, 7, 19,          // r3, g3, b3
+                                      8, 9, 20,          // r4, g4, b4
+                                      10>(rg_lanes, c2); // r5
 
         let rgb_hi = i8x16_shuffle::<11, 21, 12,       // g5, b5, r6
                                      13, 22, 14,       // g6, b6, r7
                                      15, 23,  0,       // g7, b7, --
                                       0,  0,  0,       // --, --, --
                                       0,  0,  0,       // --, --, --
-                                      0>(rg_lanes, b); // --
+                                      0>(rg_lanes, c2); // --
 
         // SAFETY: i is at most `output.len() / 24 - 1` so the highest possible write is to
         // `output.len() - 1`.
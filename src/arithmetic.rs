@@ -0,0 +1,562 @@
+use crate::decoder::{MAX_SCAN_COMPONENTS, UNZIGZAG};
+use crate::error::{Error, Result};
+use crate::marker::Marker;
+use crate::read_u8;
+use alloc::borrow::ToOwned;
+use core::cmp;
+use core::ops::Range;
+use std::io::Read;
+
+const DC_STAT_BINS: usize = 64;
+const AC_STAT_BINS: usize = 256;
+
+// Table D.2
+// Each entry is (Qe_Value, Next_Index_LPS, Next_Index_MPS, Switch_MPS). The final entry is not
+// part of Table D.2, it is the fixed probability estimate of 0.5 recommended in Section 10.3 of
+// ITU-T Rec. T.851 and used by libjpeg to code sign bits and successive approximation bits.
+#[rustfmt::skip]
+static QE_TABLE: [(u16, u8, u8, u8); 114] = [
+    (0x5a1d,   1,   1, 1),
+    (0x2586,  14,   2, 0),
+    (0x1114,  16,   3, 0),
+    (0x080b,  18,   4, 0),
+    (0x03d8,  20,   5, 0),
+    (0x01da,  23,   6, 0),
+    (0x00e5,  25,   7, 0),
+    (0x006f,  28,   8, 0),
+    (0x0036,  30,   9, 0),
+    (0x001a,  33,  10, 0),
+    (0x000d,  35,  11, 0),
+    (0x0006,   9,  12, 0),
+    (0x0003,  10,  13, 0),
+    (0x0001,  12,  13, 0),
+    (0x5a7f,  15,  15, 1),
+    (0x3f25,  36,  16, 0),
+    (0x2cf2,  38,  17, 0),
+    (0x207c,  39,  18, 0),
+    (0x17b9,  40,  19, 0),
+    (0x1182,  42,  20, 0),
+    (0x0cef,  43,  21, 0),
+    (0x09a1,  45,  22, 0),
+    (0x072f,  46,  23, 0),
+    (0x055c,  48,  24, 0),
+    (0x0406,  49,  25, 0),
+    (0x0303,  51,  26, 0),
+    (0x0240,  52,  27, 0),
+    (0x01b1,  54,  28, 0),
+    (0x0144,  56,  29, 0),
+    (0x00f5,  57,  30, 0),
+    (0x00b7,  59,  31, 0),
+    (0x008a,  60,  32, 0),
+    (0x0068,  62,  33, 0),
+    (0x004e,  63,  34, 0),
+    (0x003b,  32,  35, 0),
+    (0x002c,  33,   9, 0),
+    (0x5ae1,  37,  37, 1),
+    (0x484c,  64,  38, 0),
+    (0x3a0d,  65,  39, 0),
+    (0x2ef1,  67,  40, 0),
+    (0x261f,  68,  41, 0),
+    (0x1f33,  69,  42, 0),
+    (0x19a8,  70,  43, 0),
+    (0x1518,  72,  44, 0),
+    (0x1177,  73,  45, 0),
+    (0x0e74,  74,  46, 0),
+    (0x0bfb,  75,  47, 0),
+    (0x09f8,  77,  48, 0),
+    (0x0861,  78,  49, 0),
+    (0x0706,  79,  50, 0),
+    (0x05cd,  48,  51, 0),
+    (0x04de,  50,  52, 0),
+    (0x040f,  50,  53, 0),
+    (0x0363,  51,  54, 0),
+    (0x02d4,  52,  55, 0),
+    (0x025c,  53,  56, 0),
+    (0x01f8,  54,  57, 0),
+    (0x01a4,  55,  58, 0),
+    (0x0160,  56,  59, 0),
+    (0x0125,  57,  60, 0),
+    (0x00f6,  58,  61, 0),
+    (0x00cb,  59,  62, 0),
+    (0x00ab,  61,  63, 0),
+    (0x008f,  61,  32, 0),
+    (0x5b12,  65,  65, 1),
+    (0x4d04,  80,  66, 0),
+    (0x412c,  81,  67, 0),
+    (0x37d8,  82,  68, 0),
+    (0x2fe8,  83,  69, 0),
+    (0x293c,  84,  70, 0),
+    (0x2379,  86,  71, 0),
+    (0x1edf,  87,  72, 0),
+    (0x1aa9,  87,  73, 0),
+    (0x174e,  72,  74, 0),
+    (0x1424,  72,  75, 0),
+    (0x119c,  74,  76, 0),
+    (0x0f6b,  74,  77, 0),
+    (0x0d51,  75,  78, 0),
+    (0x0bb6,  77,  79, 0),
+    (0x0a40,  77,  48, 0),
+    (0x5832,  80,  81, 1),
+    (0x4d1c,  88,  82, 0),
+    (0x438e,  89,  83, 0),
+    (0x3bdd,  90,  84, 0),
+    (0x34ee,  91,  85, 0),
+    (0x2eae,  92,  86, 0),
+    (0x299a,  93,  87, 0),
+    (0x2516,  86,  71, 0),
+    (0x5570,  88,  89, 1),
+    (0x4ca9,  95,  90, 0),
+    (0x44d9,  96,  91, 0),
+    (0x3e22,  97,  92, 0),
+    (0x3824,  99,  93, 0),
+    (0x32b4,  99,  94, 0),
+    (0x2e17,  93,  86, 0),
+    (0x56a8,  95,  96, 1),
+    (0x4f46, 101,  97, 0),
+    (0x47e5, 102,  98, 0),
+    (0x41cf, 103,  99, 0),
+    (0x3c3d, 104, 100, 0),
+    (0x375e,  99,  93, 0),
+    (0x5231, 105, 102, 0),
+    (0x4c0f, 106, 103, 0),
+    (0x4639, 107, 104, 0),
+    (0x415e, 103,  99, 0),
+    (0x5627, 105, 106, 1),
+    (0x50e7, 108, 107, 0),
+    (0x4b85, 109, 103, 0),
+    (0x5597, 110, 109, 0),
+    (0x504f, 111, 107, 0),
+    (0x5a10, 110, 111, 1),
+    (0x5522, 112, 109, 0),
+    (0x59eb, 112, 111, 1),
+    (0x5a1d, 113, 113, 0),
+];
+
+const FIXED_PROBABILITY_STATE: u8 = 113;
+
+/// Conditioning of the DC statistics, see Section F.1.4.4.1.2.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DcConditioning {
+    pub lower: u8,
+    pub upper: u8,
+}
+
+impl Default for DcConditioning {
+    // Section F.1.4.4.1.4
+    fn default() -> DcConditioning {
+        DcConditioning { lower: 0, upper: 1 }
+    }
+}
+
+/// Conditioning of the AC statistics, see Section F.1.4.4.2.1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AcConditioning {
+    pub kx: u8,
+}
+
+impl Default for AcConditioning {
+    // Section F.1.4.4.2.1
+    fn default() -> AcConditioning {
+        AcConditioning { kx: 5 }
+    }
+}
+
+// The QM-coder decoding registers, Section D.2.
+#[derive(Debug)]
+struct QmDecoder {
+    c: u32,
+    a: u32,
+    ct: i32,
+    marker: Option<Marker>,
+}
+
+impl QmDecoder {
+    fn new() -> QmDecoder {
+        QmDecoder {
+            c: 0,
+            a: 0,
+            // Forces two bytes to be read in before the first decision is decoded.
+            ct: -16,
+            marker: None,
+        }
+    }
+
+    // Section D.2.3
+    // Figure D.19 and D.20, in the form used by libjpeg.
+    fn decode<R: Read>(&mut self, reader: &mut R, state: &mut u8) -> Result<bool> {
+        // Section D.2.6
+        // Renormalization and data input.
+        while self.a < 0x8000 {
+            self.ct -= 1;
+
+            if self.ct < 0 {
+                let byte = self.read_byte(reader)?;
+                self.c = (self.c << 8) | u32::from(byte);
+                self.ct += 8;
+
+                if self.ct < 0 {
+                    self.ct += 1;
+
+                    if self.ct == 0 {
+                        // Both initial bytes have been read.
+                        self.a = 0x8000;
+                    }
+                }
+            }
+
+            self.a <<= 1;
+        }
+
+        let sv = *state;
+        let mps = sv >> 7 == 1;
+        let (qe, next_lps, next_mps, switch_mps) = QE_TABLE[usize::from(sv & 0x7f)];
+        let qe = u32::from(qe);
+        let after_mps = (sv & 0x80) | next_mps;
+        let after_lps = (sv & 0x80) ^ (next_lps | (switch_mps << 7));
+
+        self.a -= qe;
+        let temp = self.a << self.ct;
+
+        if self.c >= temp {
+            self.c -= temp;
+
+            // Conditional LPS exchange
+            if self.a < qe {
+                self.a = qe;
+                *state = after_mps;
+                Ok(mps)
+            } else {
+                self.a = qe;
+                *state = after_lps;
+                Ok(!mps)
+            }
+        } else if self.a < 0x8000 {
+            // Conditional MPS exchange
+            if self.a < qe {
+                *state = after_lps;
+                Ok(!mps)
+            } else {
+                *state = after_mps;
+                Ok(mps)
+            }
+        } else {
+            Ok(mps)
+        }
+    }
+
+    // Section D.2.6
+    // Unlike Huffman coded data it is legal to hit a marker while decoding arithmetic coded data,
+    // zero bytes are fed to the decoder from then on.
+    fn read_byte<R: Read>(&mut self, reader: &mut R) -> Result<u8> {
+        if self.marker.is_some() {
+            return Ok(0);
+        }
+
+        let byte = read_u8(reader)?;
+
+        if byte != 0xFF {
+            return Ok(byte);
+        }
+
+        let mut next_byte = read_u8(reader)?;
+
+        // Section B.1.1.2
+        // "Any marker may optionally be preceded by any number of fill bytes, which are bytes assigned code X’FF’."
+        while next_byte == 0xFF {
+            next_byte = read_u8(reader)?;
+        }
+
+        if next_byte == 0x00 {
+            // Byte stuffing
+            Ok(0xFF)
+        } else {
+            self.marker = Some(Marker::from_u8(next_byte).unwrap());
+            Ok(0)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ArithmeticDecoder {
+    coder: QmDecoder,
+
+    dc_stats: [[u8; DC_STAT_BINS]; 4],
+    ac_stats: [[u8; AC_STAT_BINS]; 4],
+    fixed_bin: u8,
+
+    // Conditioning category of the previous DC difference for each component in the scan.
+    dc_context: [usize; MAX_SCAN_COMPONENTS],
+}
+
+impl ArithmeticDecoder {
+    pub fn new() -> ArithmeticDecoder {
+        ArithmeticDecoder {
+            coder: QmDecoder::new(),
+            dc_stats: [[0; DC_STAT_BINS]; 4],
+            ac_stats: [[0; AC_STAT_BINS]; 4],
+            fixed_bin: FIXED_PROBABILITY_STATE,
+            dc_context: [0; MAX_SCAN_COMPONENTS],
+        }
+    }
+
+    /// Resets the decoder and all statistics areas, as done at the start of a scan and after each
+    /// restart marker (Section F.1.4.4.1.1 and F.1.4.4.2).
+    pub fn reset(&mut self) {
+        *self = ArithmeticDecoder::new();
+    }
+
+    /// Reads forward to the marker terminating the entropy-coded segment.
+    pub fn take_marker<R: Read>(&mut self, reader: &mut R) -> Result<Option<Marker>> {
+        while self.coder.marker.is_none() {
+            self.coder.read_byte(reader)?;
+        }
+
+        Ok(self.coder.marker.take())
+    }
+
+    // Section F.2.4
+    #[allow(clippy::too_many_arguments)]
+    pub fn decode_block<R: Read>(
+        &mut self,
+        reader: &mut R,
+        coefficients: &mut [i16; 64],
+        component: usize,
+        dc_table: usize,
+        ac_table: usize,
+        dc_conditioning: DcConditioning,
+        ac_conditioning: AcConditioning,
+        spectral_selection: Range<u8>,
+        successive_approximation_low: u8,
+        dc_predictor: &mut i16,
+    ) -> Result<()> {
+        if spectral_selection.start == 0 {
+            // Section F.2.4.1
+            let diff = self.decode_dc_diff(reader, component, dc_table, dc_conditioning)?;
+
+            *dc_predictor = dc_predictor.wrapping_add(diff as i16);
+            coefficients[0] = *dc_predictor << successive_approximation_low;
+        }
+
+        let stats = &mut self.ac_stats[ac_table];
+        let mut index = cmp::max(spectral_selection.start, 1);
+
+        // Section F.2.4.2
+        // Figure F.20
+        while index < spectral_selection.end {
+            let mut bin = 3 * (usize::from(index) - 1);
+
+            // End-of-block decision
+            if self.coder.decode(reader, &mut stats[bin])? {
+                break;
+            }
+
+            while !self.coder.decode(reader, &mut stats[bin + 1])? {
+                bin += 3;
+                index += 1;
+
+                if index >= spectral_selection.end {
+                    return Err(Error::Format(
+                        "spectral overflow in arithmetic coded data".to_owned(),
+                    ));
+                }
+            }
+
+            let sign = self.coder.decode(reader, &mut self.fixed_bin)?;
+
+            // Figure F.23
+            // Decoding the magnitude category, bins SN, X1 and X2..X15.
+            bin += 2;
+            let mut magnitude = u32::from(self.coder.decode(reader, &mut stats[bin])?);
+
+            if magnitude != 0 && self.coder.decode(reader, &mut stats[bin])? {
+                magnitude <<= 1;
+                bin = if index <= ac_conditioning.kx {
+                    189
+                } else {
+                    217
+                };
+
+                while self.coder.decode(reader, &mut stats[bin])? {
+                    magnitude <<= 1;
+
+                    if magnitude == 0x8000 {
+                        return Err(Error::Format(
+                            "invalid magnitude in arithmetic coded data".to_owned(),
+                        ));
+                    }
+
+                    bin += 1;
+                }
+            }
+
+            let value =
+                decode_magnitude_bits(&mut self.coder, reader, &mut stats[bin + 14..], magnitude)?;
+            let value = if sign { -value } else { value };
+
+            coefficients[UNZIGZAG[usize::from(index)] as usize] =
+                (value << successive_approximation_low) as i16;
+            index += 1;
+        }
+
+        Ok(())
+    }
+
+    // Section G.1.3.3
+    pub fn decode_block_successive_approximation<R: Read>(
+        &mut self,
+        reader: &mut R,
+        coefficients: &mut [i16; 64],
+        ac_table: usize,
+        spectral_selection: Range<u8>,
+        successive_approximation_low: u8,
+    ) -> Result<()> {
+        let positive = 1i16 << successive_approximation_low;
+        let negative = -1i16 << successive_approximation_low;
+
+        if spectral_selection.start == 0 {
+            // The next bit of the DC coefficient is coded with a fixed probability estimate.
+            if self.coder.decode(reader, &mut self.fixed_bin)? {
+                coefficients[0] |= positive;
+            }
+
+            return Ok(());
+        }
+
+        // Coefficients past the previous end-of-block position skip the end-of-block decision.
+        let mut end_of_block = spectral_selection.end - 1;
+
+        while end_of_block > 0 && coefficients[UNZIGZAG[usize::from(end_of_block)] as usize] == 0 {
+            end_of_block -= 1;
+        }
+
+        let stats = &mut self.ac_stats[ac_table];
+        let mut index = spectral_selection.start;
+
+        // Figure G.10
+        while index < spectral_selection.end {
+            let mut bin = 3 * (usize::from(index) - 1);
+
+            if index > end_of_block && self.coder.decode(reader, &mut stats[bin])? {
+                break;
+            }
+
+            loop {
+                let coefficient = &mut coefficients[UNZIGZAG[usize::from(index)] as usize];
+
+                if *coefficient != 0 {
+                    // Correction bit of a previously nonzero coefficient
+                    if self.coder.decode(reader, &mut stats[bin + 2])? {
+                        if *coefficient < 0 {
+                            *coefficient = coefficient.wrapping_add(negative);
+                        } else {
+                            *coefficient = coefficient.wrapping_add(positive);
+                        }
+                    }
+
+                    break;
+                }
+
+                if self.coder.decode(reader, &mut stats[bin + 1])? {
+                    // Newly nonzero coefficient
+                    if self.coder.decode(reader, &mut self.fixed_bin)? {
+                        *coefficient = negative;
+                    } else {
+                        *coefficient = positive;
+                    }
+
+                    break;
+                }
+
+                bin += 3;
+                index += 1;
+
+                if index >= spectral_selection.end {
+                    return Err(Error::Format(
+                        "spectral overflow in arithmetic coded data".to_owned(),
+                    ));
+                }
+            }
+
+            index += 1;
+        }
+
+        Ok(())
+    }
+
+    // Section F.1.4.4.1
+    // Figure F.19
+    fn decode_dc_diff<R: Read>(
+        &mut self,
+        reader: &mut R,
+        component: usize,
+        table: usize,
+        conditioning: DcConditioning,
+    ) -> Result<i32> {
+        let stats = &mut self.dc_stats[table];
+        let context = self.dc_context[component];
+
+        if !self.coder.decode(reader, &mut stats[context])? {
+            self.dc_context[component] = 0;
+            return Ok(0);
+        }
+
+        let sign = self.coder.decode(reader, &mut stats[context + 1])?;
+
+        // Figure F.23
+        // Decoding the magnitude category, bins SP/SN, X1 and X2..X15.
+        let mut bin = context + 2 + usize::from(sign);
+        let mut magnitude = u32::from(self.coder.decode(reader, &mut stats[bin])?);
+
+        if magnitude != 0 {
+            bin = 20;
+
+            while self.coder.decode(reader, &mut stats[bin])? {
+                magnitude <<= 1;
+
+                if magnitude == 0x8000 {
+                    return Err(Error::Format(
+                        "invalid magnitude in arithmetic coded data".to_owned(),
+                    ));
+                }
+
+                bin += 1;
+            }
+        }
+
+        // Section F.1.4.4.1.2
+        // Table F.4
+        self.dc_context[component] = if magnitude < (1 << conditioning.lower) >> 1 {
+            0
+        } else if magnitude > (1 << conditioning.upper) >> 1 {
+            12 + 4 * usize::from(sign)
+        } else {
+            4 + 4 * usize::from(sign)
+        };
+
+        let value =
+            decode_magnitude_bits(&mut self.coder, reader, &mut stats[bin + 14..], magnitude)?;
+
+        Ok(if sign { -value } else { value })
+    }
+}
+
+// Figure F.24
+fn decode_magnitude_bits<R: Read>(
+    coder: &mut QmDecoder,
+    reader: &mut R,
+    stats: &mut [u8],
+    magnitude: u32,
+) -> Result<i32> {
+    let mut value = magnitude;
+    let mut bit = magnitude >> 1;
+
+    while bit != 0 {
+        if coder.decode(reader, &mut stats[0])? {
+            value |= bit;
+        }
+
+        bit >>= 1;
+    }
+
+    Ok(value as i32 + 1)
+}
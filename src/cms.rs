@@ -0,0 +1,840 @@
+use crate::error::{Error, Result};
+use crate::icc::{IccColorSpace, IccError, IccProfile, IccRenderingIntent};
+use alloc::vec;
+use alloc::vec::Vec;
+
+// Colour transforms between ICC profiles. The source profile maps the decoded samples to CIE XYZ
+// relative to the D50 white point, which the destination profile maps to its device values. Both
+// are combined into a single lookup table interpolated for every pixel.
+
+// Most channels of the colour spaces handled, CMYK
+const MAX_CHANNELS: usize = 4;
+
+// The number of samples of curves evaluated by table
+const CURVE_SIZE: usize = 4096;
+
+// ICC.1:2022 Annex D.1: D50, the white point of the profile connection space
+const D50: [f32; 3] = [0.9642, 1.0, 0.8249];
+
+// ICC.1:2022 Section 10.5 and 10.18: A tone reproduction curve, taking and returning values from
+// 0 to 1.
+#[derive(Clone, Debug)]
+enum Curve {
+    Gamma(f32),
+    // The function type and the parameters g, a, b, c, d, e and f
+    Parametric(u16, [f32; 7]),
+    Table(Vec<f32>),
+}
+
+impl Curve {
+    fn eval(&self, x: f32) -> f32 {
+        let x = x.clamp(0.0, 1.0);
+
+        match *self {
+            Curve::Gamma(gamma) => x.powf(gamma),
+            Curve::Parametric(function, [g, a, b, c, d, e, f]) => {
+                let power = |x: f32| (a * x + b).max(0.0).powf(g);
+                match function {
+                    0 => x.powf(g),
+                    1 if a * x + b >= 0.0 => power(x),
+                    1 => 0.0,
+                    2 if a * x + b >= 0.0 => power(x) + c,
+                    2 => c,
+                    3 if x >= d => power(x),
+                    3 => c * x,
+                    _ if x >= d => power(x) + e,
+                    _ => c * x + f,
+                }
+            }
+            Curve::Table(ref table) => interpolate(table, x),
+        }
+    }
+
+    fn sampled(&self) -> Curve {
+        let scale = (CURVE_SIZE - 1) as f32;
+        Curve::Table(
+            (0..CURVE_SIZE)
+                .map(|i| self.eval(i as f32 / scale))
+                .collect(),
+        )
+    }
+
+    // Inverts a monotonic curve by sampling it.
+    fn inverse(&self) -> Curve {
+        if let Curve::Gamma(gamma) = *self {
+            if gamma > 0.0 {
+                return Curve::Gamma(1.0 / gamma);
+            }
+        }
+
+        let scale = (CURVE_SIZE - 1) as f32;
+        let forward: Vec<f32> = (0..CURVE_SIZE)
+            .map(|i| self.eval(i as f32 / scale))
+            .collect();
+        let decreasing = forward[0] > forward[CURVE_SIZE - 1];
+
+        Curve::Table(
+            (0..CURVE_SIZE)
+                .map(|i| {
+                    let y = i as f32 / scale;
+                    // The first sample reaching the value
+                    let j = if decreasing {
+                        forward.partition_point(|&value| value > y)
+                    } else {
+                        forward.partition_point(|&value| value < y)
+                    };
+
+                    match j {
+                        0 => 0.0,
+                        CURVE_SIZE => 1.0,
+                        _ => {
+                            let (low, high) = (forward[j - 1], forward[j]);
+                            let t = if high != low {
+                                (y - low) / (high - low)
+                            } else {
+                                0.0
+                            };
+                            ((j - 1) as f32 + t) / scale
+                        }
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+// The sRGB tone reproduction curve of IEC 61966-2-1, as a parametric curve
+fn srgb_curve() -> Curve {
+    Curve::Parametric(
+        3,
+        [
+            2.4,
+            1.0 / 1.055,
+            0.055 / 1.055,
+            1.0 / 12.92,
+            0.04045,
+            0.0,
+            0.0,
+        ],
+    )
+}
+
+// Converts an sRGB value from 0 to 1 to linear light.
+pub(crate) fn srgb_to_linear(value: f32) -> f32 {
+    srgb_curve().eval(value)
+}
+
+// The inverse of the sRGB tone reproduction curve of IEC 61966-2-1, as a parametric curve
+fn srgb_inverse_curve() -> Curve {
+    Curve::Parametric(
+        4,
+        [
+            1.0 / 2.4,
+            1.055f32.powf(2.4),
+            0.0,
+            12.92,
+            0.0031308,
+            -0.055,
+            0.0,
+        ],
+    )
+}
+
+// The sRGB primaries adapted to D50, as in the sRGB profile of the ICC.
+const SRGB_MATRIX: [f32; 9] = [
+    0.4360747, 0.3850649, 0.1430804, //
+    0.2225045, 0.7168786, 0.0606169, //
+    0.0139322, 0.0971045, 0.7141733,
+];
+
+fn interpolate(table: &[f32], x: f32) -> f32 {
+    if table.len() < 2 {
+        return table.first().copied().unwrap_or(x);
+    }
+
+    let position = x * (table.len() - 1) as f32;
+    let index = (position as usize).min(table.len() - 2);
+    let t = position - index as f32;
+    table[index] * (1.0 - t) + table[index + 1] * t
+}
+
+// A colour lookup table interpolated multilinearly. The first input varies slowest.
+#[derive(Clone, Debug)]
+struct Clut {
+    grid: Vec<usize>,
+    outputs: usize,
+    table: Vec<f32>,
+}
+
+impl Clut {
+    fn eval(&self, input: &[f32], output: &mut [f32]) {
+        let mut base = 0;
+        let mut strides = [0; MAX_CHANNELS];
+        let mut fractions = [0.0; MAX_CHANNELS];
+        let mut stride = self.outputs;
+
+        for (i, &size) in self.grid.iter().enumerate().rev() {
+            let position = input[i].clamp(0.0, 1.0) * (size - 1) as f32;
+            let index = (position as usize).min(size - 2);
+            fractions[i] = position - index as f32;
+            strides[i] = stride;
+            base += index * stride;
+            stride *= size;
+        }
+
+        output[..self.outputs].fill(0.0);
+
+        for corner in 0..1usize << self.grid.len() {
+            let mut weight = 1.0;
+            let mut index = base;
+
+            for i in 0..self.grid.len() {
+                if corner >> i & 1 == 1 {
+                    weight *= fractions[i];
+                    index += strides[i];
+                } else {
+                    weight *= 1.0 - fractions[i];
+                }
+            }
+
+            if weight != 0.0 {
+                for (output, &value) in output.iter_mut().zip(&self.table[index..][..self.outputs])
+                {
+                    *output += weight * value;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Stage {
+    Curves(Vec<Curve>),
+    Matrix([f32; 9], [f32; 3]),
+    Clut(Clut),
+    LabToXyz,
+    XyzToLab,
+    GrayToXyz,
+    XyzToGray,
+}
+
+impl Stage {
+    // Returns the number of output channels for the number of input channels, or `None` if the
+    // stage does not take that many.
+    fn channels(&self, inputs: usize) -> Option<usize> {
+        let (expected, outputs) = match *self {
+            Stage::Curves(ref curves) => (curves.len(), curves.len()),
+            Stage::Clut(ref clut) => (clut.grid.len(), clut.outputs),
+            Stage::GrayToXyz => (1, 3),
+            Stage::XyzToGray => (3, 1),
+            _ => (3, 3),
+        };
+
+        if expected == inputs && (1..=MAX_CHANNELS).contains(&outputs) {
+            Some(outputs)
+        } else {
+            None
+        }
+    }
+
+    fn eval(&self, values: &mut [f32; MAX_CHANNELS]) {
+        match *self {
+            Stage::Curves(ref curves) => {
+                for (value, curve) in values.iter_mut().zip(curves) {
+                    *value = curve.eval(*value);
+                }
+            }
+            Stage::Matrix(ref matrix, ref offset) => {
+                let [x, y, z, _] = *values;
+                for i in 0..3 {
+                    values[i] = matrix[i * 3] * x
+                        + matrix[i * 3 + 1] * y
+                        + matrix[i * 3 + 2] * z
+                        + offset[i];
+                }
+            }
+            Stage::Clut(ref clut) => {
+                let input = *values;
+                clut.eval(&input, values);
+            }
+            // CIE 15:2004 Section 8.2.1
+            Stage::LabToXyz => {
+                let f = |t: f32| {
+                    if t > 6.0 / 29.0 {
+                        t * t * t
+                    } else {
+                        3.0 * (6.0f32 / 29.0).powi(2) * (t - 4.0 / 29.0)
+                    }
+                };
+                let fy = (values[0] + 16.0) / 116.0;
+                let (fx, fz) = (fy + values[1] / 500.0, fy - values[2] / 200.0);
+                values[0] = D50[0] * f(fx);
+                values[1] = D50[1] * f(fy);
+                values[2] = D50[2] * f(fz);
+            }
+            Stage::XyzToLab => {
+                let f = |t: f32| {
+                    if t > (6.0f32 / 29.0).powi(3) {
+                        t.cbrt()
+                    } else {
+                        t / (3.0 * (6.0f32 / 29.0).powi(2)) + 4.0 / 29.0
+                    }
+                };
+                let fx = f(values[0] / D50[0]);
+                let fy = f(values[1] / D50[1]);
+                let fz = f(values[2] / D50[2]);
+                values[0] = 116.0 * fy - 16.0;
+                values[1] = 500.0 * (fx - fy);
+                values[2] = 200.0 * (fy - fz);
+            }
+            Stage::GrayToXyz => {
+                let y = values[0];
+                values[..3].copy_from_slice(&[D50[0] * y, D50[1] * y, D50[2] * y]);
+            }
+            Stage::XyzToGray => values[0] = values[1],
+        }
+    }
+}
+
+// The encoding of the profile connection space by a LUT-based tag, as a linear mapping of the
+// values from 0 to 1.
+fn pcs_encoding(profile: &IccProfile, tag_type: &[u8]) -> Option<([f32; 3], [f32; 3])> {
+    match (profile.pcs, tag_type) {
+        // ICC.1:2022 Section 6.3.4.2: 1 + 32767 / 32768 is encoded as 0xFFFF.
+        (IccColorSpace::Xyz, _) => Some(([65535.0 / 32768.0; 3], [0.0; 3])),
+        // ICC.1:2001-04 Section 6.5.8: The legacy 16-bit encoding of lut16Type
+        (IccColorSpace::Lab, b"mft2") => Some((
+            [65535.0 / 652.8, 65535.0 / 256.0, 65535.0 / 256.0],
+            [0.0, -128.0, -128.0],
+        )),
+        (IccColorSpace::Lab, _) => Some(([100.0, 255.0, 255.0], [0.0, -128.0, -128.0])),
+        _ => None,
+    }
+}
+
+fn unsupported() -> Error {
+    Error::Icc(IccError::UnsupportedProfile)
+}
+
+// Returns the number of channels of a colour space decoded images can have.
+pub(crate) fn device_channels(color_space: IccColorSpace) -> Option<usize> {
+    match color_space {
+        IccColorSpace::Gray => Some(1),
+        IccColorSpace::Rgb => Some(3),
+        IccColorSpace::Cmyk => Some(4),
+        _ => None,
+    }
+}
+
+// Returns the number of channels of the samples converted from the source profile to the
+// destination profile, or to sRGB if there is none.
+pub(crate) fn output_channels(
+    source: &IccProfile,
+    destination: Option<&IccProfile>,
+) -> Option<usize> {
+    match destination {
+        Some(destination) => device_channels(destination.color_space),
+        None if source.color_space == IccColorSpace::Gray => Some(1),
+        None => Some(3),
+    }
+}
+
+// Returns the LUT-based tag of the rendering intent of the profile, falling back to the
+// perceptual one.
+fn lut_tag<'a>(profile: &'a IccProfile, prefix: &[u8; 3]) -> Option<&'a [u8]> {
+    let intent = match profile.rendering_intent {
+        IccRenderingIntent::RelativeColorimetric | IccRenderingIntent::AbsoluteColorimetric => b'1',
+        IccRenderingIntent::Saturation => b'2',
+        _ => b'0',
+    };
+
+    let tag = |intent| profile.tag([prefix[0], prefix[1], prefix[2], intent]);
+    tag(intent).or_else(|| tag(b'0'))
+}
+
+// Returns the stages mapping the device values of the profile to XYZ.
+fn source_stages(profile: &IccProfile) -> Option<Vec<Stage>> {
+    if let Some(tag) = lut_tag(profile, b"A2B") {
+        let (scale, offset) = pcs_encoding(profile, tag.get(0..4)?)?;
+        let mut stages = parse_lut(tag, true, false)?;
+        stages.push(Stage::Matrix(diagonal(scale), offset));
+        if profile.pcs == IccColorSpace::Lab {
+            stages.push(Stage::LabToXyz);
+        }
+        return Some(stages);
+    }
+
+    let curve = |signature: &[u8; 4]| Some(parse_curve(profile.tag(*signature)?)?.0);
+
+    match profile.color_space {
+        IccColorSpace::Gray => Some(vec![Stage::Curves(vec![curve(b"kTRC")?]), Stage::GrayToXyz]),
+        IccColorSpace::Rgb => {
+            let curves = vec![curve(b"rTRC")?, curve(b"gTRC")?, curve(b"bTRC")?];
+            Some(vec![
+                Stage::Curves(curves),
+                Stage::Matrix(colorants(profile)?, [0.0; 3]),
+            ])
+        }
+        _ => None,
+    }
+}
+
+// Returns the stages mapping XYZ to the device values of the profile.
+fn destination_stages(profile: &IccProfile) -> Option<Vec<Stage>> {
+    if let Some(tag) = lut_tag(profile, b"B2A") {
+        let (scale, offset) = pcs_encoding(profile, tag.get(0..4)?)?;
+        let mut stages = Vec::new();
+        if profile.pcs == IccColorSpace::Lab {
+            stages.push(Stage::XyzToLab);
+        }
+        stages.push(Stage::Matrix(
+            diagonal(scale.map(|scale| 1.0 / scale)),
+            [0, 1, 2].map(|i| -offset[i] / scale[i]),
+        ));
+        stages.extend(parse_lut(tag, false, profile.pcs == IccColorSpace::Xyz)?);
+        return Some(stages);
+    }
+
+    let curve = |signature: &[u8; 4]| Some(parse_curve(profile.tag(*signature)?)?.0.inverse());
+
+    match profile.color_space {
+        IccColorSpace::Gray => Some(vec![Stage::XyzToGray, Stage::Curves(vec![curve(b"kTRC")?])]),
+        IccColorSpace::Rgb => {
+            let curves = vec![curve(b"rTRC")?, curve(b"gTRC")?, curve(b"bTRC")?];
+            Some(vec![
+                Stage::Matrix(invert(&colorants(profile)?)?, [0.0; 3]),
+                Stage::Curves(curves),
+            ])
+        }
+        _ => None,
+    }
+}
+
+// The matrix whose columns are the XYZ values of the red, green and blue colorants
+fn colorants(profile: &IccProfile) -> Option<[f32; 9]> {
+    let mut matrix = [0.0; 9];
+
+    for (column, signature) in [b"rXYZ", b"gXYZ", b"bXYZ"].iter().enumerate() {
+        // ICC.1:2022 Section 10.31: XYZType
+        let tag = profile.tag(**signature)?;
+        if tag.get(0..4)? != b"XYZ " {
+            return None;
+        }
+
+        for row in 0..3 {
+            matrix[row * 3 + column] = read_s15_fixed16(tag, 8 + row * 4)?;
+        }
+    }
+
+    Some(matrix)
+}
+
+fn diagonal(values: [f32; 3]) -> [f32; 9] {
+    let [x, y, z] = values;
+    [x, 0.0, 0.0, 0.0, y, 0.0, 0.0, 0.0, z]
+}
+
+fn invert(m: &[f32; 9]) -> Option<[f32; 9]> {
+    let cofactor = |a: usize, b: usize, c: usize, d: usize| m[a] * m[d] - m[b] * m[c];
+    let inverse = [
+        cofactor(4, 5, 7, 8),
+        -cofactor(1, 2, 7, 8),
+        cofactor(1, 2, 4, 5),
+        -cofactor(3, 5, 6, 8),
+        cofactor(0, 2, 6, 8),
+        -cofactor(0, 2, 3, 5),
+        cofactor(3, 4, 6, 7),
+        -cofactor(0, 1, 6, 7),
+        cofactor(0, 1, 3, 4),
+    ];
+    let determinant = m[0] * inverse[0] + m[1] * inverse[3] + m[2] * inverse[6];
+
+    if determinant.abs() < 1e-9 {
+        return None;
+    }
+
+    Some(inverse.map(|value| value / determinant))
+}
+
+// Parses a lut8Type, lut16Type, lutAtoBType or lutBtoAType tag. The matrix of lut8Type and
+// lut16Type is only used if the input is XYZ.
+fn parse_lut(tag: &[u8], a_to_b: bool, xyz_input: bool) -> Option<Vec<Stage>> {
+    let inputs = usize::from(*tag.get(8)?);
+    let outputs = usize::from(*tag.get(9)?);
+
+    if !(1..=MAX_CHANNELS).contains(&inputs) || !(1..=MAX_CHANNELS).contains(&outputs) {
+        return None;
+    }
+
+    match tag.get(0..4)? {
+        // ICC.1:2001-04 Section 6.5.7 and 6.5.8
+        lut_type @ (b"mft1" | b"mft2") => {
+            let grid = usize::from(tag[10]);
+            let (input_entries, output_entries, sample_size, start) = if lut_type == b"mft1" {
+                (256, 256, 1, 48)
+            } else {
+                let input_entries = usize::from(read_u16(tag, 48)?);
+                (input_entries, usize::from(read_u16(tag, 50)?), 2, 52)
+            };
+
+            if input_entries < 2 || output_entries < 2 {
+                return None;
+            }
+
+            let input_curves = read_tables(tag, start, inputs, input_entries, sample_size)?;
+            let clut_offset = start + inputs * input_entries * sample_size;
+            let clut = parse_clut(tag, clut_offset, vec![grid; inputs], outputs, sample_size)?;
+            let output_offset = clut_offset + clut.table.len() * sample_size;
+            let output_curves =
+                read_tables(tag, output_offset, outputs, output_entries, sample_size)?;
+
+            let mut stages = Vec::new();
+            if xyz_input {
+                let mut matrix = [0.0; 9];
+                for (i, value) in matrix.iter_mut().enumerate() {
+                    *value = read_s15_fixed16(tag, 12 + i * 4)?;
+                }
+                stages.push(Stage::Matrix(matrix, [0.0; 3]));
+            }
+            stages.push(Stage::Curves(input_curves));
+            stages.push(Stage::Clut(clut));
+            stages.push(Stage::Curves(output_curves));
+            Some(stages)
+        }
+        // ICC.1:2022 Section 10.12 and 10.13
+        b"mAB " | b"mBA " => {
+            let offset = |position| read_u32(tag, position).map(|offset| offset as usize);
+            let (b, matrix, m, clut, a) = (
+                offset(12)?,
+                offset(16)?,
+                offset(20)?,
+                offset(24)?,
+                offset(28)?,
+            );
+            let curves = |position: usize, count| {
+                Some(Stage::Curves(parse_curves(tag.get(position..)?, count)?))
+            };
+
+            let matrix = if matrix != 0 {
+                let mut values = [0.0; 12];
+                for (i, value) in values.iter_mut().enumerate() {
+                    *value = read_s15_fixed16(tag, matrix + i * 4)?;
+                }
+                let [m0, m1, m2, m3, m4, m5, m6, m7, m8, o0, o1, o2] = values;
+                Some(Stage::Matrix(
+                    [m0, m1, m2, m3, m4, m5, m6, m7, m8],
+                    [o0, o1, o2],
+                ))
+            } else {
+                None
+            };
+
+            let clut = if clut != 0 {
+                let grid = tag
+                    .get(clut..clut + inputs)?
+                    .iter()
+                    .map(|&size| usize::from(size))
+                    .collect();
+                let sample_size = usize::from(*tag.get(clut + 16)?);
+                Some(Stage::Clut(parse_clut(
+                    tag,
+                    clut + 20,
+                    grid,
+                    outputs,
+                    sample_size,
+                )?))
+            } else {
+                None
+            };
+
+            // The A curves are on the device side, the B curves on the PCS side.
+            let (device, pcs) = if a_to_b {
+                (inputs, outputs)
+            } else {
+                (outputs, inputs)
+            };
+            let a = if a != 0 {
+                Some(curves(a, device)?)
+            } else {
+                None
+            };
+            let m = if m != 0 { Some(curves(m, pcs)?) } else { None };
+            let b = curves(b, pcs)?;
+
+            let stages = if a_to_b {
+                [a, clut, m, matrix, Some(b)]
+            } else {
+                [Some(b), matrix, m, clut, a]
+            };
+            Some(stages.into_iter().flatten().collect())
+        }
+        _ => None,
+    }
+}
+
+fn parse_clut(
+    tag: &[u8],
+    offset: usize,
+    grid: Vec<usize>,
+    outputs: usize,
+    sample_size: usize,
+) -> Option<Clut> {
+    if grid.iter().any(|&size| size < 2) || !(1..=2).contains(&sample_size) {
+        return None;
+    }
+
+    let count = grid
+        .iter()
+        .try_fold(outputs, |count, &size| count.checked_mul(size))?;
+    let table = read_samples(tag, offset, count, sample_size)?;
+
+    Some(Clut {
+        grid,
+        outputs,
+        table,
+    })
+}
+
+// Reads the consecutive input or output tables of lut8Type and lut16Type.
+fn read_tables(
+    tag: &[u8],
+    offset: usize,
+    count: usize,
+    entries: usize,
+    sample_size: usize,
+) -> Option<Vec<Curve>> {
+    (0..count)
+        .map(|i| {
+            let offset = offset + i * entries * sample_size;
+            Some(Curve::Table(read_samples(
+                tag,
+                offset,
+                entries,
+                sample_size,
+            )?))
+        })
+        .collect()
+}
+
+// Reads 8 or 16-bit samples, scaled to 0 to 1.
+fn read_samples(data: &[u8], offset: usize, count: usize, sample_size: usize) -> Option<Vec<f32>> {
+    let data = data.get(offset..offset.checked_add(count.checked_mul(sample_size)?)?)?;
+
+    Some(if sample_size == 1 {
+        data.iter()
+            .map(|&sample| f32::from(sample) / 255.0)
+            .collect()
+    } else {
+        data.chunks_exact(2)
+            .map(|sample| f32::from(u16::from_be_bytes([sample[0], sample[1]])) / 65535.0)
+            .collect()
+    })
+}
+
+// Parses a curveType or parametricCurveType, returns the curve and its size in bytes.
+fn parse_curve(data: &[u8]) -> Option<(Curve, usize)> {
+    match data.get(0..4)? {
+        b"curv" => {
+            let count = read_u32(data, 8)? as usize;
+            let curve = match count {
+                0 => Curve::Gamma(1.0),
+                // A u8Fixed8Number
+                1 => Curve::Gamma(f32::from(read_u16(data, 12)?) / 256.0),
+                _ => Curve::Table(read_samples(data, 12, count, 2)?),
+            };
+            Some((curve, 12 + 2 * count))
+        }
+        b"para" => {
+            let function = read_u16(data, 8)?;
+            let count = match function {
+                0 => 1,
+                1 => 3,
+                2 => 4,
+                3 => 5,
+                4 => 7,
+                _ => return None,
+            };
+
+            let mut parameters = [0.0; 7];
+            for (i, parameter) in parameters.iter_mut().take(count).enumerate() {
+                *parameter = read_s15_fixed16(data, 12 + i * 4)?;
+            }
+            Some((Curve::Parametric(function, parameters), 12 + 4 * count))
+        }
+        _ => None,
+    }
+}
+
+// Parses consecutive curves, each aligned to four bytes.
+fn parse_curves(data: &[u8], count: usize) -> Option<Vec<Curve>> {
+    let mut offset = 0;
+    let mut curves = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        let (curve, size) = parse_curve(data.get(offset..)?)?;
+        curves.push(curve);
+        offset += (size + 3) & !3;
+    }
+
+    Some(curves)
+}
+
+// A transform of decoded samples from the colour space of a profile to another.
+pub(crate) struct Transform {
+    inputs: usize,
+    input_curves: Option<Vec<Curve>>,
+    lut: Clut,
+    output_curves: Option<Vec<Curve>>,
+}
+
+impl Transform {
+    // Creates the transform from the source profile to the destination profile, or to sRGB if
+    // there is none. Grayscale images are converted to the sRGB tone curve then.
+    pub(crate) fn new(source: &IccProfile, destination: Option<&IccProfile>) -> Result<Transform> {
+        let inputs = device_channels(source.color_space).ok_or_else(unsupported)?;
+        let mut stages = source_stages(source).ok_or_else(unsupported)?;
+
+        match destination {
+            Some(destination) => {
+                stages.extend(destination_stages(destination).ok_or_else(unsupported)?)
+            }
+            None if inputs == 1 => {
+                stages.push(Stage::XyzToGray);
+                stages.push(Stage::Curves(vec![srgb_inverse_curve()]));
+            }
+            None => {
+                stages.push(Stage::Matrix(invert(&SRGB_MATRIX).unwrap(), [0.0; 3]));
+                stages.push(Stage::Curves(vec![srgb_inverse_curve(); 3]));
+            }
+        }
+
+        let outputs = stages
+            .iter()
+            .try_fold(inputs, |channels, stage| stage.channels(channels))
+            .ok_or_else(unsupported)?;
+
+        if Some(outputs) != output_channels(source, destination) {
+            return Err(unsupported());
+        }
+
+        // The tone curves at either end are the steepest stages, they are evaluated by their own
+        // tables instead of being interpolated on the grid.
+        let input_curves = take_curves(&mut stages, 0);
+        let output_curves = match stages.len() {
+            0 => None,
+            len => take_curves(&mut stages, len - 1),
+        };
+
+        // Samples the other stages on a grid fine enough for the interpolation to stay within a
+        // level of 8-bit samples.
+        let size = match inputs {
+            1 => 1024,
+            2 | 3 => 33,
+            _ => 17,
+        };
+        let nodes = (0..inputs).fold(1, |nodes, _| nodes * size);
+        let mut table = Vec::with_capacity(nodes * outputs);
+
+        for node in 0..nodes {
+            let mut values = [0.0; MAX_CHANNELS];
+            let mut rest = node;
+            for value in values[..inputs].iter_mut().rev() {
+                *value = (rest % size) as f32 / (size - 1) as f32;
+                rest /= size;
+            }
+
+            for stage in &stages {
+                stage.eval(&mut values);
+            }
+            table.extend(values[..outputs].iter().map(|value| value.clamp(0.0, 1.0)));
+        }
+
+        Ok(Transform {
+            inputs,
+            input_curves,
+            lut: Clut {
+                grid: vec![size; inputs],
+                outputs,
+                table,
+            },
+            output_curves,
+        })
+    }
+
+    fn eval(&self, values: &mut [f32; MAX_CHANNELS], result: &mut [f32; MAX_CHANNELS]) {
+        if let Some(ref curves) = self.input_curves {
+            for (value, curve) in values.iter_mut().zip(curves) {
+                *value = curve.eval(*value);
+            }
+        }
+
+        self.lut.eval(values, result);
+
+        if let Some(ref curves) = self.output_curves {
+            for (value, curve) in result.iter_mut().zip(curves) {
+                *value = curve.eval(*value);
+            }
+        }
+    }
+
+    // Converts 8-bit samples, or native endian 16-bit samples if the largest sample value does
+    // not fit 8 bits.
+    pub(crate) fn apply(&self, data: &[u8], sample_max: u16) -> Vec<u8> {
+        let outputs = self.lut.outputs;
+        let sample_size = if sample_max <= 255 { 1 } else { 2 };
+        let scale = f32::from(sample_max);
+        let pixel_size = self.inputs * sample_size;
+        let mut output = Vec::with_capacity(data.len() / pixel_size * outputs * sample_size);
+        let mut values = [0.0; MAX_CHANNELS];
+        let mut result = [0.0; MAX_CHANNELS];
+
+        for pixel in data.chunks_exact(pixel_size) {
+            if sample_size == 1 {
+                for (value, &sample) in values.iter_mut().zip(pixel) {
+                    *value = f32::from(sample) / scale;
+                }
+                self.eval(&mut values, &mut result);
+                output.extend(
+                    result[..outputs]
+                        .iter()
+                        .map(|value| (value * scale).round() as u8),
+                );
+            } else {
+                for (value, sample) in values.iter_mut().zip(pixel.chunks_exact(2)) {
+                    *value = f32::from(u16::from_ne_bytes([sample[0], sample[1]])) / scale;
+                }
+                self.eval(&mut values, &mut result);
+                for value in &result[..outputs] {
+                    output.extend_from_slice(&((value * scale).round() as u16).to_ne_bytes());
+                }
+            }
+        }
+
+        output
+    }
+}
+
+// Removes the curves stage at the index, returning the curves sampled into tables.
+fn take_curves(stages: &mut Vec<Stage>, index: usize) -> Option<Vec<Curve>> {
+    let curves = match stages.get(index) {
+        Some(Stage::Curves(curves)) => curves.iter().map(Curve::sampled).collect(),
+        _ => return None,
+    };
+
+    stages.remove(index);
+    Some(curves)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_s15_fixed16(data: &[u8], offset: usize) -> Option<f32> {
+    read_u32(data, offset).map(|value| value as i32 as f32 / 65536.0)
+}
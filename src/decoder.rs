@@ -1,30 +1,55 @@
+use crate::arithmetic::{AcConditioning, ArithmeticDecoder, DcConditioning};
+use crate::cms::{self, Transform};
 use crate::error::{Error, Result, UnsupportedFeature};
+use crate::exif::Exif;
 use crate::huffman::{fill_default_mjpeg_tables, HuffmanDecoder, HuffmanTable};
+use crate::icc::{self, IccError, IccProfile};
+use crate::iptc::Iptc;
+use crate::jumbf;
 use crate::marker::Marker;
 use crate::parser::{
-    parse_app, parse_com, parse_dht, parse_dqt, parse_dri, parse_sof, parse_sos,
-    AdobeColorTransform, AppData, CodingProcess, Component, Dimensions, EntropyCoding, FrameInfo,
-    IccChunk, ScanInfo,
+    parse_app, parse_com, parse_dac, parse_dhp, parse_dht, parse_dnl, parse_dqt, parse_dri,
+    parse_exif_density, parse_exif_thumbnail, parse_exp, parse_lse, parse_sof, parse_sos,
+    AdobeColorTransform, AppData, CodingProcess, Component, DensityUnit, Dimensions, EntropyCoding,
+    ExtendedXmpChunk, FrameInfo, IccChunk, JfifHeader, JfifThumbnail, JumbfSegment, LseData,
+    MpImage, PixelDensity, ScanInfo,
 };
+use crate::psir::ImageResource;
 use crate::read_u8;
 use crate::upsampler::Upsampler;
 use crate::worker::{compute_image_parallel, PreferWorkerKind, RowData, Worker, WorkerScope};
-use alloc::borrow::ToOwned;
+use crate::xmp;
+use alloc::borrow::{Cow, ToOwned};
+use alloc::boxed::Box;
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use alloc::{format, vec};
 use core::cmp;
 use core::mem;
 use core::ops::Range;
-use std::io::Read;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
 
-pub const MAX_COMPONENTS: usize = 4;
+// Section B.2.3
+// A scan contains at most four components, while a frame can have up to 255.
+pub const MAX_SCAN_COMPONENTS: usize = 4;
 
+mod gainmap;
+mod hierarchical;
+mod jpegls;
+mod layout;
 mod lossless;
-use self::lossless::compute_image_lossless;
+mod orientation;
+pub use self::gainmap::{GainMap, GainMapMetadata, HdrFormat};
+use self::hierarchical::Hierarchical;
+use self::jpegls::JpegLsTables;
+use self::layout::LineLayout;
+pub use self::layout::OutputLayout;
+use self::lossless::{compute_image_lossless, compute_planes_lossless};
+pub use self::orientation::Orientation;
 
 #[rustfmt::skip]
-static UNZIGZAG: [u8; 64] = [
+pub static UNZIGZAG: [u8; 64] = [
      0,  1,  8, 16,  9,  2,  3, 10,
     17, 24, 32, 25, 18, 11,  4,  5,
     12, 19, 26, 33, 40, 48, 41, 34,
@@ -37,6 +62,7 @@ static UNZIGZAG: [u8; 64] = [
 
 /// An enumeration over combinations of color spaces and bit depths a pixel can have.
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum PixelFormat {
     /// Luminance (grayscale), 8 bits
     L8,
@@ -46,6 +72,32 @@ pub enum PixelFormat {
     RGB24,
     /// CMYK, 8 bits per channel
     CMYK32,
+    /// RGB, 16 bits per channel
+    RGB48,
+    /// CMYK, 16 bits per channel
+    CMYK64,
+    /// The given number of channels without colour transform, 8 bits per channel. Used for
+    /// images with 2 or more than 4 components.
+    Channels8(u8),
+    /// The given number of channels without colour transform, 16 bits per channel. Used for
+    /// images with 2 or more than 4 components.
+    Channels16(u8),
+    /// RGB and an opaque alpha channel, 8 bits per channel, see `OutputLayout::Rgba`
+    RGBA32,
+    /// RGB and an opaque alpha channel, 16 bits per channel, see `OutputLayout::Rgba`
+    RGBA64,
+    /// BGR, 8 bits per channel, see `OutputLayout::Bgr`
+    BGR24,
+    /// BGR, 16 bits per channel, see `OutputLayout::Bgr`
+    BGR48,
+    /// BGR and an opaque alpha channel, 8 bits per channel, see `OutputLayout::Bgra`
+    BGRA32,
+    /// BGR and an opaque alpha channel, 16 bits per channel, see `OutputLayout::Bgra`
+    BGRA64,
+    /// An opaque alpha channel and RGB, 8 bits per channel, see `OutputLayout::Argb`
+    ARGB32,
+    /// An opaque alpha channel and RGB, 16 bits per channel, see `OutputLayout::Argb`
+    ARGB64,
 }
 
 impl PixelFormat {
@@ -56,10 +108,66 @@ impl PixelFormat {
             PixelFormat::L16 => 2,
             PixelFormat::RGB24 => 3,
             PixelFormat::CMYK32 => 4,
+            PixelFormat::RGB48 => 6,
+            PixelFormat::CMYK64 => 8,
+            PixelFormat::Channels8(channels) => usize::from(*channels),
+            PixelFormat::Channels16(channels) => 2 * usize::from(*channels),
+            PixelFormat::BGR24 => 3,
+            PixelFormat::RGBA32 | PixelFormat::BGRA32 | PixelFormat::ARGB32 => 4,
+            PixelFormat::BGR48 => 6,
+            PixelFormat::RGBA64 | PixelFormat::BGRA64 | PixelFormat::ARGB64 => 8,
         }
     }
 }
 
+/// Integer type of decoded samples, `u8` for 8-bit and `u16` for 12-bit DCT frames.
+pub trait Sample: Copy + Default + Send + Sync + 'static {
+    fn from_u32(value: u32) -> Self;
+    fn to_u32(self) -> u32;
+    #[allow(clippy::type_complexity)]
+    fn color_convert_func(
+        component_count: usize,
+        color_transform: ColorTransform,
+        layout: OutputLayout,
+    ) -> Result<fn(&[Vec<Self>], &mut [Self])>;
+}
+
+impl Sample for u8 {
+    fn from_u32(value: u32) -> u8 {
+        value as u8
+    }
+
+    fn to_u32(self) -> u32 {
+        self.into()
+    }
+
+    fn color_convert_func(
+        component_count: usize,
+        color_transform: ColorTransform,
+        layout: OutputLayout,
+    ) -> Result<fn(&[Vec<u8>], &mut [u8])> {
+        choose_color_convert_func(component_count, color_transform, layout)
+    }
+}
+
+impl Sample for u16 {
+    fn from_u32(value: u32) -> u16 {
+        value as u16
+    }
+
+    fn to_u32(self) -> u32 {
+        self.into()
+    }
+
+    fn color_convert_func(
+        component_count: usize,
+        color_transform: ColorTransform,
+        layout: OutputLayout,
+    ) -> Result<fn(&[Vec<u16>], &mut [u16])> {
+        choose_color_convert_func_12bit(component_count, color_transform, layout)
+    }
+}
+
 /// Represents metadata of an image.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct ImageInfo {
@@ -91,65 +199,150 @@ pub enum ColorTransform {
     CMYK,
     /// YCCK transform should be applied.
     YCCK,
-    /// big gamut Y/Cb/Cr, bg-sYCC
+    /// big gamut Y/Cb/Cr, bg-sYCC. The chroma is stored at half the scale of YCbCr.
     JcsBgYcc,
-    /// big gamut red/green/blue, bg-sRGB
+    /// big gamut red/green/blue, bg-sRGB. The samples are returned in the bg-sRGB encoding,
+    /// like libjpeg 9 does.
     JcsBgRgb,
 }
 
+/// Describes the colour conversion `Decoder::decode` applies to images with an embedded ICC
+/// profile.
+///
+/// Grayscale, RGB and CMYK profiles made of tone reproduction curves and colorants, or of
+/// `lut8Type`, `lut16Type` and `lutAtoBType` tags, are supported. Images without a profile, or
+/// whose profile is for another number of components, are returned as they are decoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ColorManagement {
+    /// The decoded samples are returned without conversion.
+    None,
+    /// The samples are converted to sRGB. Grayscale images keep a single channel, converted to
+    /// the sRGB tone curve.
+    Srgb,
+    /// The samples are converted to the colour space of a grayscale, RGB or CMYK profile, made of
+    /// tone reproduction curves and colorants or of `lut8Type`, `lut16Type` and `lutBtoAType`
+    /// tags.
+    Profile(IccProfile),
+}
+
+/// An application (APPn) segment collected by the decoder, see
+/// `Decoder::set_app_segment_filter`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AppSegment {
+    /// The number n of the APPn marker, from 0 to 15.
+    pub marker: u8,
+    /// The offset of the marker in bytes from where the input was positioned when the decoder was
+    /// created.
+    pub offset: u64,
+    /// The contents of the segment, following the segment length.
+    pub data: Vec<u8>,
+}
+
 /// JPEG decoder
 pub struct Decoder<R> {
-    reader: R,
+    reader: PositionReader<R>,
 
     frame: Option<FrameInfo>,
+    hierarchical: Option<Hierarchical>,
     dc_huffman_tables: Vec<Option<HuffmanTable>>,
     ac_huffman_tables: Vec<Option<HuffmanTable>>,
     quantization_tables: [Option<Arc<[u16; 64]>>; 4],
+    dc_conditioning_tables: [DcConditioning; 4],
+    ac_conditioning_tables: [AcConditioning; 4],
 
     restart_interval: u16,
 
     adobe_color_transform: Option<AdobeColorTransform>,
     color_transform: Option<ColorTransform>,
+    // Red and blue are stored with green subtracted, signalled by an LSE marker segment.
+    subtract_green: bool,
+    jpeg_ls: JpegLsTables,
 
-    is_jfif: bool,
+    jfif_header: Option<JfifHeader>,
+    jfif_thumbnails: Vec<JfifThumbnail>,
     is_mjpeg: bool,
 
     icc_markers: Vec<IccChunk>,
+    // ICC profile of the APP2 chunks, parsed when the first frame header is read
+    icc_profile: core::result::Result<Option<IccProfile>, IccError>,
 
     exif_data: Option<Vec<u8>>,
+    // Orientation given by the exif data, parsed when the exif data is read
+    orientation: Option<Orientation>,
     xmp_data: Option<Vec<u8>>,
+    extended_xmp_chunks: Vec<ExtendedXmpChunk>,
     psir_data: Option<Vec<u8>>,
+    jumbf_segments: Vec<JumbfSegment>,
+    comments: Vec<Vec<u8>>,
+    comments_size: usize,
+    app_segment_filter: Option<Box<dyn Fn(u8) -> bool + Send + Sync>>,
+    app_segments: Vec<AppSegment>,
+    mpf_images: Option<Vec<MpImage>>,
 
     // Used for progressive JPEGs.
     coefficients: Vec<Vec<i16>>,
     // Bitmask of which coefficients has been completely decoded.
-    coefficients_finished: [u64; MAX_COMPONENTS],
+    coefficients_finished: Vec<u64>,
 
     // Maximum allowed size of decoded image buffer
     decoding_buffer_size_limit: usize,
+    // Maximum total size of the kept comments
+    comments_size_limit: usize,
+    // Number of resolution levels of a hierarchical image to decode
+    max_hierarchical_levels: usize,
+    // Whether the exif orientation is applied to the decoded pixels
+    apply_orientation: bool,
+    color_management: ColorManagement,
+    // Whether CMYK images are converted to RGB
+    cmyk_to_rgb: bool,
+    output_layout: OutputLayout,
 }
 
 impl<R: Read> Decoder<R> {
     /// Creates a new `Decoder` using the reader `reader`.
     pub fn new(reader: R) -> Decoder<R> {
         Decoder {
-            reader,
+            reader: PositionReader {
+                inner: reader,
+                position: 0,
+            },
             frame: None,
+            hierarchical: None,
             dc_huffman_tables: vec![None, None, None, None],
             ac_huffman_tables: vec![None, None, None, None],
             quantization_tables: [None, None, None, None],
+            dc_conditioning_tables: [DcConditioning::default(); 4],
+            ac_conditioning_tables: [AcConditioning::default(); 4],
             restart_interval: 0,
             adobe_color_transform: None,
             color_transform: None,
-            is_jfif: false,
+            subtract_green: false,
+            jpeg_ls: JpegLsTables::default(),
+            jfif_header: None,
+            jfif_thumbnails: Vec::new(),
             is_mjpeg: false,
             icc_markers: Vec::new(),
+            icc_profile: Ok(None),
             exif_data: None,
+            orientation: None,
             xmp_data: None,
+            extended_xmp_chunks: Vec::new(),
             psir_data: None,
+            jumbf_segments: Vec::new(),
+            comments: Vec::new(),
+            comments_size: 0,
+            app_segment_filter: None,
+            app_segments: Vec::new(),
+            mpf_images: None,
             coefficients: Vec::new(),
-            coefficients_finished: [0; MAX_COMPONENTS],
+            coefficients_finished: Vec::new(),
             decoding_buffer_size_limit: usize::MAX,
+            comments_size_limit: 1 << 20,
+            max_hierarchical_levels: usize::MAX,
+            apply_orientation: false,
+            color_management: ColorManagement::None,
+            cmyk_to_rgb: false,
+            output_layout: OutputLayout::Native,
         }
     }
 
@@ -159,35 +352,139 @@ impl<R: Read> Decoder<R> {
         self.color_transform = Some(transform);
     }
 
+    /// Converts the colours of images with an embedded ICC profile when decoding, see
+    /// `ColorManagement`. `info` then returns the pixel format of the converted image.
+    ///
+    /// `decode` returns an `Error::Icc` if the profile is broken or cannot be used. The planes of
+    /// `decode_planar` are not converted.
+    pub fn set_color_management(&mut self, color_management: ColorManagement) {
+        self.color_management = color_management;
+    }
+
+    /// Converts CMYK and YCCK images to RGB when decoding, `info` then returns `RGB24` or `RGB48`.
+    /// Disabled by default.
+    ///
+    /// The embedded ICC profile is used to convert to sRGB if it is supported, otherwise the
+    /// samples are converted without colour management. The inverted samples stored by Adobe
+    /// applications are told apart by the Adobe segment, see `adobe_color_transform`. Colour
+    /// management enabled by `set_color_management` takes precedence.
+    pub fn set_cmyk_to_rgb(&mut self, convert: bool) {
+        self.cmyk_to_rgb = convert;
+    }
+
+    /// Arranges the pixels `decode` returns in the layout, `info` then returns the pixel format
+    /// of the layout. Defaults to `OutputLayout::Native`.
+    ///
+    /// Grayscale and RGB images are arranged after any conversion by `set_color_management` and
+    /// `set_cmyk_to_rgb`. The colour conversion of 8-bit YCbCr and RGB images writes the layout
+    /// directly.
+    pub fn set_output_layout(&mut self, layout: OutputLayout) {
+        self.output_layout = layout;
+    }
+
     /// Set maximum buffer size allowed for decoded images
     pub fn set_max_decoding_buffer_size(&mut self, max: usize) {
         self.decoding_buffer_size_limit = max;
     }
 
+    /// Rotates and mirrors the decoded pixels as given by the exif orientation, see `orientation`.
+    /// `info` then returns the size of the upright image. Disabled by default.
+    pub fn set_apply_orientation(&mut self, apply: bool) {
+        self.apply_orientation = apply;
+    }
+
+    /// Set maximum total size of the comments kept by `comments`, 1 MiB by default. Comments that
+    /// do not fit are discarded.
+    pub fn set_max_comments_size(&mut self, max: usize) {
+        self.comments_size_limit = max;
+    }
+
+    /// Collects the application segments whose marker number n of APPn the filter returns `true`
+    /// for, including the ones the decoder interprets itself. They are returned by
+    /// `app_segments`.
+    ///
+    /// ```
+    /// # use jpeg_decoder::Decoder;
+    /// # let input: &[u8] = &[];
+    /// let mut decoder = Decoder::new(input);
+    /// // Keep APP11 (JUMBF) and APP15 segments
+    /// decoder.set_app_segment_filter(|marker| marker == 11 || marker == 15);
+    /// ```
+    pub fn set_app_segment_filter<F>(&mut self, filter: F)
+    where
+        F: Fn(u8) -> bool + Send + Sync + 'static,
+    {
+        self.app_segment_filter = Some(Box::new(filter));
+    }
+
+    /// Limits the number of resolution levels decoded from a hierarchical image.
+    ///
+    /// A hierarchical image stores frames of increasing resolution, each refining the previous
+    /// one. Decoding stops before the first frame that exceeds the given number of distinct frame
+    /// sizes and the image is returned at the resolution reached so far, which `info` reports.
+    /// As the size of a level is only given by its first frame, `read_info` decodes the levels
+    /// below the last one to find it. A limit of zero is treated as one. This has no effect on
+    /// other images.
+    pub fn set_max_hierarchical_levels(&mut self, levels: usize) {
+        self.max_hierarchical_levels = levels.max(1);
+    }
+
     /// Returns metadata about the image.
     ///
     /// The returned value will be `None` until a call to either `read_info` or `decode` has
     /// returned `Ok`.
+    ///
+    /// Images that define their height with a DNL segment report a height of zero after
+    /// `read_info`, the actual height is known once `decode` has returned `Ok`. Likewise the pixel
+    /// format of JPEG-LS images using mapping tables, such as palettes, is only known then.
     pub fn info(&self) -> Option<ImageInfo> {
-        match self.frame {
+        let (mut info, _) = self.stored_info()?;
+
+        if let Some(orientation) = self.applied_orientation() {
+            if orientation.swaps_dimensions() {
+                mem::swap(&mut info.width, &mut info.height);
+            }
+        }
+
+        info.pixel_format = self
+            .output_layout
+            .pixel_format(self.converted_format(info.pixel_format));
+
+        Some(info)
+    }
+
+    // Returns the size and pixel format of the image as it is stored, and the precision of its
+    // samples.
+    fn stored_info(&self) -> Option<(ImageInfo, u8)> {
+        let frame = match self.hierarchical {
+            Some(ref hierarchical) => &hierarchical.frame,
+            None => &self.frame,
+        };
+
+        match *frame {
             Some(ref frame) => {
-                let pixel_format = match frame.components.len() {
-                    1 => match frame.precision {
-                        2..=8 => PixelFormat::L8,
-                        9..=16 => PixelFormat::L16,
-                        _ => panic!(),
-                    },
-                    3 => PixelFormat::RGB24,
-                    4 => PixelFormat::CMYK32,
+                let mapped = self.jpeg_ls.mapped_frame(frame);
+                let frame = mapped.as_ref().unwrap_or(frame);
+                let pixel_format = match (frame.components.len(), frame.precision) {
+                    (1, 2..=8) => PixelFormat::L8,
+                    (1, 9..=16) => PixelFormat::L16,
+                    (3, 2..=8) => PixelFormat::RGB24,
+                    (3, 9..=16) => PixelFormat::RGB48,
+                    (4, 2..=8) => PixelFormat::CMYK32,
+                    (4, 9..=16) => PixelFormat::CMYK64,
+                    (count, 2..=8) => PixelFormat::Channels8(count as u8),
+                    (count, 9..=16) => PixelFormat::Channels16(count as u8),
                     _ => panic!(),
                 };
 
-                Some(ImageInfo {
+                let info = ImageInfo {
                     width: frame.output_size.width,
                     height: frame.output_size.height,
                     pixel_format,
                     coding_process: frame.coding_process,
-                })
+                };
+
+                Some((info, frame.precision))
             }
             None => None,
         }
@@ -200,6 +497,32 @@ impl<R: Read> Decoder<R> {
         self.exif_data.as_deref()
     }
 
+    /// Returns the fields of the exif data, if the image contains any, see `Exif::parse`.
+    pub fn exif(&self) -> Result<Option<Exif>> {
+        match self.exif_data {
+            Some(ref data) => Exif::parse(data).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the orientation given by the Orientation (0x0112) field of the exif data, if there
+    /// is a valid one.
+    ///
+    /// The returned value will be `None` until a call to either `read_info` or `decode` has
+    /// returned `Ok`.
+    pub fn orientation(&self) -> Option<Orientation> {
+        self.orientation
+    }
+
+    // Returns the orientation applied to the decoded pixels, see `set_apply_orientation`.
+    pub(crate) fn applied_orientation(&self) -> Option<Orientation> {
+        if self.apply_orientation {
+            self.orientation()
+        } else {
+            None
+        }
+    }
+
     /// Returns the raw XMP packet if there is any.
     ///
     /// The returned value will be `None` until a call to `decode` has returned `Ok`.
@@ -207,37 +530,230 @@ impl<R: Read> Decoder<R> {
         self.xmp_data.as_deref()
     }
 
-    /// Returns the embeded icc profile if the image contains one.
-    pub fn icc_profile(&self) -> Option<Vec<u8>> {
-        let mut marker_present: [Option<&IccChunk>; 256] = [None; 256];
-        let num_markers = self.icc_markers.len();
-        if num_markers == 0 || num_markers >= 255 {
-            return None;
-        }
-        // check the validity of the markers
-        for chunk in &self.icc_markers {
-            if usize::from(chunk.num_markers) != num_markers {
-                // all the lengths must match
-                return None;
-            }
-            if chunk.seq_no == 0 {
-                return None;
+    /// Returns the contents of the COM segments in the order they appear in, see
+    /// `set_max_comments_size`.
+    ///
+    /// The returned value will be empty until a call to either `read_info` or `decode` has
+    /// returned `Ok`.
+    pub fn comments(&self) -> &[Vec<u8>] {
+        &self.comments
+    }
+
+    /// Returns the comments as text, replacing invalid UTF-8 sequences, see `comments`.
+    pub fn comments_lossy(&self) -> Vec<Cow<'_, str>> {
+        self.comments
+            .iter()
+            .map(|comment| String::from_utf8_lossy(comment))
+            .collect()
+    }
+
+    /// Returns the application segments collected according to `set_app_segment_filter`, in the
+    /// order they appear in.
+    ///
+    /// The returned value will be empty until a call to either `read_info` or `decode` has
+    /// returned `Ok`.
+    pub fn app_segments(&self) -> &[AppSegment] {
+        &self.app_segments
+    }
+
+    /// Returns the byte range of the JPEG thumbnail stored in IFD1 of the exif data, relative to
+    /// `exif_data`.
+    ///
+    /// The returned value will be `None` if there is no exif data or it holds no JPEG thumbnail,
+    /// an error is returned if the thumbnail lies outside of the exif data.
+    pub fn exif_thumbnail_range(&self) -> Result<Option<Range<usize>>> {
+        match self.exif_data {
+            Some(ref exif) => parse_exif_thumbnail(exif),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns a decoder for the JPEG thumbnail stored in the exif data, see
+    /// `exif_thumbnail_range`.
+    pub fn exif_thumbnail_decoder(&self) -> Result<Option<Decoder<&[u8]>>> {
+        let range = match self.exif_thumbnail_range()? {
+            Some(range) => range,
+            None => return Ok(None),
+        };
+        let exif = self.exif_data.as_deref().unwrap();
+
+        let mut decoder = Decoder::new(&exif[range]);
+        decoder.set_max_decoding_buffer_size(self.decoding_buffer_size_limit);
+        Ok(Some(decoder))
+    }
+
+    /// Returns the version and pixel density given by the JFIF APP0 segment, if the image has one.
+    ///
+    /// The returned value will be `None` until a call to either `read_info` or `decode` has
+    /// returned `Ok`.
+    pub fn jfif_header(&self) -> Option<JfifHeader> {
+        self.jfif_header
+    }
+
+    /// Returns the colour transform of the Adobe APP14 segment, if the image has one. Adobe
+    /// applications store the samples of CMYK and YCCK images inverted.
+    ///
+    /// The returned value will be `None` until a call to either `read_info` or `decode` has
+    /// returned `Ok`.
+    pub fn adobe_color_transform(&self) -> Option<AdobeColorTransform> {
+        self.adobe_color_transform
+    }
+
+    /// Returns the pixel density of the image.
+    ///
+    /// The density is taken from the JFIF APP0 segment, or from the XResolution, YResolution and
+    /// ResolutionUnit of the exif data if there is no JFIF segment or it only gives the pixel
+    /// aspect ratio. The returned value will be `None` if neither gives a density.
+    pub fn pixel_density(&self) -> Option<PixelDensity> {
+        let jfif = self.jfif_header.map(|header| header.density);
+        let exif = self.exif_data.as_deref().and_then(parse_exif_density);
+
+        match (jfif, exif) {
+            (Some(jfif), Some(exif)) if jfif.unit == DensityUnit::None => {
+                if exif.unit == DensityUnit::None {
+                    Some(jfif)
+                } else {
+                    Some(exif)
+                }
             }
-            if marker_present[usize::from(chunk.seq_no)].is_some() {
-                // duplicate seq_no
-                return None;
-            } else {
-                marker_present[usize::from(chunk.seq_no)] = Some(chunk);
+            (Some(jfif), _) => Some(jfif),
+            (None, exif) => exif,
+        }
+    }
+
+    /// Returns the thumbnails of the JFIF APP0 segment and of JFXX extension segments, in the order
+    /// they appear in.
+    ///
+    /// The returned value will be empty until a call to either `read_info` or `decode` has
+    /// returned `Ok`.
+    pub fn jfif_thumbnails(&self) -> &[JfifThumbnail] {
+        &self.jfif_thumbnails
+    }
+
+    /// Returns the raw Photoshop image resource data of APP13 segments, following their
+    /// `Photoshop 3.0` identifier.
+    ///
+    /// The returned value will be `None` until a call to either `read_info` or `decode` has
+    /// returned `Ok`.
+    pub fn psir_data(&self) -> Option<&[u8]> {
+        self.psir_data.as_deref()
+    }
+
+    /// Returns the Photoshop image resource blocks, see `psir_data`.
+    pub fn image_resources(&self) -> Result<Vec<ImageResource<'_>>> {
+        match self.psir_data {
+            Some(ref data) => ImageResource::parse_all(data),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Returns the IPTC-IIM metadata of the IPTC-NAA Photoshop image resource, if there is one.
+    pub fn iptc(&self) -> Result<Option<Iptc<'_>>> {
+        match self
+            .image_resources()?
+            .iter()
+            .find_map(|resource| resource.iptc_data())
+        {
+            Some(data) => Iptc::parse(data).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the JPEG universal metadata box format (JUMBF) boxes of APP11 segments, such as
+    /// C2PA content credentials, which can be parsed with `JumbfBox::parse_all`.
+    ///
+    /// The segments of each box instance are joined in the order of their sequence numbers and
+    /// the boxes are ordered by instance number. The returned value will be `None` until a call
+    /// to either `read_info` or `decode` has returned `Ok`.
+    pub fn jumbf_data(&self) -> Result<Option<Vec<u8>>> {
+        if self.jumbf_segments.is_empty() {
+            return Ok(None);
+        }
+
+        jumbf::reassemble(&self.jumbf_segments).map(Some)
+    }
+
+    /// Returns the extended XMP packet, which holds the properties that do not fit into the XMP
+    /// packet returned by `xmp_data`, such as depth maps.
+    ///
+    /// The packet is joined from the chunks whose GUID matches the `xmpNote:HasExtendedXMP`
+    /// property of the XMP packet, looked up with the prefix the packet declares for the
+    /// `http://ns.adobe.com/xmp/note/` namespace. Returns `None` if the XMP packet has no such
+    /// property, and an error if the chunks do not make up the whole extended packet.
+    pub fn extended_xmp_data(&self) -> Result<Option<Vec<u8>>> {
+        let guid = match self
+            .xmp_data
+            .as_deref()
+            .and_then(|xmp| core::str::from_utf8(xmp).ok())
+            .and_then(|xmp| xmp::property(xmp, xmp::XMP_NOTE_NAMESPACE, "HasExtendedXMP"))
+        {
+            Some(values) => values[0].trim().as_bytes(),
+            None => return Ok(None),
+        };
+
+        let mut chunks: Vec<&ExtendedXmpChunk> = self
+            .extended_xmp_chunks
+            .iter()
+            .filter(|chunk| chunk.guid[..] == *guid)
+            .collect();
+        chunks.sort_by_key(|chunk| chunk.offset);
+
+        // XMP Specification Part 3 Section 1.1.3.1: All chunks give the full length of the packet
+        // and the offset of their data in it.
+        let mut length = 0usize;
+        for chunk in &chunks {
+            if chunk.full_length != chunks[0].full_length || chunk.offset as usize != length {
+                return Err(Error::Format(
+                    "extended XMP chunks overlap or leave gaps".to_owned(),
+                ));
             }
+            length += chunk.data.len();
         }
 
-        // assemble them together by seq_no failing if any are missing
-        let mut data = Vec::new();
-        // seq_no's start at 1
-        for &chunk in marker_present.get(1..=num_markers)? {
-            data.extend_from_slice(&chunk?.data);
+        if chunks.is_empty() || length != chunks[0].full_length as usize {
+            return Err(Error::Format(
+                "extended XMP packet is incomplete".to_owned(),
+            ));
+        }
+
+        let data = chunks.iter().flat_map(|chunk| &chunk.data).copied();
+        Ok(Some(data.collect()))
+    }
+
+    /// Returns the images of a Multi-Picture Format (MPF) file, if the image contains an MP Index.
+    ///
+    /// The first image is the primary one decoded by this decoder, the others can be decoded
+    /// using `mpf_image_decoder`. The returned value will be `None` until a call to either
+    /// `read_info` or `decode` has returned `Ok`.
+    pub fn mpf_images(&self) -> Option<&[MpImage]> {
+        self.mpf_images.as_deref()
+    }
+
+    /// Returns the embeded icc profile if the image contains one.
+    ///
+    /// The returned value will be `None` if the APP2 chunks of the profile are broken, `icc`
+    /// tells why.
+    pub fn icc_profile(&self) -> Option<Vec<u8>> {
+        icc::reassemble(&self.icc_markers).ok().flatten()
+    }
+
+    /// Returns the embedded ICC profile with the fields of its header, if the image contains one.
+    ///
+    /// An `Error::Icc` is returned if a chunk of the profile is missing, duplicate or has a
+    /// sequence number out of range, if the chunks disagree about their number, or if the profile
+    /// header is invalid.
+    ///
+    /// The returned value will be `None` until a call to either `read_info` or `decode` has
+    /// returned `Ok`.
+    pub fn icc(&self) -> Result<Option<IccProfile>> {
+        self.embedded_icc().map(Option::<&IccProfile>::cloned)
+    }
+
+    fn embedded_icc(&self) -> Result<Option<&IccProfile>> {
+        match self.icc_profile {
+            Ok(ref profile) => Ok(profile.as_ref()),
+            Err(error) => Err(Error::Icc(error)),
         }
-        Some(data)
     }
 
     /// Heuristic to avoid starting thread, synchronization if we expect a small amount of
@@ -259,11 +775,24 @@ impl<R: Read> Decoder<R> {
         }
     }
 
+    /// Whether the coefficients of the scans are kept until the end of the frame instead of being
+    /// handed to the worker row by row.
+    fn keeps_coefficients(&self, frame: &FrameInfo) -> bool {
+        match frame.coding_process {
+            CodingProcess::DctProgressive => true,
+            // The height is zero until it is defined by the DNL marker after the first scan.
+            CodingProcess::DctSequential => {
+                self.hierarchical.is_some() || frame.image_size.height == 0
+            }
+            CodingProcess::Lossless => false,
+        }
+    }
+
     /// Tries to read metadata from the image without decoding it.
     ///
     /// If successful, the metadata can be obtained using the `info` method.
     pub fn read_info(&mut self) -> Result<()> {
-        WorkerScope::with(|worker| self.decode_internal(true, worker)).map(|_| ())
+        WorkerScope::with(|worker| self.decode_internal(true, false, worker)).map(|_| ())
     }
 
     /// Configure the decoder to scale the image during decoding.
@@ -275,9 +804,17 @@ impl<R: Read> Decoder<R> {
     ///
     /// To generate a thumbnail of an exact size, pass the desired size and
     /// then scale to the final size using a traditional resampling algorithm.
+    ///
+    /// Hierarchical images are not scaled, see `set_max_hierarchical_levels` instead. Neither are
+    /// images with 12-bit samples or whose height is defined by a DNL segment. For these this
+    /// method has no effect and returns the size `info` reports.
     pub fn scale(&mut self, requested_width: u16, requested_height: u16) -> Result<(u16, u16)> {
         self.read_info()?;
         let frame = self.frame.as_mut().unwrap();
+        if self.hierarchical.is_some() || frame.precision != 8 || frame.image_size.height == 0 {
+            let info = self.info().unwrap();
+            return Ok((info.width, info.height));
+        }
         let idct_size = crate::idct::choose_idct_size(
             frame.image_size,
             Dimensions {
@@ -291,14 +828,197 @@ impl<R: Read> Decoder<R> {
 
     /// Decodes the image and returns the decoded pixels if successful.
     pub fn decode(&mut self) -> Result<Vec<u8>> {
-        WorkerScope::with(|worker| self.decode_internal(false, worker))
+        let mut output = WorkerScope::with(|worker| self.decode_internal(false, false, worker))?;
+        let data = self.convert_colors(output.remove(0))?;
+        let data = self.arrange(data);
+        Ok(self.orient(data))
+    }
+
+    /// Decodes the image and returns the samples of each component in a separate plane.
+    ///
+    /// Every plane is upsampled to the size of the image and no colour transform is applied,
+    /// which suits images whose components are not colours. Samples of more than 8 bits are
+    /// returned as native endian `u16`, like `decode` does.
+    pub fn decode_planar(&mut self) -> Result<Vec<Vec<u8>>> {
+        let planes = WorkerScope::with(|worker| self.decode_internal(false, true, worker))?;
+        Ok(planes.into_iter().map(|plane| self.orient(plane)).collect())
+    }
+
+    // Applies the exif orientation to the decoded pixels if `set_apply_orientation` asks for it.
+    fn orient(&self, data: Vec<u8>) -> Vec<u8> {
+        let orientation = match self.applied_orientation() {
+            Some(orientation) => orientation,
+            None => return data,
+        };
+
+        let (info, _) = self.stored_info().unwrap();
+        orientation.apply(data, info.width.into(), info.height.into())
     }
 
+    // Converts the decoded pixels to the colour space `set_color_management` and `set_cmyk_to_rgb`
+    // ask for.
+    fn convert_colors(&self, mut data: Vec<u8>) -> Result<Vec<u8>> {
+        let (info, precision) = self.stored_info().unwrap();
+        let sample_max = ((1u32 << precision) - 1) as u16;
+        let cmyk = matches!(info.pixel_format, PixelFormat::CMYK32 | PixelFormat::CMYK64);
+
+        let transform = if self.color_managed() {
+            match self.embedded_profile(info.pixel_format)? {
+                Some(source) => Some(Transform::new(source, self.destination_profile())?),
+                None => None,
+            }
+        } else if cmyk && self.cmyk_to_rgb {
+            // Broken or unsupported profiles fall back to the conversion without colour management.
+            self.embedded_profile(info.pixel_format)
+                .ok()
+                .flatten()
+                .and_then(|source| Transform::new(source, None).ok())
+        } else {
+            None
+        };
+
+        if transform.is_none() && !(cmyk && self.cmyk_to_rgb) {
+            return Ok(data);
+        }
+
+        if cmyk && self.cmyk_inverted(info.coding_process) {
+            invert_samples(&mut data, sample_max);
+        }
+
+        Ok(match transform {
+            Some(transform) => transform.apply(&data, sample_max),
+            None => cmyk_to_rgb(&data, sample_max),
+        })
+    }
+
+    // Arranges the pixels in the layout `set_output_layout` asks for, unless the colour conversion
+    // of the lines already has.
+    fn arrange(&self, data: Vec<u8>) -> Vec<u8> {
+        if self.line_layout() != OutputLayout::Native {
+            return data;
+        }
+
+        let (info, precision) = self.stored_info().unwrap();
+        let sample_max = ((1u32 << precision) - 1) as u16;
+        self.output_layout
+            .arrange(data, self.converted_format(info.pixel_format), sample_max)
+    }
+
+    // Returns the layout the colour conversion of the lines produces, which it does for 8-bit
+    // YCbCr and RGB images whose pixels are not converted afterwards.
+    fn line_layout(&self) -> OutputLayout {
+        let (info, _) = match self.stored_info() {
+            Some(info) => info,
+            None => return OutputLayout::Native,
+        };
+
+        let converts = info.pixel_format == PixelFormat::RGB24
+            && info.coding_process != CodingProcess::Lossless
+            && !self.subtract_green
+            && self.converted_channels(info.pixel_format).is_none()
+            && matches!(
+                self.determine_color_transform(),
+                ColorTransform::YCbCr
+                    | ColorTransform::RGB
+                    | ColorTransform::JcsBgYcc
+                    | ColorTransform::JcsBgRgb
+            );
+
+        if converts {
+            self.output_layout
+        } else {
+            OutputLayout::Native
+        }
+    }
+
+    // Returns the pixel format `convert_colors` converts the decoded pixels to.
+    fn converted_format(&self, pixel_format: PixelFormat) -> PixelFormat {
+        let wide = sample_size(pixel_format) == 2;
+
+        match (self.converted_channels(pixel_format), wide) {
+            (Some(1), false) => PixelFormat::L8,
+            (Some(1), true) => PixelFormat::L16,
+            (Some(3), false) => PixelFormat::RGB24,
+            (Some(3), true) => PixelFormat::RGB48,
+            (Some(4), false) => PixelFormat::CMYK32,
+            (Some(4), true) => PixelFormat::CMYK64,
+            _ => pixel_format,
+        }
+    }
+
+    // Returns the number of channels `convert_colors` converts the decoded pixels to, if it
+    // converts them.
+    fn converted_channels(&self, pixel_format: PixelFormat) -> Option<usize> {
+        if self.color_managed() {
+            if let Ok(Some(source)) = self.embedded_profile(pixel_format) {
+                return cms::output_channels(source, self.destination_profile());
+            }
+        }
+
+        match pixel_format {
+            PixelFormat::CMYK32 | PixelFormat::CMYK64 if self.cmyk_to_rgb => Some(3),
+            _ => None,
+        }
+    }
+
+    // Whether the decoded CMYK samples are inverted, the largest value being no ink. Adobe
+    // applications store inverted CMYK, which the CMYK transform inverts back, while other
+    // applications store plain CMYK.
+    fn cmyk_inverted(&self, coding_process: CodingProcess) -> bool {
+        let adobe = self.adobe_color_transform.is_some();
+
+        // Lossless frames are returned without colour transform.
+        if coding_process == CodingProcess::Lossless {
+            return adobe;
+        }
+
+        match self.determine_color_transform() {
+            ColorTransform::CMYK => !adobe,
+            ColorTransform::YCCK => false,
+            _ => adobe,
+        }
+    }
+
+    fn color_managed(&self) -> bool {
+        !matches!(self.color_management, ColorManagement::None)
+    }
+
+    // Returns the embedded profile if it is of the colour space of the decoded pixels. Profiles of
+    // another colour space, such as RGB profiles of grayscale images, are ignored.
+    fn embedded_profile(&self, pixel_format: PixelFormat) -> Result<Option<&IccProfile>> {
+        let profile = match self.embedded_icc()? {
+            Some(profile) => profile,
+            None => return Ok(None),
+        };
+
+        let channels = match pixel_format {
+            PixelFormat::L8 | PixelFormat::L16 => 1,
+            PixelFormat::RGB24 | PixelFormat::RGB48 => 3,
+            PixelFormat::CMYK32 | PixelFormat::CMYK64 => 4,
+            _ => 0,
+        };
+
+        if cms::device_channels(profile.color_space) != Some(channels) {
+            return Ok(None);
+        }
+
+        Ok(Some(profile))
+    }
+
+    fn destination_profile(&self) -> Option<&IccProfile> {
+        match self.color_management {
+            ColorManagement::Profile(ref profile) => Some(profile),
+            _ => None,
+        }
+    }
+
+    // Returns the interleaved pixels as a single plane unless `planar` is set.
     fn decode_internal(
         &mut self,
         stop_after_metadata: bool,
+        planar: bool,
         worker_scope: &WorkerScope,
-    ) -> Result<Vec<u8>> {
+    ) -> Result<Vec<Vec<u8>>> {
         if stop_after_metadata && self.frame.is_some() {
             // The metadata has already been read.
             return Ok(Vec::new());
@@ -336,51 +1056,75 @@ impl<R: Read> Decoder<R> {
             match marker {
                 // Frame header
                 Marker::SOF(..) => {
+                    let frame = parse_sof(&mut self.reader, marker)?;
+                    let component_count = frame.components.len();
+
                     // Section 4.10
                     // "An image contains only one frame in the cases of sequential and
                     //  progressive coding processes; an image contains multiple frames for the
                     //  hierarchical mode."
-                    if self.frame.is_some() {
-                        return Err(Error::Unsupported(UnsupportedFeature::Hierarchical));
-                    }
-
-                    let frame = parse_sof(&mut self.reader, marker)?;
-                    let component_count = frame.components.len();
-
-                    if frame.is_differential {
-                        return Err(Error::Unsupported(UnsupportedFeature::Hierarchical));
+                    if self.hierarchical.is_none() {
+                        if self.frame.is_some() {
+                            return Err(Error::Format(
+                                "multiple frames in a non-hierarchical image".to_owned(),
+                            ));
+                        }
+                        if frame.is_differential {
+                            return Err(Error::Format(
+                                "differential frame in a non-hierarchical image".to_owned(),
+                            ));
+                        }
                     }
-                    if frame.entropy_coding == EntropyCoding::Arithmetic {
+                    if frame.entropy_coding == EntropyCoding::Arithmetic
+                        && frame.coding_process == CodingProcess::Lossless
+                    {
                         return Err(Error::Unsupported(
                             UnsupportedFeature::ArithmeticEntropyCoding,
                         ));
                     }
-                    if frame.precision != 8 && frame.coding_process != CodingProcess::Lossless {
-                        return Err(Error::Unsupported(UnsupportedFeature::SamplePrecision(
-                            frame.precision,
-                        )));
-                    }
                     if !(2..=16).contains(&frame.precision) {
                         return Err(Error::Unsupported(UnsupportedFeature::SamplePrecision(
                             frame.precision,
                         )));
                     }
-                    if component_count != 1 && component_count != 3 && component_count != 4 {
-                        return Err(Error::Unsupported(UnsupportedFeature::ComponentCount(
-                            component_count as u8,
-                        )));
+                    if frame.entropy_coding == EntropyCoding::JpegLs {
+                        if self.hierarchical.is_some() {
+                            return Err(Error::Format(
+                                "JPEG-LS frame in a hierarchical image".to_owned(),
+                            ));
+                        }
+                        if frame.components.iter().any(|c| {
+                            c.horizontal_sampling_factor != 1 || c.vertical_sampling_factor != 1
+                        }) {
+                            return Err(Error::Unsupported(UnsupportedFeature::SubsamplingRatio));
+                        }
+                    }
+
+                    if self.hierarchical.is_some() {
+                        // The components of the image and their upsampling are defined by the
+                        // DHP segment, each frame only refines some of them.
+                        self.finish_hierarchical_frame(mem::take(&mut planes_u16))?;
+
+                        if !self.start_hierarchical_frame(&frame)? {
+                            break;
+                        }
                     }
 
-                    // Make sure we support the subsampling ratios used.
-                    let _ = Upsampler::new(
-                        &frame.components,
-                        frame.image_size.width,
-                        frame.image_size.height,
-                    )?;
+                    // The APP2 chunks precede the first frame header.
+                    if self.frame.is_none() {
+                        self.icc_profile = icc::read_profile(&self.icc_markers);
+                    }
 
+                    self.coefficients_finished = vec![0; component_count];
                     self.frame = Some(frame);
 
-                    if stop_after_metadata {
+                    // The lower levels of a hierarchical image are decoded until the size of
+                    // the image is known.
+                    if stop_after_metadata
+                        && self.hierarchical.as_ref().map_or(true, |hierarchical| {
+                            hierarchical.has_final_size(self.max_hierarchical_levels)
+                        })
+                    {
                         return Ok(Vec::new());
                     }
 
@@ -397,9 +1141,28 @@ impl<R: Read> Decoder<R> {
                     let frame = self.frame.clone().unwrap();
                     let scan = parse_sos(&mut self.reader, &frame)?;
 
-                    if frame.coding_process == CodingProcess::DctProgressive
-                        && self.coefficients.is_empty()
-                    {
+                    if let Some(ref mut hierarchical) = self.hierarchical {
+                        hierarchical.latch_quantization_tables(
+                            &scan,
+                            &frame,
+                            &self.quantization_tables,
+                        );
+                    }
+
+                    if frame.image_size.height == 0 {
+                        // Section B.2.1
+                        // "If a DNL segment (see B.2.5) is present, it shall immediately follow the first scan."
+                        if scans_processed > 0 {
+                            return Err(Error::Format(
+                                "missing DNL segment after the first scan".to_owned(),
+                            ));
+                        }
+                        if frame.entropy_coding != EntropyCoding::Huffman {
+                            return Err(Error::Unsupported(UnsupportedFeature::DNL));
+                        }
+                    }
+
+                    if self.keeps_coefficients(&frame) && self.coefficients.is_empty() {
                         self.coefficients = frame
                             .components
                             .iter()
@@ -411,7 +1174,18 @@ impl<R: Read> Decoder<R> {
                             .collect();
                     }
 
-                    if frame.coding_process == CodingProcess::Lossless {
+                    if frame.entropy_coding == EntropyCoding::JpegLs {
+                        let (marker, data) = self.decode_scan_jpeg_ls(&frame, &scan)?;
+
+                        for (i, plane) in data
+                            .into_iter()
+                            .enumerate()
+                            .filter(|(_, plane)| !plane.is_empty())
+                        {
+                            planes_u16[i] = plane;
+                        }
+                        pending_marker = marker;
+                    } else if frame.coding_process == CodingProcess::Lossless {
                         let (marker, data) = self.decode_scan_lossless(&frame, &scan)?;
 
                         for (i, plane) in data
@@ -436,9 +1210,12 @@ impl<R: Read> Decoder<R> {
                         // are part of a scan than allowed.) What a weird edge case.
                         //
                         // But this means we track precisely which components get completed here.
-                        let mut finished = [false; MAX_COMPONENTS];
+                        let mut finished = [false; MAX_SCAN_COMPONENTS];
 
-                        if scan.successive_approximation_low == 0 {
+                        if scan.successive_approximation_low == 0
+                            && self.hierarchical.is_none()
+                            && frame.image_size.height != 0
+                        {
                             for (&i, component_finished) in
                                 scan.component_indices.iter().zip(&mut finished)
                             {
@@ -462,7 +1239,7 @@ impl<R: Read> Decoder<R> {
                                 self.decode_scan(&frame, &scan, worker, &finished)
                             })?;
 
-                        if let Some(data) = data {
+                        if let Some((data, data_u16)) = data {
                             for (i, plane) in data
                                 .into_iter()
                                 .enumerate()
@@ -472,6 +1249,15 @@ impl<R: Read> Decoder<R> {
                                     planes[i] = plane;
                                 }
                             }
+                            for (i, plane) in data_u16
+                                .into_iter()
+                                .enumerate()
+                                .filter(|(_, plane)| !plane.is_empty())
+                            {
+                                if self.coefficients_finished[i] == !0 {
+                                    planes_u16[i] = plane;
+                                }
+                            }
                         }
 
                         pending_marker = marker;
@@ -518,24 +1304,65 @@ impl<R: Read> Decoder<R> {
                 }
                 // Arithmetic conditioning table-specification
                 Marker::DAC => {
-                    return Err(Error::Unsupported(
-                        UnsupportedFeature::ArithmeticEntropyCoding,
-                    ))
+                    let (dc_tables, ac_tables) = parse_dac(&mut self.reader)?;
+
+                    for (i, table) in dc_tables.iter().enumerate() {
+                        if let Some(table) = table {
+                            self.dc_conditioning_tables[i] = *table;
+                        }
+                    }
+                    for (i, table) in ac_tables.iter().enumerate() {
+                        if let Some(table) = table {
+                            self.ac_conditioning_tables[i] = *table;
+                        }
+                    }
                 }
                 // Restart interval definition
                 Marker::DRI => self.restart_interval = parse_dri(&mut self.reader)?,
                 // Comment
                 Marker::COM => {
-                    let _comment = parse_com(&mut self.reader)?;
+                    let comment = parse_com(&mut self.reader)?;
+                    let available = self.comments_size_limit.saturating_sub(self.comments_size);
+
+                    if comment.len() <= available {
+                        self.comments_size += comment.len();
+                        self.comments.push(comment);
+                    }
                 }
                 // Application data
-                Marker::APP(..) => {
-                    if let Some(data) = parse_app(&mut self.reader, marker)? {
+                Marker::APP(number) => {
+                    let position = self.reader.position;
+                    let keep = match self.app_segment_filter {
+                        Some(ref filter) => filter(number),
+                        None => false,
+                    };
+
+                    let app_data = if keep {
+                        // Read the whole segment, including its length, to keep a copy of it.
+                        let mut segment = vec![0u8; 2];
+                        self.reader.read_exact(&mut segment)?;
+                        let length = usize::from(u16::from_be_bytes([segment[0], segment[1]]));
+                        segment.resize(cmp::max(length, 2), 0);
+                        self.reader.read_exact(&mut segment[2..])?;
+
+                        let app_data = parse_app(&mut &segment[..], marker)?;
+                        segment.drain(..2);
+                        self.app_segments.push(AppSegment {
+                            marker: number,
+                            offset: position - 2,
+                            data: segment,
+                        });
+                        app_data
+                    } else {
+                        parse_app(&mut self.reader, marker)?
+                    };
+
+                    if let Some(data) = app_data {
                         match data {
                             AppData::Adobe(color_transform) => {
                                 self.adobe_color_transform = Some(color_transform)
                             }
-                            AppData::Jfif => {
+                            AppData::Jfif(header, thumbnail) => {
                                 // From the JFIF spec:
                                 // "The APP0 marker is used to identify a JPEG FIF file.
                                 //     The JPEG FIF APP0 marker is mandatory right after the SOI marker."
@@ -547,13 +1374,40 @@ impl<R: Read> Decoder<R> {
                                 }
                                 */
 
-                                self.is_jfif = true;
+                                self.jfif_header = Some(header);
+                                self.jfif_thumbnails.extend(thumbnail);
                             }
+                            AppData::Jfxx(thumbnail) => self.jfif_thumbnails.push(thumbnail),
                             AppData::Avi1 => self.is_mjpeg = true,
                             AppData::Icc(icc) => self.icc_markers.push(icc),
-                            AppData::Exif(data) => self.exif_data = Some(data),
+                            AppData::Exif(data) => {
+                                self.orientation = Exif::parse(&data)
+                                    .ok()
+                                    .and_then(|exif| exif.orientation())
+                                    .and_then(Orientation::from_exif);
+                                self.exif_data = Some(data);
+                            }
                             AppData::Xmp(data) => self.xmp_data = Some(data),
-                            AppData::Psir(data) => self.psir_data = Some(data),
+                            AppData::ExtendedXmp(chunk) => self.extended_xmp_chunks.push(chunk),
+                            // Resources too large for one segment continue in the next one.
+                            AppData::Psir(data) => self
+                                .psir_data
+                                .get_or_insert_with(Vec::new)
+                                .extend_from_slice(&data),
+                            AppData::Jumbf(segment) => self.jumbf_segments.push(segment),
+                            AppData::Mpf(mut images) => {
+                                // CIPA DC-007 Section 5.2.3.3.3
+                                // The offsets are relative to the MP header, which follows the
+                                // segment length and the MPF identifier. The primary image has
+                                // an offset of zero.
+                                for image in images.iter_mut().filter(|image| image.offset != 0) {
+                                    image.offset += position + 6;
+                                }
+
+                                if self.mpf_images.is_none() {
+                                    self.mpf_images = Some(images);
+                                }
+                            }
                         }
                     }
                 }
@@ -574,16 +1428,90 @@ impl<R: Read> Decoder<R> {
                     // "If a DNL segment (see B.2.5) is present, it shall immediately follow the first scan."
                     if previous_marker != Marker::SOS || scans_processed != 1 {
                         return Err(Error::Format(
-                            "DNL is only allowed immediately after the first scan".to_owned(),
+                            "DNL is only allowed immediately after the first scan".to_owned(),
+                        ));
+                    }
+
+                    let height = parse_dnl(&mut self.reader)?;
+                    let frame = self.frame.as_mut().unwrap();
+
+                    // Like libjpeg, ignore the DNL segment if the frame header defines the height.
+                    if frame.image_size.height == 0 {
+                        frame.image_size.height = height;
+                        let idct_size = frame.components[0].dct_scale;
+                        frame.update_idct_size(idct_size)?;
+
+                        check_buffer_size(
+                            frame.components.len().checked_mul(
+                                usize::from(frame.image_size.width) * usize::from(height),
+                            ),
+                            self.decoding_buffer_size_limit,
+                        )?;
+
+                        // The first scan decoded as many rows as it contained, make them match
+                        // the defined number of lines.
+                        for (coefficients, component) in
+                            self.coefficients.iter_mut().zip(&frame.components)
+                        {
+                            let block_count = usize::from(component.block_size.width)
+                                * usize::from(component.block_size.height);
+                            coefficients.resize(block_count * 64, 0);
+                        }
+                        for plane in planes_u16.iter_mut().filter(|plane| !plane.is_empty()) {
+                            plane.resize(
+                                usize::from(frame.image_size.width) * usize::from(height),
+                                0,
+                            );
+                        }
+                    }
+                }
+
+                // JPEG-LS preset parameters, libjpeg 9 also uses them for its colour transform
+                Marker::LSE => match parse_lse(&mut self.reader, self.frame.as_ref())? {
+                    LseData::PresetParameters(parameters) => {
+                        self.jpeg_ls.preset_parameters = parameters
+                    }
+                    LseData::MappingTable {
+                        table_id,
+                        entry_width,
+                        entries,
+                        continuation,
+                    } => self.jpeg_ls.add_mapping_table(
+                        table_id,
+                        entry_width,
+                        entries,
+                        continuation,
+                    )?,
+                    LseData::SubtractGreen => self.subtract_green = true,
+                },
+
+                // Define hierarchical progression
+                Marker::DHP => {
+                    // Section B.3
+                    // "The DHP marker segment ... shall precede the first frame header."
+                    if self.frame.is_some() || self.hierarchical.is_some() {
+                        return Err(Error::Format(
+                            "DHP is only allowed before the first frame".to_owned(),
                         ));
                     }
 
-                    return Err(Error::Unsupported(UnsupportedFeature::DNL));
+                    let info = parse_dhp(&mut self.reader)?;
+                    self.hierarchical = Some(Hierarchical::new(info));
                 }
+                // Expand reference components
+                Marker::EXP => {
+                    let (horizontal, vertical) = parse_exp(&mut self.reader)?;
 
-                // Hierarchical mode markers
-                Marker::DHP | Marker::EXP => {
-                    return Err(Error::Unsupported(UnsupportedFeature::Hierarchical))
+                    match self.hierarchical {
+                        Some(ref mut hierarchical) if self.frame.is_some() => {
+                            hierarchical.set_expansion(horizontal, vertical)
+                        }
+                        _ => {
+                            return Err(Error::Format(
+                                "EXP found without reference frame".to_owned(),
+                            ))
+                        }
+                    }
                 }
 
                 // End of image
@@ -600,17 +1528,27 @@ impl<R: Read> Decoder<R> {
             previous_marker = marker;
         }
 
+        if self.hierarchical.is_some() {
+            self.finish_hierarchical_frame(planes_u16)?;
+            (planes, planes_u16) = self.hierarchical_planes()?;
+        }
+
         if self.frame.is_none() {
             return Err(Error::Format(
                 "end of image encountered before frame".to_owned(),
             ));
         }
+        if self.frame.as_ref().unwrap().image_size.height == 0 {
+            return Err(Error::Format(
+                "end of image encountered before DNL segment".to_owned(),
+            ));
+        }
 
         let frame = self.frame.as_ref().unwrap();
         let preference = Self::select_worker(frame, PreferWorkerKind::Multithreaded);
 
         worker_scope.get_or_init_worker(preference, |worker| {
-            self.decode_planes(worker, planes, planes_u16)
+            self.decode_planes(worker, planes, planes_u16, planar)
         })
     }
 
@@ -618,8 +1556,9 @@ impl<R: Read> Decoder<R> {
         &mut self,
         worker: &mut dyn Worker,
         mut planes: Vec<Vec<u8>>,
-        planes_u16: Vec<Vec<u16>>,
-    ) -> Result<Vec<u8>> {
+        mut planes_u16: Vec<Vec<u16>>,
+        planar: bool,
+    ) -> Result<Vec<Vec<u8>>> {
         if self.frame.is_none() {
             return Err(Error::Format(
                 "end of image encountered before frame".to_owned(),
@@ -628,20 +1567,29 @@ impl<R: Read> Decoder<R> {
 
         let frame = self.frame.as_ref().unwrap();
 
-        if frame
-            .components
-            .len()
-            .checked_mul(frame.output_size.width.into())
-            .and_then(|m| m.checked_mul(frame.output_size.height.into()))
-            .map_or(true, |m| self.decoding_buffer_size_limit < m)
-        {
-            return Err(Error::Format(
-                "size of decoded image exceeds maximum allowed size".to_owned(),
-            ));
-        }
+        // ITU-T T.87 Section C.2.4.1.2
+        // The samples of JPEG-LS components that select a mapping table are indices into it.
+        let mapped_frame;
+        let frame = match self.jpeg_ls.mapped_frame(frame) {
+            Some(mapped) => {
+                planes_u16 = self.jpeg_ls.map_planes(planes_u16)?;
+                mapped_frame = mapped;
+                &mapped_frame
+            }
+            None => frame,
+        };
 
-        // If we're decoding a progressive jpeg and a component is unfinished, render what we've got
-        if frame.coding_process == CodingProcess::DctProgressive
+        check_buffer_size(
+            frame
+                .components
+                .len()
+                .checked_mul(frame.output_size.width.into())
+                .and_then(|m| m.checked_mul(frame.output_size.height.into())),
+            self.decoding_buffer_size_limit,
+        )?;
+
+        // If we kept the coefficients of a component that is unfinished, render what we've got
+        if frame.coding_process != CodingProcess::Lossless
             && self.coefficients.len() == frame.components.len()
         {
             for (i, component) in frame.components.iter().enumerate() {
@@ -661,6 +1609,7 @@ impl<R: Read> Decoder<R> {
                     index: i,
                     component: component.clone(),
                     quantization_table,
+                    precision: frame.precision,
                 };
                 worker.start(row_data)?;
 
@@ -679,20 +1628,74 @@ impl<R: Read> Decoder<R> {
                 // FIXME: additional potential work stealing opportunities for rayon case if we
                 // also internally can parallelize over components.
                 worker.append_rows(&mut tasks)?;
-                planes[i] = worker.get_result(i)?;
+                if frame.precision == 8 {
+                    planes[i] = worker.get_result(i)?;
+                } else {
+                    planes_u16[i] = worker.get_result_u16(i)?;
+                }
             }
         }
 
-        if frame.coding_process == CodingProcess::Lossless {
-            compute_image_lossless(frame, planes_u16)
-        } else {
-            compute_image(
+        if planar {
+            return if frame.coding_process == CodingProcess::Lossless {
+                compute_planes_lossless(frame, planes_u16)
+            } else if frame.precision == 8 {
+                compute_planes(&frame.components, planes, frame.output_size)
+            } else {
+                let planes = compute_planes(&frame.components, planes_u16, frame.output_size)?;
+                Ok(planes
+                    .iter()
+                    .map(|plane| {
+                        plane
+                            .iter()
+                            .flat_map(|sample| sample.to_ne_bytes())
+                            .collect()
+                    })
+                    .collect())
+            };
+        }
+
+        let color_transform = self.determine_color_transform();
+        // Like libjpeg, the colour transform of the LSE segment is only undone for RGB output.
+        let subtract_green = self.subtract_green
+            && matches!(
+                color_transform,
+                ColorTransform::RGB | ColorTransform::JcsBgRgb
+            );
+
+        let image = if frame.coding_process == CodingProcess::Lossless {
+            compute_image_lossless(frame, planes_u16)?
+        } else if frame.precision == 8 {
+            let mut image = compute_image(
                 &frame.components,
                 planes,
                 frame.output_size,
-                self.determine_color_transform(),
-            )
-        }
+                color_transform,
+                self.line_layout(),
+            )?;
+            if subtract_green {
+                add_green(&mut image, frame.precision);
+            }
+            image
+        } else {
+            let mut image = compute_image(
+                &frame.components,
+                planes_u16,
+                frame.output_size,
+                color_transform,
+                OutputLayout::Native,
+            )?;
+            if subtract_green {
+                add_green(&mut image, frame.precision);
+            }
+            // we output native endian, like the lossless decoding process
+            image
+                .iter()
+                .flat_map(|sample| sample.to_ne_bytes())
+                .collect()
+        };
+
+        Ok(vec![image])
     }
 
     fn determine_color_transform(&self) -> ColorTransform {
@@ -706,6 +1709,11 @@ impl<R: Read> Decoder<R> {
             return ColorTransform::Grayscale;
         }
 
+        // The components of other images need not be colours, they are returned as they are.
+        if frame.components.len() != 3 && frame.components.len() != 4 {
+            return ColorTransform::None;
+        }
+
         // Using logic for determining colour as described here: https://entropymine.wordpress.com/2018/10/22/how-is-a-jpeg-images-color-type-determined/
 
         if frame.components.len() == 3 {
@@ -729,7 +1737,7 @@ impl<R: Read> Decoder<R> {
                 _ => {}
             }
 
-            if self.is_jfif {
+            if self.jfif_header.is_some() {
                 return ColorTransform::YCbCr;
             }
         }
@@ -796,9 +1804,9 @@ impl<R: Read> Decoder<R> {
         frame: &FrameInfo,
         scan: &ScanInfo,
         worker: &mut dyn Worker,
-        finished: &[bool; MAX_COMPONENTS],
-    ) -> Result<(Option<Marker>, Option<Vec<Vec<u8>>>)> {
-        assert!(scan.component_indices.len() <= MAX_COMPONENTS);
+        finished: &[bool; MAX_SCAN_COMPONENTS],
+    ) -> Result<(Option<Marker>, Option<(Vec<Vec<u8>>, Vec<Vec<u16>>)>)> {
+        assert!(scan.component_indices.len() <= MAX_SCAN_COMPONENTS);
 
         let components: Vec<Component> = scan
             .component_indices
@@ -814,7 +1822,9 @@ impl<R: Read> Decoder<R> {
             return Err(Error::Format("use of unset quantization table".to_owned()));
         }
 
-        if self.is_mjpeg {
+        let is_arithmetic = frame.entropy_coding == EntropyCoding::Arithmetic;
+
+        if self.is_mjpeg && !is_arithmetic {
             fill_default_mjpeg_tables(
                 scan,
                 &mut self.dc_huffman_tables,
@@ -823,7 +1833,8 @@ impl<R: Read> Decoder<R> {
         }
 
         // Verify that all required huffman tables has been set.
-        if scan.spectral_selection.start == 0
+        if !is_arithmetic
+            && scan.spectral_selection.start == 0
             && scan
                 .dc_table_indices
                 .iter()
@@ -833,7 +1844,8 @@ impl<R: Read> Decoder<R> {
                 "scan makes use of unset dc huffman table".to_owned(),
             ));
         }
-        if scan.spectral_selection.end > 1
+        if !is_arithmetic
+            && scan.spectral_selection.end > 1
             && scan
                 .ac_table_indices
                 .iter()
@@ -854,23 +1866,26 @@ impl<R: Read> Decoder<R> {
                         [component.quantization_table_index]
                         .clone()
                         .unwrap(),
+                    precision: frame.precision,
                 };
 
                 worker.start(row_data)?;
             }
         }
 
-        let is_progressive = frame.coding_process == CodingProcess::DctProgressive;
+        let keeps_coefficients = self.keeps_coefficients(frame);
+        let is_height_unknown = frame.image_size.height == 0;
         let is_interleaved = components.len() > 1;
         let mut dummy_block = [0i16; 64];
         let mut huffman = HuffmanDecoder::new();
-        let mut dc_predictors = [0i16; MAX_COMPONENTS];
+        let mut arithmetic = ArithmeticDecoder::new();
+        let mut dc_predictors = [0i16; MAX_SCAN_COMPONENTS];
         let mut mcus_left_until_restart = self.restart_interval;
         let mut expected_rst_num = 0;
         let mut eob_run = 0;
         let mut mcu_row_coefficients = vec![vec![]; components.len()];
 
-        if !is_progressive {
+        if !keeps_coefficients {
             for (i, component) in components.iter().enumerate().filter(|&(i, _)| finished[i]) {
                 let coefficients_per_mcu_row = component.block_size.width as usize
                     * component.vertical_sampling_factor as usize
@@ -898,17 +1913,57 @@ impl<R: Read> Decoder<R> {
 
         // This also affects how many MCU values we read from stream. If it's a non-interleaved stream,
         // the MCUs will be exactly the block count.
-        let (max_mcu_x, max_mcu_y) = if is_interleaved {
+        // Section A.2.2
+        // The blocks of a non-interleaved scan only cover the samples of the component, which can
+        // be fewer than the blocks of its MCUs when sampling factors are not powers of two.
+        let (max_mcu_x, mut max_mcu_y) = if is_interleaved {
             (frame.mcu_size.width, frame.mcu_size.height)
         } else {
+            let dct_scale = components[0].dct_scale as u32;
+            let blocks = |samples: u16| ((u32::from(samples) + dct_scale - 1) / dct_scale) as u16;
             (
-                components[0].block_size.width,
-                components[0].block_size.height,
+                blocks(components[0].size.width),
+                blocks(components[0].size.height),
             )
         };
 
+        // Section B.2.5
+        // Without a known height the scan continues until the DNL marker follows, up to the
+        // largest height the DNL segment can define.
+        if is_height_unknown {
+            let mut largest_frame = frame.clone();
+            largest_frame.image_size.height = u16::MAX;
+            largest_frame.update_idct_size(components[0].dct_scale)?;
+
+            max_mcu_y = if is_interleaved {
+                largest_frame.mcu_size.height
+            } else {
+                largest_frame.components[scan.component_indices[0]]
+                    .block_size
+                    .height
+            };
+        }
+
         for mcu_y in 0..max_mcu_y {
-            if mcu_y * 8 >= frame.image_size.height {
+            if is_height_unknown {
+                if mcu_y > 0 && huffman.is_end_of_data(&mut self.reader)? {
+                    break;
+                }
+
+                // Add a row of MCUs to the coefficients, which must stay within the limit of the
+                // decoded image as they grow.
+                for (i, component) in components.iter().enumerate() {
+                    let rows = usize::from(mcu_vertical_samples[i]);
+                    let coefficients = &mut self.coefficients[scan.component_indices[i]];
+                    let length =
+                        coefficients.len() + usize::from(component.block_size.width) * rows * 64;
+                    coefficients.resize(length, 0);
+                }
+                check_buffer_size(
+                    Some(self.coefficients.iter().map(Vec::len).sum()),
+                    self.decoding_buffer_size_limit,
+                )?;
+            } else if mcu_y * 8 >= frame.image_size.height {
                 break;
             }
 
@@ -919,7 +1974,13 @@ impl<R: Read> Decoder<R> {
 
                 if self.restart_interval > 0 {
                     if mcus_left_until_restart == 0 {
-                        match huffman.take_marker(&mut self.reader)? {
+                        let marker = if is_arithmetic {
+                            arithmetic.take_marker(&mut self.reader)?
+                        } else {
+                            huffman.take_marker(&mut self.reader)?
+                        };
+
+                        match marker {
                             Some(Marker::RST(n)) => {
                                 if n != expected_rst_num {
                                     return Err(Error::Format(format!(
@@ -929,8 +1990,10 @@ impl<R: Read> Decoder<R> {
                                 }
 
                                 huffman.reset();
+                                // Section F.1.4.4.1.1 and F.1.4.4.2
+                                arithmetic.reset();
                                 // Section F.2.1.3.1
-                                dc_predictors = [0i16; MAX_COMPONENTS];
+                                dc_predictors = [0i16; MAX_SCAN_COMPONENTS];
                                 // Section G.1.2.2
                                 eob_run = 0;
 
@@ -958,7 +2021,7 @@ impl<R: Read> Decoder<R> {
                 for (i, component) in components.iter().enumerate() {
                     for v_pos in 0..mcu_vertical_samples[i] {
                         for h_pos in 0..mcu_horizontal_samples[i] {
-                            let coefficients = if is_progressive {
+                            let coefficients = if keeps_coefficients {
                                 let block_y = (mcu_y * mcu_vertical_samples[i] + v_pos) as usize;
                                 let block_x = (mcu_x * mcu_horizontal_samples[i] + h_pos) as usize;
                                 let block_offset =
@@ -987,7 +2050,30 @@ impl<R: Read> Decoder<R> {
                             .try_into()
                             .unwrap();
 
-                            if scan.successive_approximation_high == 0 {
+                            if is_arithmetic {
+                                if scan.successive_approximation_high == 0 {
+                                    arithmetic.decode_block(
+                                        &mut self.reader,
+                                        coefficients,
+                                        i,
+                                        scan.dc_table_indices[i],
+                                        scan.ac_table_indices[i],
+                                        self.dc_conditioning_tables[scan.dc_table_indices[i]],
+                                        self.ac_conditioning_tables[scan.ac_table_indices[i]],
+                                        scan.spectral_selection.clone(),
+                                        scan.successive_approximation_low,
+                                        &mut dc_predictors[i],
+                                    )?;
+                                } else {
+                                    arithmetic.decode_block_successive_approximation(
+                                        &mut self.reader,
+                                        coefficients,
+                                        scan.ac_table_indices[i],
+                                        scan.spectral_selection.clone(),
+                                        scan.successive_approximation_low,
+                                    )?;
+                                }
+                            } else if scan.successive_approximation_high == 0 {
                                 decode_block(
                                     &mut self.reader,
                                     coefficients,
@@ -998,6 +2084,7 @@ impl<R: Read> Decoder<R> {
                                     scan.successive_approximation_low,
                                     &mut eob_run,
                                     &mut dc_predictors[i],
+                                    frame.precision,
                                 )?;
                             } else {
                                 decode_block_successive_approximation(
@@ -1022,7 +2109,7 @@ impl<R: Read> Decoder<R> {
                     // keep going; don't send it yet. We also need to ensure we don't skip over the last
                     // row(s) of the image.
                     if !is_interleaved
-                        && (mcu_y + 1) * 8 < frame.image_size.height
+                        && mcu_y + 1 < max_mcu_y
                         && (mcu_y + 1) % component.vertical_sampling_factor as u16 > 0
                     {
                         continue;
@@ -1032,7 +2119,7 @@ impl<R: Read> Decoder<R> {
                         * component.vertical_sampling_factor as usize
                         * 64;
 
-                    let row_coefficients = if is_progressive {
+                    let row_coefficients = if keeps_coefficients {
                         // Because non-interleaved streams will have multiple MCU rows concatenated together,
                         // the row for calculating the offset is different.
                         let worker_mcu_y = if is_interleaved {
@@ -1060,7 +2147,11 @@ impl<R: Read> Decoder<R> {
             }
         }
 
-        let mut marker = huffman.take_marker(&mut self.reader)?;
+        let mut marker = if is_arithmetic {
+            arithmetic.take_marker(&mut self.reader)?
+        } else {
+            huffman.take_marker(&mut self.reader)?
+        };
         while let Some(Marker::RST(_)) = marker {
             marker = self.read_marker().ok();
         }
@@ -1068,20 +2159,86 @@ impl<R: Read> Decoder<R> {
         if finished.iter().any(|&c| c) {
             // Retrieve all the data from the worker thread.
             let mut data = vec![Vec::new(); frame.components.len()];
+            let mut data_u16 = vec![Vec::new(); frame.components.len()];
 
             for (i, &component_index) in scan.component_indices.iter().enumerate() {
-                if finished[i] {
+                if !finished[i] {
+                    continue;
+                }
+
+                if frame.precision == 8 {
                     data[component_index] = worker.get_result(i)?;
+                } else {
+                    data_u16[component_index] = worker.get_result_u16(i)?;
                 }
             }
 
-            Ok((marker, Some(data)))
+            Ok((marker, Some((data, data_u16))))
         } else {
             Ok((marker, None))
         }
     }
 }
 
+impl<R: Read + Seek> Decoder<R> {
+    /// Returns a decoder for an image listed by `mpf_images`, such as a large thumbnail, another
+    /// view of a stereo image or a gain map.
+    ///
+    /// The image is read from the same input, counting its offset from where the input was
+    /// positioned when this decoder was created. The input is returned to its previous position
+    /// afterwards, so this decoder can still be used.
+    pub fn mpf_image_decoder(&mut self, image: MpImage) -> Result<Decoder<Cursor<Vec<u8>>>> {
+        let reader = &mut self.reader;
+        let current = reader.inner.stream_position()?;
+        let start = match current.checked_sub(reader.position) {
+            Some(start) => start,
+            None => {
+                return Err(Error::Format(
+                    "input was moved after the decoder was created".to_owned(),
+                ))
+            }
+        };
+
+        reader.inner.seek(SeekFrom::Start(start + image.offset))?;
+        let mut data = Vec::new();
+        let result = (&mut reader.inner)
+            .take(image.size.into())
+            .read_to_end(&mut data);
+        reader.inner.seek(SeekFrom::Start(current))?;
+        result?;
+
+        if data.len() != image.size as usize {
+            return Err(Error::Format(
+                "MPF image extends beyond the end of the input".to_owned(),
+            ));
+        }
+
+        let mut decoder = Decoder::new(Cursor::new(data));
+        decoder.set_max_decoding_buffer_size(self.decoding_buffer_size_limit);
+        Ok(decoder)
+    }
+}
+
+// Counts the bytes read from the input, which locates the images of MPF files.
+struct PositionReader<R> {
+    inner: R,
+    position: u64,
+}
+
+impl<R: Read> Read for PositionReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.position += count as u64;
+        Ok(count)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.inner.read_exact(buf)?;
+        self.position += buf.len() as u64;
+        Ok(())
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 fn decode_block<R: Read>(
     reader: &mut R,
@@ -1093,6 +2250,7 @@ fn decode_block<R: Read>(
     successive_approximation_low: u8,
     eob_run: &mut u16,
     dc_predictor: &mut i16,
+    precision: u8,
 ) -> Result<()> {
     debug_assert_eq!(coefficients.len(), 64);
 
@@ -1103,6 +2261,8 @@ fn decode_block<R: Read>(
         let diff = match value {
             0 => 0,
             1..=11 => huffman.receive_extend(reader, value)?,
+            // Categories above 11 are only used by 12-bit frames.
+            12..=15 if precision > 8 => huffman.receive_extend(reader, value)?,
             _ => {
                 // Section F.1.2.1.1
                 // Table F.1
@@ -1297,19 +2457,32 @@ fn refine_non_zeroes<R: Read>(
     Ok(last)
 }
 
-fn compute_image(
+// Returns an error if buffers of `size` samples exceed the limit set by
+// `Decoder::set_max_decoding_buffer_size`, a size of `None` having overflowed.
+pub(crate) fn check_buffer_size(size: Option<usize>, limit: usize) -> Result<()> {
+    if size.map_or(true, |size| limit < size) {
+        return Err(Error::Format(
+            "size of decoded image exceeds maximum allowed size".to_owned(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn compute_image<T: Sample>(
     components: &[Component],
-    mut data: Vec<Vec<u8>>,
+    mut data: Vec<Vec<T>>,
     output_size: Dimensions,
     color_transform: ColorTransform,
-) -> Result<Vec<u8>> {
+    layout: OutputLayout,
+) -> Result<Vec<T>> {
     if data.is_empty() || data.iter().any(Vec::is_empty) {
         return Err(Error::Format("not all components have data".to_owned()));
     }
 
     if components.len() == 1 {
         let component = &components[0];
-        let mut decoded: Vec<u8> = data.remove(0);
+        let mut decoded: Vec<T> = data.remove(0);
 
         let width = component.size.width as usize;
         let height = component.size.height as usize;
@@ -1328,18 +2501,53 @@ fn compute_image(
                 decoded.copy_within(source_idx..end, destination_idx);
             }
         }
-        decoded.resize(size, 0);
+        decoded.resize(size, T::default());
         Ok(decoded)
     } else {
-        compute_image_parallel(components, data, output_size, color_transform)
+        compute_image_parallel(components, data, output_size, color_transform, layout)
+    }
+}
+
+fn compute_planes<T: Sample>(
+    components: &[Component],
+    data: Vec<Vec<T>>,
+    output_size: Dimensions,
+) -> Result<Vec<Vec<T>>> {
+    if data.is_empty() || data.iter().any(Vec::is_empty) {
+        return Err(Error::Format("not all components have data".to_owned()));
     }
+
+    let upsampler = Upsampler::new(components, output_size.width, output_size.height);
+
+    Ok(data
+        .iter()
+        .enumerate()
+        .map(|(i, plane)| {
+            upsampler.upsample_component(
+                i,
+                plane,
+                output_size.width as usize,
+                output_size.height as usize,
+            )
+        })
+        .collect())
 }
 
 #[allow(clippy::type_complexity)]
 pub(crate) fn choose_color_convert_func(
     component_count: usize,
     color_transform: ColorTransform,
+    layout: OutputLayout,
 ) -> Result<fn(&[Vec<u8>], &mut [u8])> {
+    match layout {
+        OutputLayout::Native => {}
+        OutputLayout::Rgba => return choose_layout_func::<layout::Rgba>(color_transform),
+        OutputLayout::Bgr => return choose_layout_func::<layout::Bgr>(color_transform),
+        OutputLayout::Bgra => return choose_layout_func::<layout::Bgra>(color_transform),
+        OutputLayout::Argb => return choose_layout_func::<layout::Argb>(color_transform),
+        OutputLayout::Luma => return choose_layout_func::<layout::Luma>(color_transform),
+    }
+
     match component_count {
         3 => match color_transform {
             ColorTransform::None => Ok(color_no_convert),
@@ -1347,19 +2555,15 @@ pub(crate) fn choose_color_convert_func(
                 "Invalid number of channels (3) for Grayscale data".to_string(),
             )),
             ColorTransform::RGB => Ok(color_convert_line_rgb),
-            ColorTransform::YCbCr => Ok(color_convert_line_ycbcr),
+            ColorTransform::YCbCr => Ok(color_convert_line_ycbcr::<layout::Rgb>),
             ColorTransform::CMYK => Err(Error::Format(
                 "Invalid number of channels (3) for CMYK data".to_string(),
             )),
             ColorTransform::YCCK => Err(Error::Format(
                 "Invalid number of channels (3) for YCCK data".to_string(),
             )),
-            ColorTransform::JcsBgYcc => Err(Error::Unsupported(
-                UnsupportedFeature::ColorTransform(ColorTransform::JcsBgYcc),
-            )),
-            ColorTransform::JcsBgRgb => Err(Error::Unsupported(
-                UnsupportedFeature::ColorTransform(ColorTransform::JcsBgRgb),
-            )),
+            ColorTransform::JcsBgYcc => Ok(color_convert_line_bg_ycc::<layout::Rgb>),
+            ColorTransform::JcsBgRgb => Ok(color_convert_line_rgb),
             ColorTransform::Unknown => Err(Error::Format("Unknown colour transform".to_string())),
         },
         4 => match color_transform {
@@ -1375,22 +2579,74 @@ pub(crate) fn choose_color_convert_func(
             )),
             ColorTransform::CMYK => Ok(color_convert_line_cmyk),
             ColorTransform::YCCK => Ok(color_convert_line_ycck),
-
-            ColorTransform::JcsBgYcc => Err(Error::Unsupported(
-                UnsupportedFeature::ColorTransform(ColorTransform::JcsBgYcc),
+            ColorTransform::JcsBgYcc => Err(Error::Format(
+                "Invalid number of channels (4) for bg-sYCC data".to_string(),
             )),
-            ColorTransform::JcsBgRgb => Err(Error::Unsupported(
-                UnsupportedFeature::ColorTransform(ColorTransform::JcsBgRgb),
+            ColorTransform::JcsBgRgb => Err(Error::Format(
+                "Invalid number of channels (4) for bg-sRGB data".to_string(),
             )),
             ColorTransform::Unknown => Err(Error::Format("Unknown colour transform".to_string())),
         },
-        _ => panic!(),
+        _ => match color_transform {
+            ColorTransform::None => Ok(color_no_convert),
+            _ => Err(Error::Format(format!(
+                "Invalid number of channels ({}) for {:?} data",
+                component_count, color_transform
+            ))),
+        },
+    }
+}
+
+// Returns the line function of a colour transform writing 8-bit RGB or YCbCr lines in the layout
+// of `L`, see `Decoder::line_layout`.
+#[allow(clippy::type_complexity)]
+fn choose_layout_func<L: LineLayout>(
+    color_transform: ColorTransform,
+) -> Result<fn(&[Vec<u8>], &mut [u8])> {
+    match color_transform {
+        ColorTransform::RGB | ColorTransform::JcsBgRgb => Ok(color_arrange_line_rgb::<L>),
+        ColorTransform::YCbCr => Ok(color_convert_line_ycbcr::<L>),
+        ColorTransform::JcsBgYcc => Ok(color_convert_line_bg_ycc::<L>),
+        _ => Err(Error::Format(format!(
+            "{:?} output layout for {:?} data",
+            L::LAYOUT,
+            color_transform
+        ))),
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn choose_color_convert_func_12bit(
+    component_count: usize,
+    color_transform: ColorTransform,
+    layout: OutputLayout,
+) -> Result<fn(&[Vec<u16>], &mut [u16])> {
+    // The same transforms are supported as for 8-bit samples.
+    choose_color_convert_func(component_count, color_transform, OutputLayout::Native)?;
+
+    // Pixels of more than 8 bits are arranged after decoding, see `Decoder::arrange`.
+    if layout != OutputLayout::Native {
+        return Err(Error::Format(format!(
+            "{:?} output layout for 12-bit data",
+            layout
+        )));
+    }
+
+    match color_transform {
+        ColorTransform::None => Ok(color_no_convert),
+        ColorTransform::RGB => Ok(color_convert_line_rgb),
+        ColorTransform::YCbCr => Ok(color_convert_line_ycbcr_12bit),
+        ColorTransform::CMYK => Ok(color_convert_line_cmyk_12bit),
+        ColorTransform::YCCK => Ok(color_convert_line_ycck_12bit),
+        ColorTransform::JcsBgYcc => Ok(color_convert_line_bg_ycc_12bit),
+        ColorTransform::JcsBgRgb => Ok(color_convert_line_rgb),
+        _ => unreachable!(),
     }
 }
 
-fn color_convert_line_rgb(data: &[Vec<u8>], output: &mut [u8]) {
+fn color_convert_line_rgb<T: Copy>(data: &[Vec<T>], output: &mut [T]) {
     assert!(data.len() == 3, "wrong number of components for rgb");
-    let [r, g, b]: &[Vec<u8>; 3] = data.try_into().unwrap();
+    let [r, g, b]: &[Vec<T>; 3] = data.try_into().unwrap();
     for (((chunk, r), g), b) in output
         .chunks_exact_mut(3)
         .zip(r.iter())
@@ -1403,16 +2659,38 @@ fn color_convert_line_rgb(data: &[Vec<u8>], output: &mut [u8]) {
     }
 }
 
-fn color_convert_line_ycbcr(data: &[Vec<u8>], output: &mut [u8]) {
+fn color_arrange_line_rgb<L: LineLayout>(data: &[Vec<u8>], output: &mut [u8]) {
+    assert!(data.len() == 3, "wrong number of components for rgb");
+    let [r, g, b]: &[Vec<u8>; 3] = data.try_into().unwrap();
+
+    for (((chunk, r), g), b) in output
+        .chunks_exact_mut(L::LAYOUT.samples_per_pixel(3))
+        .zip(r.iter())
+        .zip(g.iter())
+        .zip(b.iter())
+    {
+        L::LAYOUT.write_pixel(chunk, [*r, *g, *b], 255);
+    }
+}
+
+fn color_convert_line_ycbcr<L: LineLayout>(data: &[Vec<u8>], output: &mut [u8]) {
     assert!(data.len() == 3, "wrong number of components for ycbcr");
     let [y, cb, cr]: &[_; 3] = data.try_into().unwrap();
 
+    // The luma is the Y component.
+    if L::LAYOUT == OutputLayout::Luma {
+        for (output, y) in output.iter_mut().zip(y.iter()) {
+            *output = *y;
+        }
+        return;
+    }
+
     #[cfg(not(feature = "platform_independent"))]
     let arch_specific_pixels = {
         if let Some(ycbcr) = crate::arch::get_color_convert_line_ycbcr() {
             #[allow(unsafe_code)]
             unsafe {
-                ycbcr(y, cb, cr, output)
+                ycbcr(y, cb, cr, output, L::LAYOUT)
             }
         } else {
             0
@@ -1423,16 +2701,53 @@ fn color_convert_line_ycbcr(data: &[Vec<u8>], output: &mut [u8]) {
     let arch_specific_pixels = 0;
 
     for (((chunk, y), cb), cr) in output
-        .chunks_exact_mut(3)
+        .chunks_exact_mut(L::LAYOUT.samples_per_pixel(3))
         .zip(y.iter())
         .zip(cb.iter())
         .zip(cr.iter())
         .skip(arch_specific_pixels)
     {
         let (r, g, b) = ycbcr_to_rgb(*y, *cb, *cr);
-        chunk[0] = r;
-        chunk[1] = g;
-        chunk[2] = b;
+        L::LAYOUT.write_pixel(chunk, [r, g, b], 255);
+    }
+}
+
+fn color_convert_line_bg_ycc<L: LineLayout>(data: &[Vec<u8>], output: &mut [u8]) {
+    assert!(data.len() == 3, "wrong number of components for bg-sycc");
+    let [y, cb, cr]: &[_; 3] = data.try_into().unwrap();
+
+    // bg-sYCC has the luma of YCbCr, only its chroma is scaled.
+    if L::LAYOUT == OutputLayout::Luma {
+        for (output, y) in output.iter_mut().zip(y.iter()) {
+            *output = *y;
+        }
+        return;
+    }
+
+    #[cfg(not(feature = "platform_independent"))]
+    let arch_specific_pixels = {
+        if let Some(bg_ycc) = crate::arch::get_color_convert_line_bg_ycc() {
+            #[allow(unsafe_code)]
+            unsafe {
+                bg_ycc(y, cb, cr, output, L::LAYOUT)
+            }
+        } else {
+            0
+        }
+    };
+
+    #[cfg(feature = "platform_independent")]
+    let arch_specific_pixels = 0;
+
+    for (((chunk, y), cb), cr) in output
+        .chunks_exact_mut(L::LAYOUT.samples_per_pixel(3))
+        .zip(y.iter())
+        .zip(cb.iter())
+        .zip(cr.iter())
+        .skip(arch_specific_pixels)
+    {
+        let (r, g, b) = bg_ycc_to_rgb(*y, *cb, *cr);
+        L::LAYOUT.write_pixel(chunk, [r, g, b], 255);
     }
 }
 
@@ -1455,6 +2770,77 @@ fn color_convert_line_ycck(data: &[Vec<u8>], output: &mut [u8]) {
     }
 }
 
+fn color_convert_line_ycbcr_12bit(data: &[Vec<u16>], output: &mut [u16]) {
+    assert!(data.len() == 3, "wrong number of components for ycbcr");
+    let [y, cb, cr]: &[_; 3] = data.try_into().unwrap();
+
+    for (((chunk, y), cb), cr) in output
+        .chunks_exact_mut(3)
+        .zip(y.iter())
+        .zip(cb.iter())
+        .zip(cr.iter())
+    {
+        let (r, g, b) = ycbcr_to_rgb_12bit(*y, *cb, *cr);
+        chunk[0] = r;
+        chunk[1] = g;
+        chunk[2] = b;
+    }
+}
+
+fn color_convert_line_bg_ycc_12bit(data: &[Vec<u16>], output: &mut [u16]) {
+    assert!(data.len() == 3, "wrong number of components for bg-sycc");
+    let [y, cb, cr]: &[_; 3] = data.try_into().unwrap();
+
+    for (((chunk, y), cb), cr) in output
+        .chunks_exact_mut(3)
+        .zip(y.iter())
+        .zip(cb.iter())
+        .zip(cr.iter())
+    {
+        let (r, g, b) = bg_ycc_to_rgb_12bit(*y, *cb, *cr);
+        chunk[0] = r;
+        chunk[1] = g;
+        chunk[2] = b;
+    }
+}
+
+fn color_convert_line_ycck_12bit(data: &[Vec<u16>], output: &mut [u16]) {
+    assert!(data.len() == 4, "wrong number of components for ycck");
+    let [c, m, y, k]: &[Vec<u16>; 4] = data.try_into().unwrap();
+
+    for ((((chunk, c), m), y), k) in output
+        .chunks_exact_mut(4)
+        .zip(c.iter())
+        .zip(m.iter())
+        .zip(y.iter())
+        .zip(k.iter())
+    {
+        let (r, g, b) = ycbcr_to_rgb_12bit(*c, *m, *y);
+        chunk[0] = r;
+        chunk[1] = g;
+        chunk[2] = b;
+        chunk[3] = 4095 - *k;
+    }
+}
+
+fn color_convert_line_cmyk_12bit(data: &[Vec<u16>], output: &mut [u16]) {
+    assert!(data.len() == 4, "wrong number of components for cmyk");
+    let [c, m, y, k]: &[Vec<u16>; 4] = data.try_into().unwrap();
+
+    for ((((chunk, c), m), y), k) in output
+        .chunks_exact_mut(4)
+        .zip(c.iter())
+        .zip(m.iter())
+        .zip(y.iter())
+        .zip(k.iter())
+    {
+        chunk[0] = 4095 - c;
+        chunk[1] = 4095 - m;
+        chunk[2] = 4095 - y;
+        chunk[3] = 4095 - k;
+    }
+}
+
 fn color_convert_line_cmyk(data: &[Vec<u8>], output: &mut [u8]) {
     assert!(data.len() == 4, "wrong number of components for cmyk");
     let [c, m, y, k]: &[Vec<u8>; 4] = data.try_into().unwrap();
@@ -1473,12 +2859,79 @@ fn color_convert_line_cmyk(data: &[Vec<u8>], output: &mut [u8]) {
     }
 }
 
-fn color_no_convert(data: &[Vec<u8>], output: &mut [u8]) {
-    let mut output_iter = output.iter_mut();
+// Converts CMYK samples, zero being no ink, to RGB without colour management.
+fn cmyk_to_rgb(data: &[u8], sample_max: u16) -> Vec<u8> {
+    let max = u32::from(sample_max);
+    // (1 - c) * (1 - k) scaled to the samples
+    let convert = |c: u32, k: u32| ((max - c.min(max)) * (max - k.min(max)) + max / 2) / max;
+
+    if sample_max <= 255 {
+        data.chunks_exact(4)
+            .flat_map(|pixel| {
+                let k = u32::from(pixel[3]);
+                [0, 1, 2].map(|i| convert(u32::from(pixel[i]), k) as u8)
+            })
+            .collect()
+    } else {
+        let samples: Vec<u16> = data
+            .chunks_exact(2)
+            .map(|sample| u16::from_ne_bytes([sample[0], sample[1]]))
+            .collect();
+        samples
+            .chunks_exact(4)
+            .flat_map(|pixel| {
+                let k = u32::from(pixel[3]);
+                [0, 1, 2].map(|i| convert(u32::from(pixel[i]), k) as u16)
+            })
+            .flat_map(u16::to_ne_bytes)
+            .collect()
+    }
+}
+
+fn invert_samples(data: &mut [u8], sample_max: u16) {
+    if sample_max <= 255 {
+        for sample in data {
+            *sample = (sample_max as u8).saturating_sub(*sample);
+        }
+    } else {
+        for sample in data.chunks_exact_mut(2) {
+            let value = u16::from_ne_bytes([sample[0], sample[1]]);
+            sample.copy_from_slice(&sample_max.saturating_sub(value).to_ne_bytes());
+        }
+    }
+}
+
+// Returns the size in bytes of the samples of the pixel format.
+fn sample_size(pixel_format: PixelFormat) -> usize {
+    match pixel_format {
+        PixelFormat::L16
+        | PixelFormat::RGB48
+        | PixelFormat::CMYK64
+        | PixelFormat::Channels16(_)
+        | PixelFormat::RGBA64
+        | PixelFormat::BGR48
+        | PixelFormat::BGRA64
+        | PixelFormat::ARGB64 => 2,
+        _ => 1,
+    }
+}
+
+// Undoes the reversible colour transform of libjpeg 9, based on its jdcolor.c.
+fn add_green<T: Sample>(image: &mut [T], precision: u8) {
+    let mask = (1u32 << precision) - 1;
+    let center = 1u32 << (precision - 1);
+
+    for pixel in image.chunks_exact_mut(3) {
+        let green = pixel[1].to_u32();
+        pixel[0] = T::from_u32((pixel[0].to_u32() + green + center) & mask);
+        pixel[2] = T::from_u32((pixel[2].to_u32() + green + center) & mask);
+    }
+}
 
-    for pixel in data {
-        for d in pixel {
-            *(output_iter.next().unwrap()) = *d;
+fn color_no_convert<T: Copy>(data: &[Vec<T>], output: &mut [T]) {
+    for (i, pixel) in output.chunks_exact_mut(data.len()).enumerate() {
+        for (sample, component) in pixel.iter_mut().zip(data) {
+            *sample = component[i];
         }
     }
 }
@@ -1499,6 +2952,45 @@ fn ycbcr_to_rgb(y: u8, cb: u8, cr: u8) -> (u8, u8, u8) {
     (r, g, b)
 }
 
+// The same conversion for 12-bit samples, which need more than 32 bits in fixed point.
+fn ycbcr_to_rgb_12bit(y: u16, cb: u16, cr: u16) -> (u16, u16, u16) {
+    let y = i64::from(y) * (1 << FIXED_POINT_OFFSET) + i64::from(HALF);
+    let cb = i64::from(cb) - 2048;
+    let cr = i64::from(cr) - 2048;
+    let clamp = |value: i64| (value >> FIXED_POINT_OFFSET).clamp(0, 4095) as u16;
+
+    let r = clamp(y + i64::from(stbi_f2f(1.40200)) * cr);
+    let g = clamp(y - i64::from(stbi_f2f(0.34414)) * cb - i64::from(stbi_f2f(0.71414)) * cr);
+    let b = clamp(y + i64::from(stbi_f2f(1.77200)) * cb);
+    (r, g, b)
+}
+
+// bg-sYCC stores the chroma at half the scale of sYCC to cover a wider gamut, so the
+// coefficients are doubled.
+// Based on libjpeg 9's jdcolor.c
+fn bg_ycc_to_rgb(y: u8, cb: u8, cr: u8) -> (u8, u8, u8) {
+    let y = y as i32 * (1 << FIXED_POINT_OFFSET) + HALF;
+    let cb = cb as i32 - 128;
+    let cr = cr as i32 - 128;
+
+    let r = clamp_fixed_point(y + stbi_f2f(2.80400) * cr);
+    let g = clamp_fixed_point(y - stbi_f2f(0.688272) * cb - stbi_f2f(1.428272) * cr);
+    let b = clamp_fixed_point(y + stbi_f2f(3.54400) * cb);
+    (r, g, b)
+}
+
+fn bg_ycc_to_rgb_12bit(y: u16, cb: u16, cr: u16) -> (u16, u16, u16) {
+    let y = i64::from(y) * (1 << FIXED_POINT_OFFSET) + i64::from(HALF);
+    let cb = i64::from(cb) - 2048;
+    let cr = i64::from(cr) - 2048;
+    let clamp = |value: i64| (value >> FIXED_POINT_OFFSET).clamp(0, 4095) as u16;
+
+    let r = clamp(y + i64::from(stbi_f2f(2.80400)) * cr);
+    let g = clamp(y - i64::from(stbi_f2f(0.688272)) * cb - i64::from(stbi_f2f(1.428272)) * cr);
+    let b = clamp(y + i64::from(stbi_f2f(3.54400)) * cb);
+    (r, g, b)
+}
+
 fn stbi_f2f(x: f32) -> i32 {
     (x * ((1 << FIXED_POINT_OFFSET) as f32) + 0.5) as i32
 }
@@ -0,0 +1,337 @@
+use crate::cms;
+use crate::decoder::{Decoder, ImageInfo, PixelFormat};
+use crate::error::{Error, Result};
+use crate::parser::MpImageType;
+use crate::xmp;
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::vec::Vec;
+use core::{mem, str};
+use std::io::{Read, Seek};
+
+/// Metadata of a gain map, as defined by the `hdrgm` XMP namespace of Ultra HDR images.
+///
+/// Gains and HDR capacities are log2 values. The per channel values are equal if the metadata
+/// gives a single value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GainMapMetadata {
+    /// Gain of a gain map sample of zero.
+    pub gain_map_min: [f32; 3],
+    /// Gain of a gain map sample of one.
+    pub gain_map_max: [f32; 3],
+    /// Gamma the gain map samples are encoded with.
+    pub gamma: [f32; 3],
+    /// Offset added to the SDR samples before the gain is applied.
+    pub offset_sdr: [f32; 3],
+    /// Offset added to the HDR samples before the gain is applied.
+    pub offset_hdr: [f32; 3],
+    /// Display boost below which the gain map is not applied.
+    pub hdr_capacity_min: f32,
+    /// Display boost at which the gain map is fully applied.
+    pub hdr_capacity_max: f32,
+    /// Whether the primary image is the HDR rendition and the gain map maps it to SDR.
+    pub base_rendition_is_hdr: bool,
+}
+
+impl GainMapMetadata {
+    /// Reads the metadata from the XMP packet of a gain map image.
+    ///
+    /// The properties are looked up with the prefix the packet declares for the
+    /// `http://ns.adobe.com/hdr-gain-map/1.0/` namespace, which is usually `hdrgm`. Returns `None`
+    /// if the packet does not hold the gain map metadata.
+    pub fn from_xmp(xmp: &[u8]) -> Option<GainMapMetadata> {
+        let xmp = str::from_utf8(xmp).ok()?;
+        let channels = |name: &str, default: Option<f32>| -> Option<[f32; 3]> {
+            match xmp::property(xmp, xmp::HDR_GAIN_MAP_NAMESPACE, name) {
+                Some(values) => {
+                    let values = values
+                        .iter()
+                        .map(|value| value.trim().parse().ok())
+                        .collect::<Option<Vec<f32>>>()?;
+                    match values[..] {
+                        [value] => Some([value; 3]),
+                        [red, green, blue] => Some([red, green, blue]),
+                        _ => None,
+                    }
+                }
+                None => default.map(|value| [value; 3]),
+            }
+        };
+
+        xmp::property(xmp, xmp::HDR_GAIN_MAP_NAMESPACE, "Version")?;
+
+        Some(GainMapMetadata {
+            gain_map_min: channels("GainMapMin", Some(0.0))?,
+            gain_map_max: channels("GainMapMax", None)?,
+            gamma: channels("Gamma", Some(1.0))?,
+            offset_sdr: channels("OffsetSDR", Some(1.0 / 64.0))?,
+            offset_hdr: channels("OffsetHDR", Some(1.0 / 64.0))?,
+            hdr_capacity_min: channels("HDRCapacityMin", Some(0.0))?[0],
+            hdr_capacity_max: channels("HDRCapacityMax", None)?[0],
+            base_rendition_is_hdr: match xmp::property(
+                xmp,
+                xmp::HDR_GAIN_MAP_NAMESPACE,
+                "BaseRenditionIsHDR",
+            ) {
+                Some(values) => values[0].trim().eq_ignore_ascii_case("true"),
+                None => false,
+            },
+        })
+    }
+}
+
+/// Sample format of HDR images reconstructed with a gain map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HdrFormat {
+    /// IEEE 754 half precision floating point, 2 bytes per sample
+    F16,
+    /// IEEE 754 single precision floating point, 4 bytes per sample
+    F32,
+}
+
+/// A decoded gain map image together with its metadata.
+#[derive(Clone, Debug)]
+pub struct GainMap {
+    /// The metadata of the gain map.
+    pub metadata: GainMapMetadata,
+    /// The size and pixel format of the gain map image, either `L8` or `RGB24`.
+    pub info: ImageInfo,
+    /// The samples of the gain map image.
+    pub data: Vec<u8>,
+}
+
+impl GainMap {
+    /// Applies the gain map to the decoded primary image to reconstruct the HDR rendition for a
+    /// display that can show highlights `display_boost` times brighter than SDR white.
+    ///
+    /// The primary image is taken to be sRGB encoded, in `L8` or `RGB24` format. The returned
+    /// image holds three linear light samples per pixel in native endian, SDR white being 1.0.
+    /// The gain map is scaled to the size of the primary image with bilinear interpolation.
+    /// A display boost that is not a positive number is an error.
+    pub fn apply(
+        &self,
+        image: &[u8],
+        info: &ImageInfo,
+        display_boost: f32,
+        format: HdrFormat,
+    ) -> Result<Vec<u8>> {
+        if display_boost.is_nan() || display_boost <= 0.0 {
+            return Err(Error::Format(format!(
+                "display boost {} is not a positive number",
+                display_boost
+            )));
+        }
+
+        let image_channels = match info.pixel_format {
+            PixelFormat::L8 => 1,
+            PixelFormat::RGB24 => 3,
+            _ => {
+                return Err(Error::Format(
+                    "gain maps can only be applied to 8-bit grayscale or RGB images".to_owned(),
+                ))
+            }
+        };
+        let map_channels = match self.info.pixel_format {
+            PixelFormat::L8 => 1,
+            PixelFormat::RGB24 => 3,
+            _ => {
+                return Err(Error::Format(
+                    "gain map is not an 8-bit grayscale or RGB image".to_owned(),
+                ))
+            }
+        };
+
+        let width = usize::from(info.width);
+        let height = usize::from(info.height);
+        let map_width = usize::from(self.info.width);
+        let map_height = usize::from(self.info.height);
+
+        if image.len() != width * height * image_channels
+            || self.data.len() != map_width * map_height * map_channels
+            || map_width == 0
+            || map_height == 0
+        {
+            return Err(Error::Format(
+                "image data does not match its size".to_owned(),
+            ));
+        }
+
+        let metadata = &self.metadata;
+        let capacity = metadata.hdr_capacity_max - metadata.hdr_capacity_min;
+        let mut weight = if capacity > 0.0 {
+            ((display_boost.log2() - metadata.hdr_capacity_min) / capacity).clamp(0.0, 1.0)
+        } else if display_boost.log2() >= metadata.hdr_capacity_max {
+            1.0
+        } else {
+            0.0
+        };
+        // The gain map of an HDR primary image leads to the SDR rendition.
+        let (offset_base, offset_alternate) = if metadata.base_rendition_is_hdr {
+            weight = 1.0 - weight;
+            (metadata.offset_hdr, metadata.offset_sdr)
+        } else {
+            (metadata.offset_sdr, metadata.offset_hdr)
+        };
+
+        let linear: Vec<f32> = (0..=255u8)
+            .map(|value| cms::srgb_to_linear(f32::from(value) / 255.0))
+            .collect();
+        let sample_size = match format {
+            HdrFormat::F16 => 2,
+            HdrFormat::F32 => 4,
+        };
+        let mut output = Vec::with_capacity(width * height * 3 * sample_size);
+
+        // Position of a pixel centre in the gain map and the weights of the samples around it.
+        let position = |i: usize, size: usize, map_size: usize| {
+            let position = ((i as f32 + 0.5) * map_size as f32 / size as f32 - 0.5)
+                .clamp(0.0, (map_size - 1) as f32);
+            let low = position as usize;
+            (low, (low + 1).min(map_size - 1), position - low as f32)
+        };
+
+        for y in 0..height {
+            let (y0, y1, fy) = position(y, height, map_height);
+
+            for x in 0..width {
+                let (x0, x1, fx) = position(x, width, map_width);
+                let pixel = &image[(y * width + x) * image_channels..][..image_channels];
+
+                for c in 0..3 {
+                    let map_c = c.min(map_channels - 1);
+                    let sample = |x: usize, y: usize| {
+                        f32::from(self.data[(y * map_width + x) * map_channels + map_c])
+                    };
+                    let top = sample(x0, y0) * (1.0 - fx) + sample(x1, y0) * fx;
+                    let bottom = sample(x0, y1) * (1.0 - fx) + sample(x1, y1) * fx;
+                    let recovery = (top * (1.0 - fy) + bottom * fy) / 255.0;
+
+                    // Ultra HDR Image Format v1.0, Decode: Applying the gain map
+                    let log_recovery = recovery.powf(1.0 / metadata.gamma[c]);
+                    let log_boost = metadata.gain_map_min[c] * (1.0 - log_recovery)
+                        + metadata.gain_map_max[c] * log_recovery;
+                    let base = linear[usize::from(pixel[c.min(image_channels - 1)])];
+                    let value = ((base + offset_base[c]) * (log_boost * weight).exp2()
+                        - offset_alternate[c])
+                        .max(0.0);
+
+                    match format {
+                        HdrFormat::F16 => {
+                            output.extend_from_slice(&f32_to_f16(value).to_ne_bytes())
+                        }
+                        HdrFormat::F32 => output.extend_from_slice(&value.to_ne_bytes()),
+                    }
+                }
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+impl<R: Read + Seek> Decoder<R> {
+    /// Decodes the gain map of an Ultra HDR image.
+    ///
+    /// The gain map is the first image listed by `mpf_images` after the primary one whose XMP
+    /// packet holds gain map metadata. Returns `None` if there is none.
+    pub fn decode_gain_map(&mut self) -> Result<Option<GainMap>> {
+        self.read_info()?;
+
+        let images = match self.mpf_images() {
+            Some(images) => images.to_vec(),
+            None => return Ok(None),
+        };
+
+        for image in images.into_iter().skip(1).filter(|image| {
+            matches!(
+                image.image_type,
+                MpImageType::GainMap | MpImageType::Undefined
+            )
+        }) {
+            let mut decoder = self.mpf_image_decoder(image)?;
+            decoder.read_info()?;
+
+            let metadata = match decoder.xmp_data().and_then(GainMapMetadata::from_xmp) {
+                Some(metadata) => metadata,
+                None => continue,
+            };
+            let mut data = decoder.decode()?;
+            let mut info = decoder.info().unwrap();
+
+            // The gain map is stored in the orientation of the primary image.
+            if let Some(orientation) = self.applied_orientation() {
+                data = orientation.apply(data, info.width.into(), info.height.into());
+                if orientation.swaps_dimensions() {
+                    mem::swap(&mut info.width, &mut info.height);
+                }
+            }
+
+            return Ok(Some(GainMap {
+                metadata,
+                info,
+                data,
+            }));
+        }
+
+        Ok(None)
+    }
+
+    /// Decodes an Ultra HDR image and applies its gain map, see `GainMap::apply`.
+    ///
+    /// Returns `None` if the image has no gain map, `decode` returns the SDR rendition then.
+    pub fn decode_hdr(&mut self, display_boost: f32, format: HdrFormat) -> Result<Option<Vec<u8>>> {
+        let gain_map = match self.decode_gain_map()? {
+            Some(gain_map) => gain_map,
+            None => return Ok(None),
+        };
+
+        let image = self.decode()?;
+        let info = self.info().unwrap();
+
+        gain_map
+            .apply(&image, &info, display_boost, format)
+            .map(Some)
+    }
+}
+
+// Converts to the nearest half precision value, ties to even.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x7F_FFFF;
+
+    if exponent == 0xFF {
+        // Infinity or NaN
+        return sign | 0x7C00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+
+    if exponent >= 0x1F {
+        return sign | 0x7C00;
+    }
+
+    let (half, remainder, halfway) = if exponent <= 0 {
+        // Subnormal
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        (
+            mantissa >> shift,
+            mantissa & ((1 << shift) - 1),
+            1 << (shift - 1),
+        )
+    } else {
+        (
+            ((exponent as u32) << 10) | (mantissa >> 13),
+            mantissa & 0x1FFF,
+            0x1000,
+        )
+    };
+
+    let round_up = remainder > halfway || (remainder == halfway && half & 1 == 1);
+    sign | (half + u32::from(round_up)) as u16
+}
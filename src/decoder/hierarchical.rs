@@ -0,0 +1,425 @@
+use crate::decoder::Decoder;
+use crate::error::{Error, Result, UnsupportedFeature};
+use crate::idct::dequantize_and_idct_block_8x8_signed;
+use crate::parser::{
+    update_component_sizes, CodingProcess, Component, Dimensions, FrameInfo, HierarchicalInfo,
+    ScanInfo,
+};
+use alloc::borrow::ToOwned;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::mem;
+use std::io::Read;
+
+/// State of a hierarchical image, created by the DHP segment.
+pub(crate) struct Hierarchical {
+    pub(crate) info: HierarchicalInfo,
+    // Description of the image, with the coding process of the last frame.
+    pub(crate) frame: Option<FrameInfo>,
+    // Reconstructed samples of each DHP component, used as the reference of differential frames.
+    references: Vec<Option<Reference>>,
+    // Expansion requested by an EXP segment for the next frame.
+    expansion: (bool, bool),
+    // Quantization tables of the components of the pending frame, kept from their first scan.
+    quantization_tables: Vec<Option<Arc<[u16; 64]>>>,
+    frame_pending: bool,
+    levels: usize,
+    level_size: Option<Dimensions>,
+}
+
+struct Reference {
+    width: usize,
+    height: usize,
+    samples: Vec<u16>,
+}
+
+impl Hierarchical {
+    pub(crate) fn new(info: HierarchicalInfo) -> Hierarchical {
+        Hierarchical {
+            references: (0..info.components.len()).map(|_| None).collect(),
+            info,
+            frame: None,
+            expansion: (false, false),
+            quantization_tables: Vec::new(),
+            frame_pending: false,
+            levels: 0,
+            level_size: None,
+        }
+    }
+
+    pub(crate) fn set_expansion(&mut self, horizontal: bool, vertical: bool) {
+        self.expansion = (horizontal, vertical);
+    }
+
+    /// Keeps the quantization tables used by the components of a scan. The frame is only
+    /// dequantized when the next one starts, after DQT segments for that frame may have
+    /// redefined the tables.
+    pub(crate) fn latch_quantization_tables(
+        &mut self,
+        scan: &ScanInfo,
+        frame: &FrameInfo,
+        tables: &[Option<Arc<[u16; 64]>>; 4],
+    ) {
+        for &i in &scan.component_indices {
+            if self.quantization_tables[i].is_none() {
+                self.quantization_tables[i] =
+                    tables[frame.components[i].quantization_table_index].clone();
+            }
+        }
+    }
+
+    /// Returns whether the size of the decoded image is known, which is the case once the last
+    /// level to decode or the full resolution is reached. Without a limit on the number of levels
+    /// the image is decoded up to the size of the DHP segment.
+    pub(crate) fn has_final_size(&self, max_levels: usize) -> bool {
+        max_levels == usize::MAX
+            || self.levels == max_levels
+            || self.level_size == Some(self.info.image_size)
+    }
+
+    /// Describes the image of size `size` with the coding process of `frame`.
+    fn image_frame(&self, frame: &FrameInfo, size: Dimensions) -> Result<FrameInfo> {
+        let mut components = self.info.components.clone();
+        let mcu_size = update_component_sizes(size, &mut components)?;
+
+        Ok(FrameInfo {
+            is_baseline: false,
+            is_differential: false,
+            coding_process: frame.coding_process,
+            entropy_coding: frame.entropy_coding,
+            precision: self.info.precision,
+            image_size: size,
+            output_size: size,
+            mcu_size,
+            components,
+        })
+    }
+
+    fn component_index(&self, component: &Component) -> Result<usize> {
+        self.info
+            .components
+            .iter()
+            .position(|c| c.identifier == component.identifier)
+            .ok_or_else(|| {
+                Error::Format(format!(
+                    "frame component {} is not part of the DHP segment",
+                    component.identifier
+                ))
+            })
+    }
+}
+
+impl<R: Read> Decoder<R> {
+    /// Checks a frame of a hierarchical image and prepares the reference components it refines.
+    ///
+    /// Returns `false` if the frame starts a resolution level beyond the configured maximum, in
+    /// which case decoding stops at the current level.
+    pub(crate) fn start_hierarchical_frame(&mut self, frame: &FrameInfo) -> Result<bool> {
+        let max_levels = self.max_hierarchical_levels;
+        let hierarchical = self.hierarchical.as_mut().unwrap();
+
+        if frame.image_size.height == 0 {
+            return Err(Error::Unsupported(UnsupportedFeature::DNL));
+        }
+        if frame.precision != hierarchical.info.precision {
+            return Err(Error::Format(
+                "frame precision differs from the DHP segment".to_owned(),
+            ));
+        }
+        if frame.image_size.width > hierarchical.info.image_size.width
+            || frame.image_size.height > hierarchical.info.image_size.height
+        {
+            return Err(Error::Format(
+                "frame is larger than the DHP segment".to_owned(),
+            ));
+        }
+
+        if hierarchical.level_size != Some(frame.image_size) {
+            if hierarchical.levels == max_levels {
+                return Ok(false);
+            }
+
+            hierarchical.levels += 1;
+            hierarchical.level_size = Some(frame.image_size);
+        }
+
+        let (expand_horizontally, expand_vertically) = mem::take(&mut hierarchical.expansion);
+
+        for component in &frame.components {
+            let index = hierarchical.component_index(component)?;
+            let reference = &mut hierarchical.references[index];
+
+            if !frame.is_differential {
+                // Section J.1
+                // Each component starts with a non-differential frame at the lowest resolution.
+                if reference.is_some() {
+                    return Err(Error::Format(
+                        "non-differential frame after the first frame of a component".to_owned(),
+                    ));
+                }
+                continue;
+            }
+
+            let reference = reference.as_mut().ok_or_else(|| {
+                Error::Format("differential frame without reference component".to_owned())
+            })?;
+
+            if expand_horizontally || expand_vertically {
+                *reference = expand(reference, expand_horizontally, expand_vertically);
+            }
+
+            let (width, height) = component_size(frame, component);
+
+            if reference.width < width || reference.height < height {
+                return Err(Error::Format(
+                    "reference component is smaller than the differential frame".to_owned(),
+                ));
+            }
+        }
+
+        // The image has the size of the DHP segment, unless decoding stops at this level.
+        let size = if hierarchical.levels == max_levels {
+            frame.image_size
+        } else {
+            hierarchical.info.image_size
+        };
+        hierarchical.frame = Some(hierarchical.image_frame(frame, size)?);
+        hierarchical.quantization_tables = vec![None; frame.components.len()];
+        hierarchical.frame_pending = true;
+
+        // Coefficients are only kept per frame.
+        self.coefficients = Vec::new();
+
+        Ok(true)
+    }
+
+    /// Adds the decoded frame to the reference components.
+    pub(crate) fn finish_hierarchical_frame(&mut self, planes_u16: Vec<Vec<u16>>) -> Result<()> {
+        let frame = match self.hierarchical {
+            Some(ref mut hierarchical) if hierarchical.frame_pending => {
+                hierarchical.frame_pending = false;
+                self.frame.clone().unwrap()
+            }
+            _ => return Ok(()),
+        };
+
+        let max_value = (1i32 << frame.precision) - 1;
+        let coefficients = mem::take(&mut self.coefficients);
+
+        if frame.coding_process != CodingProcess::Lossless && coefficients.is_empty() {
+            return Err(Error::Format("frame without scans".to_owned()));
+        }
+
+        for (i, component) in frame.components.iter().enumerate() {
+            let (width, height) = component_size(&frame, component);
+            let hierarchical = self.hierarchical.as_mut().unwrap();
+            let index = hierarchical.component_index(component)?;
+
+            let reference = hierarchical.references[index]
+                .as_ref()
+                .filter(|_| frame.is_differential);
+
+            let samples = if frame.coding_process == CodingProcess::Lossless {
+                let mut samples = match planes_u16.get(i) {
+                    Some(plane) if !plane.is_empty() => plane.clone(),
+                    _ => return Err(Error::Format("not all components have data".to_owned())),
+                };
+
+                if let Some(reference) = reference {
+                    // Section J.2.2
+                    // Differences of lossless frames are added modulo 2^16.
+                    for (y, row) in samples.chunks_exact_mut(width).enumerate() {
+                        let reference_row = &reference.samples[y * reference.width..][..width];
+
+                        for (sample, &reference) in row.iter_mut().zip(reference_row) {
+                            *sample = sample.wrapping_add(reference);
+                        }
+                    }
+                }
+
+                samples
+            } else {
+                let quantization_table = hierarchical.quantization_tables[i]
+                    .as_ref()
+                    .ok_or_else(|| Error::Format("use of unset quantization table".to_owned()))?;
+
+                // Section J.2.1
+                // Differential frames code signed differences to the reference, which take the
+                // place of the level shift of non-differential frames.
+                let level_shift = 1 << (frame.precision - 1);
+                let mut samples = vec![0u16; width * height];
+                let mut block = [0i32; 64];
+
+                for block_y in 0..(height + 7) / 8 {
+                    for block_x in 0..(width + 7) / 8 {
+                        let offset =
+                            (block_y * usize::from(component.block_size.width) + block_x) * 64;
+                        dequantize_and_idct_block_8x8_signed(
+                            coefficients[i][offset..offset + 64].try_into().unwrap(),
+                            quantization_table,
+                            frame.precision,
+                            &mut block,
+                        );
+
+                        for (y, row) in block.chunks_exact(8).enumerate() {
+                            let sample_y = block_y * 8 + y;
+                            if sample_y >= height {
+                                break;
+                            }
+
+                            for (x, &value) in row.iter().enumerate() {
+                                let sample_x = block_x * 8 + x;
+                                if sample_x >= width {
+                                    break;
+                                }
+
+                                let base = match reference {
+                                    Some(reference) => i32::from(
+                                        reference.samples[sample_y * reference.width + sample_x],
+                                    ),
+                                    None => level_shift,
+                                };
+                                samples[sample_y * width + sample_x] =
+                                    (base + value).clamp(0, max_value) as u16;
+                            }
+                        }
+                    }
+                }
+
+                samples
+            };
+
+            hierarchical.references[index] = Some(Reference {
+                width,
+                height,
+                samples,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Converts the reference components into planes of the image described by the last frame.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn hierarchical_planes(&mut self) -> Result<(Vec<Vec<u8>>, Vec<Vec<u16>>)> {
+        let hierarchical = self.hierarchical.as_mut().unwrap();
+        let frame = match (&hierarchical.frame, hierarchical.level_size) {
+            (Some(frame), Some(size)) => hierarchical.image_frame(frame, size)?,
+            _ => {
+                return Err(Error::Format(
+                    "end of image encountered before frame".to_owned(),
+                ))
+            }
+        };
+
+        let mut planes = Vec::new();
+        let mut planes_u16 = Vec::new();
+
+        for (component, reference) in frame.components.iter().zip(&hierarchical.references) {
+            let (width, height) = component_size(&frame, component);
+            let reference = match reference {
+                Some(reference) if reference.width == width && reference.height == height => {
+                    reference
+                }
+                _ => {
+                    return Err(Error::Format(format!(
+                        "component {} is incomplete at the final resolution",
+                        component.identifier
+                    )))
+                }
+            };
+
+            if frame.coding_process == CodingProcess::Lossless {
+                planes_u16.push(reference.samples.clone());
+            } else {
+                // Lay the samples out like the output of the IDCT workers.
+                let line_stride = usize::from(component.block_size.width) * component.dct_scale;
+                let lines = usize::from(component.block_size.height) * component.dct_scale;
+                let mut plane = vec![0u16; line_stride * lines];
+
+                for (line, samples) in plane
+                    .chunks_exact_mut(line_stride)
+                    .zip(reference.samples.chunks_exact(width))
+                {
+                    line[..width].copy_from_slice(samples);
+                }
+
+                if frame.precision == 8 {
+                    planes.push(plane.into_iter().map(|sample| sample as u8).collect());
+                } else {
+                    planes_u16.push(plane);
+                }
+            }
+        }
+
+        hierarchical.frame = Some(frame.clone());
+        self.frame = Some(frame);
+
+        Ok((planes, planes_u16))
+    }
+}
+
+fn component_size(frame: &FrameInfo, component: &Component) -> (usize, usize) {
+    // The lossless decoding process does not subsample components.
+    if frame.coding_process == CodingProcess::Lossless {
+        (
+            usize::from(frame.image_size.width),
+            usize::from(frame.image_size.height),
+        )
+    } else {
+        (
+            usize::from(component.size.width),
+            usize::from(component.size.height),
+        )
+    }
+}
+
+// Section J.1.1.2
+// "The upsampling filter increases the spatial resolution by a factor of two horizontally,
+//  vertically, or both. Bi-linear interpolation is used for the upsampling."
+fn expand(reference: &Reference, horizontal: bool, vertical: bool) -> Reference {
+    let mut width = reference.width;
+    let mut height = reference.height;
+    let mut samples = reference.samples.clone();
+
+    if horizontal {
+        let mut expanded = Vec::with_capacity(width * 2 * height);
+
+        for row in samples.chunks_exact(width) {
+            for (x, &sample) in row.iter().enumerate() {
+                // The rightmost sample is replicated for the interpolation at the edge.
+                let next = row[(x + 1).min(width - 1)];
+                expanded.push(sample);
+                expanded.push(((u32::from(sample) + u32::from(next)) >> 1) as u16);
+            }
+        }
+
+        width *= 2;
+        samples = expanded;
+    }
+
+    if vertical {
+        let mut expanded = Vec::with_capacity(width * height * 2);
+
+        for y in 0..height {
+            let row = &samples[y * width..][..width];
+            let next = &samples[(y + 1).min(height - 1) * width..][..width];
+            expanded.extend_from_slice(row);
+            expanded.extend(
+                row.iter()
+                    .zip(next)
+                    .map(|(&a, &b)| ((u32::from(a) + u32::from(b)) >> 1) as u16),
+            );
+        }
+
+        height *= 2;
+        samples = expanded;
+    }
+
+    Reference {
+        width,
+        height,
+        samples,
+    }
+}
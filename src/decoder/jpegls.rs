@@ -0,0 +1,915 @@
+use crate::decoder::{check_buffer_size, Decoder, MAX_SCAN_COMPONENTS};
+use crate::error::{Error, Result};
+use crate::marker::Marker;
+use crate::parser::{FrameInfo, InterleaveMode, PresetParameters, ScanInfo};
+use crate::read_u8;
+use alloc::borrow::ToOwned;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::{cmp, mem};
+use std::io::Read;
+
+// ITU-T T.87 Table A.7
+const J: [u8; 32] = [
+    0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 9, 10, 11, 12, 13,
+    14, 15,
+];
+
+// ITU-T T.87 Section A.2.1
+const MIN_C: i32 = -128;
+const MAX_C: i32 = 127;
+// Number of contexts of the regular mode, the quantized gradients of each context are in -4..=4.
+const CONTEXT_COUNT: usize = 365;
+
+/// Preset parameters and mapping tables defined by LSE segments.
+#[derive(Default)]
+pub struct JpegLsTables {
+    pub preset_parameters: PresetParameters,
+    mapping_tables: Vec<MappingTable>,
+    // Mapping table selected by the last scan of each frame component, zero for none.
+    component_tables: Vec<u8>,
+}
+
+// ITU-T T.87 Section C.2.4.1.2
+struct MappingTable {
+    table_id: u8,
+    entry_width: u8,
+    entries: Vec<u8>,
+}
+
+impl JpegLsTables {
+    pub fn add_mapping_table(
+        &mut self,
+        table_id: u8,
+        entry_width: u8,
+        entries: Vec<u8>,
+        continuation: bool,
+    ) -> Result<()> {
+        let existing = self
+            .mapping_tables
+            .iter_mut()
+            .find(|table| table.table_id == table_id);
+
+        match (existing, continuation) {
+            (Some(table), true) => {
+                // ITU-T T.87 Section C.2.4.1.3
+                if table.entry_width != entry_width {
+                    return Err(Error::Format(format!(
+                        "mapping table {} continued with a different entry width",
+                        table_id
+                    )));
+                }
+                table.entries.extend_from_slice(&entries);
+            }
+            (None, true) => {
+                return Err(Error::Format(format!(
+                    "continuation of undefined mapping table {}",
+                    table_id
+                )))
+            }
+            (Some(table), false) => {
+                table.entry_width = entry_width;
+                table.entries = entries;
+            }
+            (None, false) => self.mapping_tables.push(MappingTable {
+                table_id,
+                entry_width,
+                entries,
+            }),
+        }
+
+        Ok(())
+    }
+
+    fn mapping_table(&self, table_id: u8) -> Option<&MappingTable> {
+        self.mapping_tables
+            .iter()
+            .find(|table| table.table_id == table_id)
+    }
+
+    fn select_mapping_tables(&mut self, frame: &FrameInfo, scan: &ScanInfo) -> Result<()> {
+        self.component_tables.resize(frame.components.len(), 0);
+
+        for (&i, &table_id) in scan
+            .component_indices
+            .iter()
+            .zip(&scan.mapping_table_indices)
+        {
+            if table_id != 0 {
+                let table = match self.mapping_table(table_id) {
+                    Some(table) => table,
+                    None => {
+                        return Err(Error::Format(format!(
+                            "scan makes use of undefined mapping table {}",
+                            table_id
+                        )))
+                    }
+                };
+
+                // A table with three bytes per entry is taken as a palette of RGB colours.
+                if table.entry_width > 3 || (table.entry_width == 3 && frame.components.len() != 1)
+                {
+                    return Err(Error::Format(format!(
+                        "unsupported mapping table entry width {}",
+                        table.entry_width
+                    )));
+                }
+            }
+
+            self.component_tables[i] = table_id;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the frame describing the output of the mapping tables, if any component uses one.
+    pub fn mapped_frame(&self, frame: &FrameInfo) -> Option<FrameInfo> {
+        if self.component_tables.iter().all(|&table_id| table_id == 0) {
+            return None;
+        }
+
+        let mut mapped = frame.clone();
+        let mut wide = false;
+
+        for (component, &table_id) in frame.components.iter().zip(&self.component_tables) {
+            wide |= match self.mapping_table(table_id) {
+                Some(table) => {
+                    if table.entry_width == 3 {
+                        mapped.components = vec![component.clone(); 3];
+                    }
+                    table.entry_width == 2
+                }
+                None => frame.precision > 8,
+            };
+        }
+
+        mapped.precision = if wide { 16 } else { 8 };
+        Some(mapped)
+    }
+
+    /// Replaces the samples of components that use a mapping table by the entries they select.
+    pub fn map_planes(&self, planes: Vec<Vec<u16>>) -> Result<Vec<Vec<u16>>> {
+        let mut mapped = Vec::with_capacity(planes.len());
+
+        for (plane, &table_id) in planes.into_iter().zip(&self.component_tables) {
+            let table = match self.mapping_table(table_id) {
+                Some(table) => table,
+                None => {
+                    mapped.push(plane);
+                    continue;
+                }
+            };
+
+            let entry_width = usize::from(table.entry_width);
+            let entry_count = table.entries.len() / entry_width;
+
+            if plane.iter().any(|&index| usize::from(index) >= entry_count) {
+                return Err(Error::Format(format!(
+                    "sample outside of mapping table {}",
+                    table_id
+                )));
+            }
+
+            let entry = |index: u16| &table.entries[usize::from(index) * entry_width..];
+
+            match entry_width {
+                1 => mapped.push(plane.iter().map(|&index| entry(index)[0].into()).collect()),
+                2 => mapped.push(
+                    plane
+                        .iter()
+                        .map(|&index| u16::from_be_bytes([entry(index)[0], entry(index)[1]]))
+                        .collect(),
+                ),
+                _ => {
+                    for channel in 0..3 {
+                        mapped.push(
+                            plane
+                                .iter()
+                                .map(|&index| entry(index)[channel].into())
+                                .collect(),
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(mapped)
+    }
+}
+
+impl<R: Read> Decoder<R> {
+    /// decode_scan_jpeg_ls
+    pub fn decode_scan_jpeg_ls(
+        &mut self,
+        frame: &FrameInfo,
+        scan: &ScanInfo,
+    ) -> Result<(Option<Marker>, Vec<Vec<u16>>)> {
+        let ncomp = scan.component_indices.len();
+        assert!(ncomp <= MAX_SCAN_COMPONENTS);
+
+        let parameters = Parameters::new(
+            frame.precision,
+            scan.near_lossless,
+            self.jpeg_ls.preset_parameters,
+        )?;
+        self.jpeg_ls.select_mapping_tables(frame, scan)?;
+
+        let width = usize::from(frame.image_size.width);
+        let height = usize::from(frame.image_size.height);
+        let mut lines_left_until_restart = self.restart_interval;
+        let mut expected_rst_num = 0;
+
+        check_buffer_size(
+            ncomp.checked_mul(width).and_then(|size| size.checked_mul(height)),
+            self.decoding_buffer_size_limit,
+        )?;
+
+        // Each line has an extra sample at both ends for the neighbours of the edge samples.
+        let mut previous = vec![vec![0i32; width + 2]; ncomp];
+        let mut current = vec![vec![0i32; width + 2]; ncomp];
+        let mut results = vec![Vec::with_capacity(width * height); ncomp];
+
+        let mut decoder = ScanDecoder::new(&mut self.reader, parameters);
+
+        for _line in 0..height {
+            if self.restart_interval > 0 {
+                if lines_left_until_restart == 0 {
+                    match decoder.bits.take_marker()? {
+                        Some(Marker::RST(n)) => {
+                            if n != expected_rst_num {
+                                return Err(Error::Format(format!(
+                                    "found RST{} where RST{} was expected",
+                                    n, expected_rst_num
+                                )));
+                            }
+
+                            // ITU-T T.87 Section D.2
+                            // Coding restarts as if this was the first line of the scan.
+                            decoder.reset();
+                            for line in &mut previous {
+                                line.fill(0);
+                            }
+
+                            expected_rst_num = (expected_rst_num + 1) % 8;
+                            lines_left_until_restart = self.restart_interval;
+                        }
+                        Some(marker) => {
+                            return Err(Error::Format(format!(
+                                "found marker {:?} inside scan where RST{} was expected",
+                                marker, expected_rst_num
+                            )))
+                        }
+                        None => {
+                            return Err(Error::Format(format!(
+                                "no marker found where RST{} was expected",
+                                expected_rst_num
+                            )))
+                        }
+                    }
+                }
+
+                lines_left_until_restart -= 1;
+            }
+
+            // ITU-T T.87 Section A.2.1
+            // The sample left of a line is the first sample of the line above, the sample right
+            // of the line above repeats its last sample.
+            for (previous, current) in previous.iter_mut().zip(&mut current) {
+                previous[width + 1] = previous[width];
+                current[0] = previous[1];
+            }
+
+            match scan.interleave_mode {
+                InterleaveMode::None | InterleaveMode::Line => {
+                    for (i, (previous, current)) in previous.iter().zip(&mut current).enumerate() {
+                        decoder.decode_line(i, previous, current)?;
+                    }
+                }
+                InterleaveMode::Sample => {
+                    decoder.decode_line_interleaved(&previous, &mut current)?
+                }
+            }
+
+            for (result, line) in results.iter_mut().zip(&current) {
+                result.extend(line[1..=width].iter().map(|&sample| sample as u16));
+            }
+
+            mem::swap(&mut previous, &mut current);
+        }
+
+        let mut marker = decoder.bits.take_marker()?;
+        while let Some(Marker::RST(_)) = marker {
+            marker = self.read_marker().ok();
+        }
+
+        let mut planes = vec![Vec::new(); frame.components.len()];
+        for (&i, result) in scan.component_indices.iter().zip(results) {
+            planes[i] = result;
+        }
+
+        Ok((marker, planes))
+    }
+}
+
+// ITU-T T.87 Section A.2.1 and C.2.4.1.1
+struct Parameters {
+    max_value: i32,
+    near: i32,
+    range: i32,
+    quantized_bits: u8,
+    limit: u32,
+    thresholds: [i32; 3],
+    reset: i32,
+}
+
+impl Parameters {
+    fn new(precision: u8, near: u8, preset: PresetParameters) -> Result<Parameters> {
+        let max_sample = (1i32 << precision) - 1;
+        let max_value = match preset.max_value {
+            0 => max_sample,
+            value if i32::from(value) <= max_sample => i32::from(value),
+            value => {
+                return Err(Error::Format(format!(
+                    "JPEG-LS maximum sample value {} exceeds the precision",
+                    value
+                )))
+            }
+        };
+
+        let near = i32::from(near);
+        if near > cmp::min(255, max_value / 2) {
+            return Err(Error::Format(format!(
+                "invalid NEAR value {} in JPEG-LS scan",
+                near
+            )));
+        }
+
+        let range = (max_value + 2 * near) / (2 * near + 1) + 1;
+        let quantized_bits = bit_count(range - 1);
+        let bits = cmp::max(2, bit_count(max_value));
+        let limit = 2 * (u32::from(bits) + cmp::max(8, u32::from(bits)));
+
+        // ITU-T T.87 Section C.2.4.1.1.1
+        let clamp = |value: i32, min: i32| {
+            if value > max_value || value < min {
+                min
+            } else {
+                value
+            }
+        };
+        let basic = [3, 7, 21];
+        let mut defaults = [0; 3];
+        let mut min = near + 1;
+        for (i, default) in defaults.iter_mut().enumerate() {
+            let i = i as i32;
+            let value = if max_value >= 128 {
+                let factor = (cmp::min(max_value, 4095) + 128) / 256;
+                factor * (basic[i as usize] - 2 - i) + 2 + i + (3 + 2 * i) * near
+            } else {
+                let factor = 256 / (max_value + 1);
+                cmp::max(2 + i, basic[i as usize] / factor + (3 + 2 * i) * near)
+            };
+            *default = clamp(value, min);
+            min = *default;
+        }
+
+        let mut thresholds = [0; 3];
+        let mut min = near + 1;
+        for (threshold, (&value, &default)) in thresholds
+            .iter_mut()
+            .zip(preset.thresholds.iter().zip(&defaults))
+        {
+            *threshold = match i32::from(value) {
+                0 => default,
+                value if (min..=max_value).contains(&value) => value,
+                value => {
+                    return Err(Error::Format(format!(
+                        "invalid JPEG-LS threshold {}",
+                        value
+                    )))
+                }
+            };
+            min = *threshold;
+        }
+
+        let reset = match i32::from(preset.reset) {
+            0 => 64,
+            value if (3..=cmp::max(255, max_value)).contains(&value) => value,
+            value => {
+                return Err(Error::Format(format!(
+                    "invalid JPEG-LS reset value {}",
+                    value
+                )))
+            }
+        };
+
+        Ok(Parameters {
+            max_value,
+            near,
+            range,
+            quantized_bits,
+            limit,
+            thresholds,
+            reset,
+        })
+    }
+
+    // ITU-T T.87 Section A.3.3
+    fn quantize_gradient(&self, d: i32) -> i32 {
+        let [t1, t2, t3] = self.thresholds;
+
+        if d <= -t3 {
+            -4
+        } else if d <= -t2 {
+            -3
+        } else if d <= -t1 {
+            -2
+        } else if d < -self.near {
+            -1
+        } else if d <= self.near {
+            0
+        } else if d < t1 {
+            1
+        } else if d < t2 {
+            2
+        } else if d < t3 {
+            3
+        } else {
+            4
+        }
+    }
+
+    fn context(&self, ra: i32, rb: i32, rc: i32, rd: i32) -> i32 {
+        (self.quantize_gradient(rd - rb) * 9 + self.quantize_gradient(rb - rc)) * 9
+            + self.quantize_gradient(rc - ra)
+    }
+
+    // ITU-T T.87 Section A.4.4, A.7.2.2 and Annex F
+    fn reconstruct(&self, prediction: i32, error: i32) -> i32 {
+        let step = 2 * self.near + 1;
+        let mut value = prediction + error * step;
+
+        if value < -self.near {
+            value += self.range * step;
+        } else if value > self.max_value + self.near {
+            value -= self.range * step;
+        }
+
+        value.clamp(0, self.max_value)
+    }
+}
+
+// Number of bits needed to represent the value.
+fn bit_count(value: i32) -> u8 {
+    (32 - value.leading_zeros()) as u8
+}
+
+// ITU-T T.87 Section A.4.1
+fn predict(ra: i32, rb: i32, rc: i32) -> i32 {
+    if rc >= cmp::max(ra, rb) {
+        cmp::min(ra, rb)
+    } else if rc <= cmp::min(ra, rb) {
+        cmp::max(ra, rb)
+    } else {
+        ra + rb - rc
+    }
+}
+
+// ITU-T T.87 Section A.5.1
+fn golomb_parameter(n: i32, a: i32) -> u8 {
+    let mut k = 0;
+    while k < 16 && (n << k) < a {
+        k += 1;
+    }
+    k
+}
+
+#[derive(Clone, Copy)]
+struct Context {
+    a: i32,
+    b: i32,
+    c: i32,
+    n: i32,
+}
+
+#[derive(Clone, Copy)]
+struct RunContext {
+    a: i32,
+    n: i32,
+    nn: i32,
+}
+
+struct ScanDecoder<'a, R> {
+    bits: BitReader<'a, R>,
+    parameters: Parameters,
+    contexts: [Context; CONTEXT_COUNT],
+    run_contexts: [RunContext; 2],
+    // Line interleaved scans keep an index of the run length order for each component.
+    run_indices: [usize; MAX_SCAN_COMPONENTS],
+}
+
+impl<'a, R: Read> ScanDecoder<'a, R> {
+    fn new(reader: &'a mut R, parameters: Parameters) -> Self {
+        let mut decoder = ScanDecoder {
+            bits: BitReader::new(reader),
+            parameters,
+            contexts: [Context {
+                a: 0,
+                b: 0,
+                c: 0,
+                n: 0,
+            }; CONTEXT_COUNT],
+            run_contexts: [RunContext { a: 0, n: 0, nn: 0 }; 2],
+            run_indices: [0; MAX_SCAN_COMPONENTS],
+        };
+        decoder.reset();
+        decoder
+    }
+
+    // ITU-T T.87 Section A.2.1
+    fn reset(&mut self) {
+        let a = cmp::max(2, (self.parameters.range + 32) / 64);
+
+        self.bits.reset();
+        self.contexts = [Context {
+            a,
+            b: 0,
+            c: 0,
+            n: 1,
+        }; CONTEXT_COUNT];
+        self.run_contexts = [RunContext { a, n: 1, nn: 0 }; 2];
+        self.run_indices = [0; MAX_SCAN_COMPONENTS];
+    }
+
+    // Decodes a line of a single component, the lines include the samples outside of the edges.
+    fn decode_line(
+        &mut self,
+        component: usize,
+        previous: &[i32],
+        current: &mut [i32],
+    ) -> Result<()> {
+        let width = current.len() - 2;
+        let mut x = 1;
+
+        while x <= width {
+            let ra = current[x - 1];
+            let rb = previous[x];
+            let rc = previous[x - 1];
+            let rd = previous[x + 1];
+            let context = self.parameters.context(ra, rb, rc, rd);
+
+            if context == 0 {
+                x += self.decode_run(component, previous, current, x)?;
+            } else {
+                current[x] = self.decode_regular(context, predict(ra, rb, rc))?;
+                x += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    // ITU-T T.87 Annex B
+    // Sample interleaved lines use the run mode only if it applies to all components.
+    fn decode_line_interleaved(
+        &mut self,
+        previous: &[Vec<i32>],
+        current: &mut [Vec<i32>],
+    ) -> Result<()> {
+        let width = current[0].len() - 2;
+        let mut x = 1;
+
+        while x <= width {
+            let mut contexts = [0; MAX_SCAN_COMPONENTS];
+            for ((context, previous), current) in contexts.iter_mut().zip(previous).zip(&*current) {
+                *context = self.parameters.context(
+                    current[x - 1],
+                    previous[x],
+                    previous[x - 1],
+                    previous[x + 1],
+                );
+            }
+
+            if contexts.iter().all(|&context| context == 0) {
+                x += self.decode_run_interleaved(previous, current, x)?;
+            } else {
+                for ((&context, previous), current) in
+                    contexts.iter().zip(previous).zip(current.iter_mut())
+                {
+                    let prediction = predict(current[x - 1], previous[x], previous[x - 1]);
+                    current[x] = self.decode_regular(context, prediction)?;
+                }
+                x += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    // ITU-T T.87 Section A.4 to A.6
+    fn decode_regular(&mut self, context: i32, prediction: i32) -> Result<i32> {
+        let parameters = &self.parameters;
+        let sign = if context < 0 { -1 } else { 1 };
+        let ctx = &mut self.contexts[(context * sign) as usize];
+
+        let prediction = (prediction + sign * ctx.c).clamp(0, parameters.max_value);
+        let k = golomb_parameter(ctx.n, ctx.a);
+        let mapped = self
+            .bits
+            .decode_value(k, parameters.limit, parameters.quantized_bits)?;
+
+        // ITU-T T.87 Section A.5.2
+        let mut error = if mapped % 2 == 0 {
+            (mapped / 2) as i32
+        } else {
+            -((mapped / 2) as i32) - 1
+        };
+        if parameters.near == 0 && k == 0 && 2 * ctx.b <= -ctx.n {
+            error = -(error + 1);
+        }
+
+        // ITU-T T.87 Section A.6
+        ctx.b += error * (2 * parameters.near + 1);
+        ctx.a += error.abs();
+        if ctx.n == parameters.reset {
+            ctx.a >>= 1;
+            ctx.b >>= 1;
+            ctx.n >>= 1;
+        }
+        ctx.n += 1;
+
+        if ctx.b <= -ctx.n {
+            ctx.b += ctx.n;
+            if ctx.c > MIN_C {
+                ctx.c -= 1;
+            }
+            if ctx.b <= -ctx.n {
+                ctx.b = -ctx.n + 1;
+            }
+        } else if ctx.b > 0 {
+            ctx.b -= ctx.n;
+            if ctx.c < MAX_C {
+                ctx.c += 1;
+            }
+            if ctx.b > 0 {
+                ctx.b = 0;
+            }
+        }
+
+        Ok(parameters.reconstruct(prediction, sign * error))
+    }
+
+    // ITU-T T.87 Section A.7.1.2
+    // Returns the length of the run of samples equal to the one left of it, which ends at the end
+    // of the line or before an interruption sample.
+    fn decode_run_length(&mut self, run_index: &mut usize, remaining: usize) -> Result<usize> {
+        let mut length = 0;
+
+        while self.bits.read_bit()? {
+            let count = cmp::min(1 << J[*run_index], remaining - length);
+            length += count;
+
+            if count == 1 << J[*run_index] {
+                *run_index = cmp::min(*run_index + 1, J.len() - 1);
+            }
+            if length == remaining {
+                return Ok(length);
+            }
+        }
+
+        length += self.bits.read_bits(J[*run_index])? as usize;
+
+        if length >= remaining {
+            return Err(Error::Format("JPEG-LS run exceeds the line".to_owned()));
+        }
+
+        Ok(length)
+    }
+
+    fn decode_run(
+        &mut self,
+        component: usize,
+        previous: &[i32],
+        current: &mut [i32],
+        x: usize,
+    ) -> Result<usize> {
+        let width = current.len() - 2;
+        let ra = current[x - 1];
+        let mut run_index = self.run_indices[component];
+
+        let length = self.decode_run_length(&mut run_index, width + 1 - x)?;
+        current[x..x + length].fill(ra);
+
+        if x + length <= width {
+            // ITU-T T.87 Section A.7.2
+            let rb = previous[x + length];
+            current[x + length] = if (ra - rb).abs() <= self.parameters.near {
+                let error = self.decode_interruption(1, run_index)?;
+                self.parameters.reconstruct(ra, error)
+            } else {
+                let error = self.decode_interruption(0, run_index)?;
+                self.parameters
+                    .reconstruct(rb, error * if rb < ra { -1 } else { 1 })
+            };
+
+            self.run_indices[component] = run_index.saturating_sub(1);
+            return Ok(length + 1);
+        }
+
+        self.run_indices[component] = run_index;
+        Ok(length)
+    }
+
+    fn decode_run_interleaved(
+        &mut self,
+        previous: &[Vec<i32>],
+        current: &mut [Vec<i32>],
+        x: usize,
+    ) -> Result<usize> {
+        let width = current[0].len() - 2;
+        let mut run_index = self.run_indices[0];
+
+        let length = self.decode_run_length(&mut run_index, width + 1 - x)?;
+        for current in current.iter_mut() {
+            let ra = current[x - 1];
+            current[x..x + length].fill(ra);
+        }
+
+        if x + length <= width {
+            // The interruption samples of all components use the context of differing Ra and Rb.
+            for (previous, current) in previous.iter().zip(current.iter_mut()) {
+                let ra = current[x - 1];
+                let rb = previous[x + length];
+                let error = self.decode_interruption(0, run_index)?;
+                current[x + length] = self
+                    .parameters
+                    .reconstruct(rb, error * if rb < ra { -1 } else { 1 });
+            }
+
+            self.run_indices[0] = run_index.saturating_sub(1);
+            return Ok(length + 1);
+        }
+
+        self.run_indices[0] = run_index;
+        Ok(length)
+    }
+
+    // ITU-T T.87 Section A.7.2
+    fn decode_interruption(&mut self, run_type: i32, run_index: usize) -> Result<i32> {
+        let parameters = &self.parameters;
+        let ctx = &mut self.run_contexts[run_type as usize];
+
+        let k = golomb_parameter(ctx.n, ctx.a + (ctx.n >> 1) * run_type);
+        let limit = parameters.limit - u32::from(J[run_index]) - 1;
+        let mapped = self
+            .bits
+            .decode_value(k, limit, parameters.quantized_bits)? as i32;
+
+        let value = mapped + run_type;
+        let map = value & 1;
+        let magnitude = (value + map) / 2;
+        let error = if (k != 0 || 2 * ctx.nn >= ctx.n) == (map != 0) {
+            -magnitude
+        } else {
+            magnitude
+        };
+
+        // ITU-T T.87 Section A.7.2.2
+        if error < 0 {
+            ctx.nn += 1;
+        }
+        ctx.a += (mapped + 1 - run_type) >> 1;
+        if ctx.n == parameters.reset {
+            ctx.a >>= 1;
+            ctx.n >>= 1;
+            ctx.nn >>= 1;
+        }
+        ctx.n += 1;
+
+        Ok(error)
+    }
+}
+
+// ITU-T T.87 Section A.1 and D.1
+// A byte following an 0xFF byte only contributes its seven lower bits, unless its most significant
+// bit is set and it is part of a marker.
+struct BitReader<'a, R> {
+    reader: &'a mut R,
+    bits: u64,
+    num_bits: u8,
+    marker: Option<Marker>,
+}
+
+impl<'a, R: Read> BitReader<'a, R> {
+    fn new(reader: &'a mut R) -> Self {
+        BitReader {
+            reader,
+            bits: 0,
+            num_bits: 0,
+            marker: None,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.bits = 0;
+        self.num_bits = 0;
+        self.marker = None;
+    }
+
+    fn push(&mut self, value: u8, count: u8) {
+        self.bits = (self.bits << count) | u64::from(value);
+        self.num_bits += count;
+    }
+
+    fn fill(&mut self) -> Result<()> {
+        while self.num_bits <= 48 {
+            // Zeros are read once a marker is found.
+            if self.marker.is_some() {
+                self.push(0, 8);
+                continue;
+            }
+
+            let byte = read_u8(self.reader)?;
+
+            if byte != 0xFF {
+                self.push(byte, 8);
+                continue;
+            }
+
+            let mut next = read_u8(self.reader)?;
+
+            if next & 0x80 == 0 {
+                self.push(0xFF, 8);
+                self.push(next, 7);
+                continue;
+            }
+
+            // Section B.1.1.2
+            // "Any marker may optionally be preceded by any number of fill bytes, which are bytes
+            //  assigned code X’FF’."
+            while next == 0xFF {
+                next = read_u8(self.reader)?;
+            }
+
+            match Marker::from_u8(next) {
+                Some(marker) => self.marker = Some(marker),
+                None => {
+                    return Err(Error::Format(format!(
+                        "invalid marker {:#04x} in JPEG-LS scan",
+                        next
+                    )))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_bits(&mut self, count: u8) -> Result<u32> {
+        if count == 0 {
+            return Ok(0);
+        }
+        if self.num_bits < count {
+            self.fill()?;
+        }
+
+        self.num_bits -= count;
+        Ok(((self.bits >> self.num_bits) & ((1 << count) - 1)) as u32)
+    }
+
+    fn read_bit(&mut self) -> Result<bool> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    // ITU-T T.87 Section A.5.3
+    // Decodes a limited length Golomb code.
+    fn decode_value(&mut self, k: u8, limit: u32, quantized_bits: u8) -> Result<u32> {
+        let escape = limit - u32::from(quantized_bits) - 1;
+        let mut high_bits = 0;
+
+        while !self.read_bit()? {
+            high_bits += 1;
+
+            if high_bits > escape {
+                return Err(Error::Format("invalid code in JPEG-LS scan".to_owned()));
+            }
+        }
+
+        if high_bits == escape {
+            return Ok(self.read_bits(quantized_bits)? + 1);
+        }
+
+        Ok((high_bits << k) | self.read_bits(k)?)
+    }
+
+    // Skips the padding at the end of the entropy-coded data and returns the marker following it.
+    fn take_marker(&mut self) -> Result<Option<Marker>> {
+        while self.marker.is_none() {
+            self.num_bits = 0;
+            self.fill()?;
+        }
+
+        self.num_bits = 0;
+        Ok(self.marker.take())
+    }
+}
@@ -0,0 +1,161 @@
+use super::{PixelFormat, Sample};
+use alloc::vec::Vec;
+
+/// The order of the channels of the pixels returned by `Decoder::decode`, see
+/// `Decoder::set_output_layout`.
+///
+/// Grayscale and RGB pixels are arranged in the layout, other pixels such as CMYK keep the layout
+/// of their pixel format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OutputLayout {
+    /// The layout of the pixel format of the image.
+    Native,
+    /// Red, green, blue and an opaque alpha channel.
+    Rgba,
+    /// Blue, green and red.
+    Bgr,
+    /// Blue, green, red and an opaque alpha channel.
+    Bgra,
+    /// An opaque alpha channel, red, green and blue.
+    Argb,
+    /// A single luma channel. It is the Y component of YCbCr images and is computed from the red,
+    /// green and blue of other colour images, weighted like libjpeg does.
+    Luma,
+}
+
+impl OutputLayout {
+    /// Returns the pixel format of pixels of the pixel format arranged in the layout.
+    pub fn pixel_format(self, pixel_format: PixelFormat) -> PixelFormat {
+        match (self, pixel_format) {
+            (OutputLayout::Rgba, PixelFormat::L8 | PixelFormat::RGB24) => PixelFormat::RGBA32,
+            (OutputLayout::Rgba, PixelFormat::L16 | PixelFormat::RGB48) => PixelFormat::RGBA64,
+            (OutputLayout::Bgr, PixelFormat::L8 | PixelFormat::RGB24) => PixelFormat::BGR24,
+            (OutputLayout::Bgr, PixelFormat::L16 | PixelFormat::RGB48) => PixelFormat::BGR48,
+            (OutputLayout::Bgra, PixelFormat::L8 | PixelFormat::RGB24) => PixelFormat::BGRA32,
+            (OutputLayout::Bgra, PixelFormat::L16 | PixelFormat::RGB48) => PixelFormat::BGRA64,
+            (OutputLayout::Argb, PixelFormat::L8 | PixelFormat::RGB24) => PixelFormat::ARGB32,
+            (OutputLayout::Argb, PixelFormat::L16 | PixelFormat::RGB48) => PixelFormat::ARGB64,
+            (OutputLayout::Luma, PixelFormat::RGB24) => PixelFormat::L8,
+            (OutputLayout::Luma, PixelFormat::RGB48) => PixelFormat::L16,
+            _ => pixel_format,
+        }
+    }
+
+    // Returns the number of samples of a pixel of `channels` colour channels in the layout.
+    pub(crate) fn samples_per_pixel(self, channels: usize) -> usize {
+        match self {
+            OutputLayout::Native => channels,
+            OutputLayout::Bgr => 3,
+            OutputLayout::Rgba | OutputLayout::Bgra | OutputLayout::Argb => 4,
+            OutputLayout::Luma => 1,
+        }
+    }
+
+    // Writes an RGB pixel in the layout, `alpha` being the opaque alpha value. The native layout
+    // is RGB.
+    #[inline]
+    pub(crate) fn write_pixel<T: Sample>(self, pixel: &mut [T], [r, g, b]: [T; 3], alpha: T) {
+        match self {
+            OutputLayout::Native => pixel[..3].copy_from_slice(&[r, g, b]),
+            OutputLayout::Rgba => pixel[..4].copy_from_slice(&[r, g, b, alpha]),
+            OutputLayout::Bgr => pixel[..3].copy_from_slice(&[b, g, r]),
+            OutputLayout::Bgra => pixel[..4].copy_from_slice(&[b, g, r, alpha]),
+            OutputLayout::Argb => pixel[..4].copy_from_slice(&[alpha, r, g, b]),
+            OutputLayout::Luma => pixel[0] = luma(r, g, b),
+        }
+    }
+
+    // Arranges decoded grayscale or RGB pixels of the pixel format in the layout, samples of more
+    // than 8 bits being native endian `u16`. Other pixels are returned as they are.
+    pub(crate) fn arrange(
+        self,
+        data: Vec<u8>,
+        pixel_format: PixelFormat,
+        sample_max: u16,
+    ) -> Vec<u8> {
+        let channels = match pixel_format {
+            PixelFormat::L8 | PixelFormat::L16 => 1,
+            PixelFormat::RGB24 | PixelFormat::RGB48 => 3,
+            _ => return data,
+        };
+
+        if self == OutputLayout::Native || (self == OutputLayout::Luma && channels == 1) {
+            return data;
+        }
+
+        if sample_max <= 255 {
+            self.arrange_samples(&data, channels, sample_max as u8)
+        } else {
+            let samples: Vec<u16> = data
+                .chunks_exact(2)
+                .map(|sample| u16::from_ne_bytes([sample[0], sample[1]]))
+                .collect();
+            self.arrange_samples(&samples, channels, sample_max)
+                .into_iter()
+                .flat_map(u16::to_ne_bytes)
+                .collect()
+        }
+    }
+
+    fn arrange_samples<T: Sample>(self, samples: &[T], channels: usize, alpha: T) -> Vec<T> {
+        let pixel_size = self.samples_per_pixel(channels);
+        let mut output = Vec::with_capacity(samples.len() / channels * pixel_size);
+        let mut pixel = [T::default(); 4];
+
+        for input in samples.chunks_exact(channels) {
+            // Grayscale pixels have the same red, green and blue.
+            let rgb = match *input {
+                [gray] => [gray; 3],
+                [r, g, b] => [r, g, b],
+                _ => unreachable!(),
+            };
+            self.write_pixel(&mut pixel, rgb, alpha);
+            output.extend_from_slice(&pixel[..pixel_size]);
+        }
+
+        output
+    }
+}
+
+// The layouts the colour conversion of 8-bit RGB and YCbCr lines produces directly, as types
+// choosing a line function for each, see `choose_color_convert_func`.
+pub(crate) trait LineLayout {
+    const LAYOUT: OutputLayout;
+}
+
+pub(crate) struct Rgb;
+pub(crate) struct Rgba;
+pub(crate) struct Bgr;
+pub(crate) struct Bgra;
+pub(crate) struct Argb;
+pub(crate) struct Luma;
+
+impl LineLayout for Rgb {
+    const LAYOUT: OutputLayout = OutputLayout::Native;
+}
+
+impl LineLayout for Rgba {
+    const LAYOUT: OutputLayout = OutputLayout::Rgba;
+}
+
+impl LineLayout for Bgr {
+    const LAYOUT: OutputLayout = OutputLayout::Bgr;
+}
+
+impl LineLayout for Bgra {
+    const LAYOUT: OutputLayout = OutputLayout::Bgra;
+}
+
+impl LineLayout for Argb {
+    const LAYOUT: OutputLayout = OutputLayout::Argb;
+}
+
+impl LineLayout for Luma {
+    const LAYOUT: OutputLayout = OutputLayout::Luma;
+}
+
+// The luma of libjpeg's rgb_gray_convert in jccolor.c, Y = 0.299 R + 0.587 G + 0.114 B in 16-bit
+// fixed point.
+fn luma<T: Sample>(r: T, g: T, b: T) -> T {
+    T::from_u32((19595 * r.to_u32() + 38470 * g.to_u32() + 7471 * b.to_u32() + 32768) >> 16)
+}
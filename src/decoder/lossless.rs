@@ -1,4 +1,4 @@
-use crate::decoder::{Decoder, MAX_COMPONENTS};
+use crate::decoder::{check_buffer_size, Decoder, MAX_SCAN_COMPONENTS};
 use crate::error::{Error, Result};
 use crate::huffman::HuffmanDecoder;
 use crate::marker::Marker;
@@ -14,9 +14,7 @@ impl<R: Read> Decoder<R> {
         scan: &ScanInfo,
     ) -> Result<(Option<Marker>, Vec<Vec<u16>>)> {
         let ncomp = scan.component_indices.len();
-        let npixel = frame.image_size.height as usize * frame.image_size.width as usize;
-        assert!(ncomp <= MAX_COMPONENTS);
-        let mut results = vec![vec![0u16; npixel]; ncomp];
+        assert!(ncomp <= MAX_SCAN_COMPONENTS);
 
         let components: Vec<Component> = scan
             .component_indices
@@ -36,18 +34,39 @@ impl<R: Read> Decoder<R> {
         }
 
         let mut huffman = HuffmanDecoder::new();
+        let buffer_size_limit = self.decoding_buffer_size_limit;
         let reader = &mut self.reader;
         let mut mcus_left_until_restart = self.restart_interval;
         let mut expected_rst_num = 0;
-        let mut ra = [0u16; MAX_COMPONENTS];
-        let mut rb = [0u16; MAX_COMPONENTS];
-        let mut rc = [0u16; MAX_COMPONENTS];
+        let mut ra = [0u16; MAX_SCAN_COMPONENTS];
+        let mut rb = [0u16; MAX_SCAN_COMPONENTS];
+        let mut rc = [0u16; MAX_SCAN_COMPONENTS];
 
         let width = frame.image_size.width as usize;
-        let height = frame.image_size.height as usize;
+        let is_height_unknown = frame.image_size.height == 0;
+        // Section B.2.5
+        // Without a known height the scan continues until the DNL marker follows, up to the
+        // largest height the DNL segment can define.
+        let max_height = if is_height_unknown {
+            u16::MAX as usize
+        } else {
+            frame.image_size.height as usize
+        };
+        let mut height = 0;
 
+        let npixel = frame.image_size.height as usize * width;
         let mut differences = vec![Vec::with_capacity(npixel); ncomp];
-        for _mcu_y in 0..height {
+        while height < max_height {
+            if is_height_unknown {
+                if height > 0 && huffman.is_end_of_data(reader)? {
+                    break;
+                }
+
+                // The differences grow by a row, which must stay within the limit of the decoded
+                // image.
+                check_buffer_size((height + 1).checked_mul(width * ncomp), buffer_size_limit)?;
+            }
+
             for _mcu_x in 0..width {
                 if self.restart_interval > 0 {
                     if mcus_left_until_restart == 0 {
@@ -103,9 +122,22 @@ impl<R: Read> Decoder<R> {
                     differences[i].push(diff);
                 }
             }
+
+            height += 1;
         }
 
-        if scan.predictor_selection == Predictor::Ra {
+        let mut results = vec![vec![0u16; height * width]; ncomp];
+
+        if frame.is_differential {
+            // Section J.2.2
+            // Differential frames code the difference to the reference component without
+            // prediction, it is added to the reference once the frame is complete.
+            for (result, differences) in results.iter_mut().zip(&differences) {
+                for (result, &diff) in result.iter_mut().zip(differences) {
+                    *result = ((diff & 0xFFFF) as u16) << scan.point_transform;
+                }
+            }
+        } else if scan.predictor_selection == Predictor::Ra {
             for (i, _component) in components.iter().enumerate() {
                 // calculate the top left pixel
                 let diff = differences[i][0];
@@ -249,8 +281,19 @@ pub fn compute_image_lossless(frame: &FrameInfo, mut data: Vec<Vec<u16>>) -> Res
     }
 }
 
+pub fn compute_planes_lossless(frame: &FrameInfo, data: Vec<Vec<u16>>) -> Result<Vec<Vec<u8>>> {
+    if data.is_empty() || data.iter().any(Vec::is_empty) {
+        return Err(Error::Format("not all components have data".to_owned()));
+    }
+
+    Ok(data
+        .into_iter()
+        .map(|plane| convert_to_u8(frame, plane))
+        .collect())
+}
+
 fn convert_to_u8(frame: &FrameInfo, data: Vec<u16>) -> Vec<u8> {
-    if frame.precision == 8 {
+    if frame.precision <= 8 {
         data.iter().map(|x| *x as u8).collect()
     } else {
         // we output native endian, which is the standard for image-rs
@@ -0,0 +1,130 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// The orientation of the stored image, as given by the Orientation (0x0112) field of the exif
+/// data. Each variant names the transform that displays the image upright.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Orientation {
+    /// The image is stored upright (1).
+    Normal,
+    /// The image is mirrored left to right (2).
+    FlipHorizontal,
+    /// The image is rotated by 180 degrees (3).
+    Rotate180,
+    /// The image is mirrored top to bottom (4).
+    FlipVertical,
+    /// The image is mirrored along the top-left to bottom-right diagonal (5).
+    Transpose,
+    /// The image needs to be rotated by 90 degrees clockwise (6).
+    Rotate90,
+    /// The image is mirrored along the top-right to bottom-left diagonal (7).
+    Transverse,
+    /// The image needs to be rotated by 270 degrees clockwise (8).
+    Rotate270,
+}
+
+impl Orientation {
+    /// Returns the orientation of an exif Orientation value, from 1 to 8.
+    pub fn from_exif(value: u16) -> Option<Orientation> {
+        Some(match value {
+            1 => Orientation::Normal,
+            2 => Orientation::FlipHorizontal,
+            3 => Orientation::Rotate180,
+            4 => Orientation::FlipVertical,
+            5 => Orientation::Transpose,
+            6 => Orientation::Rotate90,
+            7 => Orientation::Transverse,
+            8 => Orientation::Rotate270,
+            _ => return None,
+        })
+    }
+
+    /// Returns whether applying the orientation swaps the width and height of the image.
+    pub fn swaps_dimensions(self) -> bool {
+        matches!(
+            self,
+            Orientation::Transpose
+                | Orientation::Rotate90
+                | Orientation::Transverse
+                | Orientation::Rotate270
+        )
+    }
+
+    // Applies the orientation to an image of `width` by `height` pixels in place.
+    pub(crate) fn apply(self, mut data: Vec<u8>, width: usize, height: usize) -> Vec<u8> {
+        if width == 0 || height == 0 || data.len() % (width * height) != 0 {
+            return data;
+        }
+
+        let pixel_size = data.len() / (width * height);
+        let row_size = width * pixel_size;
+
+        // Reversing the bytes of a run of pixels also reverses the bytes within each pixel.
+        let reverse_pixels = |data: &mut [u8]| {
+            data.reverse();
+            for pixel in data.chunks_exact_mut(pixel_size) {
+                pixel.reverse();
+            }
+        };
+
+        match self {
+            Orientation::Normal => {}
+            Orientation::FlipHorizontal => {
+                for row in data.chunks_exact_mut(row_size) {
+                    reverse_pixels(row);
+                }
+            }
+            Orientation::Rotate180 => reverse_pixels(&mut data),
+            Orientation::FlipVertical => {
+                for y in 0..height / 2 {
+                    let (top, bottom) = data.split_at_mut((height - 1 - y) * row_size);
+                    top[y * row_size..][..row_size].swap_with_slice(&mut bottom[..row_size]);
+                }
+            }
+            Orientation::Transpose
+            | Orientation::Rotate90
+            | Orientation::Transverse
+            | Orientation::Rotate270 => {
+                // Index of the pixel at `index` in the output image, which is `height` pixels
+                // wide.
+                let destination = |index: usize| {
+                    let (x, y) = (index % width, index / width);
+                    let (output_x, output_y) = match self {
+                        Orientation::Transpose => (y, x),
+                        Orientation::Rotate90 => (height - 1 - y, x),
+                        Orientation::Transverse => (height - 1 - y, width - 1 - x),
+                        _ => (y, width - 1 - x),
+                    };
+                    output_y * height + output_x
+                };
+
+                // The pixels are moved along the cycles of the permutation, each one taking the
+                // place of the next, so no second image is needed. `moved` has a bit for every
+                // pixel that is at its destination.
+                let mut moved = vec![0u64; (width * height + 63) / 64];
+                let mut pixel = vec![0u8; pixel_size];
+
+                for start in 0..width * height {
+                    if moved[start / 64] & (1 << (start % 64)) != 0 {
+                        continue;
+                    }
+
+                    pixel.copy_from_slice(&data[start * pixel_size..][..pixel_size]);
+                    let mut index = start;
+
+                    loop {
+                        index = destination(index);
+                        moved[index / 64] |= 1 << (index % 64);
+                        pixel.swap_with_slice(&mut data[index * pixel_size..][..pixel_size]);
+
+                        if index == start {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        data
+    }
+}
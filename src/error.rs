@@ -5,6 +5,7 @@ use core::result;
 use std::error::Error as StdError;
 use std::io::Error as IoError;
 
+use crate::icc::IccError;
 use crate::ColorTransform;
 
 pub type Result<T> = result::Result<T, Error>;
@@ -13,34 +14,45 @@ pub type Result<T> = result::Result<T, Error>;
 ///
 /// Support for features listed here may be included in future versions of this library.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum UnsupportedFeature {
-    /// Hierarchical JPEG.
+    /// Hierarchical JPEG. No longer returned, hierarchical images are supported.
     Hierarchical,
-    /// JPEG using arithmetic entropy coding instead of Huffman coding.
+    /// Lossless JPEG using arithmetic entropy coding instead of Huffman coding.
     ArithmeticEntropyCoding,
-    /// Sample precision in bits. 8 bit sample precision is what is currently supported in non-lossless coding process.
+    /// Sample precision in bits. Precisions from 2 to 16 bits are supported, within the limits
+    /// the coding process sets.
     SamplePrecision(u8),
-    /// Number of components in an image. 1, 3 and 4 components are currently supported.
+    /// Number of components in an image. No longer returned, any number of components is
+    /// supported.
     ComponentCount(u8),
     /// An image can specify a zero height in the frame header and use the DNL (Define Number of
     /// Lines) marker at the end of the first scan to define the number of lines in the frame.
     DNL,
     /// Subsampling ratio.
     SubsamplingRatio,
-    /// A subsampling ratio not representable as an integer.
+    /// A subsampling ratio not representable as an integer. No longer returned, such ratios are
+    /// supported.
     NonIntegerSubsamplingRatio,
-    /// Colour transform
+    /// Colour transform. No longer returned, the colour transforms are supported.
     ColorTransform(ColorTransform),
+    /// JPEG-LS image dimensions larger than 65535, given by an LSE marker segment.
+    OversizeDimensions,
+    /// A non-zero point transform in a JPEG-LS scan.
+    PointTransform,
 }
 
 /// Errors that can occur while decoding a JPEG image.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// The image is not formatted properly. The string contains detailed information about the
     /// error.
     Format(String),
     /// The image makes use of a JPEG feature not (currently) supported by this library.
     Unsupported(UnsupportedFeature),
+    /// The embedded ICC profile is broken, such as by a missing APP2 chunk.
+    Icc(IccError),
     /// An I/O error occurred while decoding the image.
     Io(IoError),
     /// An internal error occurred while decoding the image.
@@ -52,6 +64,7 @@ impl fmt::Display for Error {
         match *self {
             Error::Format(ref desc) => write!(f, "invalid JPEG format: {}", desc),
             Error::Unsupported(ref feat) => write!(f, "unsupported JPEG feature: {:?}", feat),
+            Error::Icc(ref err) => write!(f, "invalid ICC profile: {}", err),
             Error::Io(ref err) => err.fmt(f),
             Error::Internal(ref err) => err.fmt(f),
         }
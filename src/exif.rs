@@ -0,0 +1,391 @@
+use crate::error::{Error, Result};
+use crate::tiff::{type_size, IfdEntry, Tiff};
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The image file directory (IFD) of exif data a field is stored in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ExifIfd {
+    /// IFD0, describing the primary image
+    Ifd0,
+    /// The Exif IFD, with the capture conditions
+    Exif,
+    /// The GPS IFD
+    Gps,
+    /// The Interoperability IFD
+    Interop,
+    /// IFD1, describing the thumbnail
+    Ifd1,
+}
+
+/// An unsigned rational number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Rational {
+    /// The numerator.
+    pub numerator: u32,
+    /// The denominator.
+    pub denominator: u32,
+}
+
+/// A signed rational number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SRational {
+    /// The numerator.
+    pub numerator: i32,
+    /// The denominator.
+    pub denominator: i32,
+}
+
+impl Rational {
+    /// Returns the value of the rational number, which is not finite if the denominator is zero.
+    pub fn to_f64(self) -> f64 {
+        f64::from(self.numerator) / f64::from(self.denominator)
+    }
+}
+
+impl SRational {
+    /// Returns the value of the rational number, which is not finite if the denominator is zero.
+    pub fn to_f64(self) -> f64 {
+        f64::from(self.numerator) / f64::from(self.denominator)
+    }
+}
+
+/// The value of an exif field, one variant per TIFF field type.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExifValue {
+    /// 8-bit unsigned integers
+    Byte(Vec<u8>),
+    /// Text, without the terminating NUL
+    Ascii(String),
+    /// 16-bit unsigned integers
+    Short(Vec<u16>),
+    /// 32-bit unsigned integers, also used for IFD offsets
+    Long(Vec<u32>),
+    /// Unsigned rational numbers
+    Rational(Vec<Rational>),
+    /// 8-bit signed integers
+    SByte(Vec<i8>),
+    /// Bytes whose meaning depends on the field
+    Undefined(Vec<u8>),
+    /// 16-bit signed integers
+    SShort(Vec<i16>),
+    /// 32-bit signed integers
+    SLong(Vec<i32>),
+    /// Signed rational numbers
+    SRational(Vec<SRational>),
+    /// Single precision floating point numbers
+    Float(Vec<f32>),
+    /// Double precision floating point numbers
+    Double(Vec<f64>),
+    /// A field type this decoder does not know, with the bytes of the value
+    Unknown {
+        /// The field type.
+        field_type: u16,
+        /// The bytes of the value.
+        data: Vec<u8>,
+    },
+}
+
+impl ExifValue {
+    /// Returns the text of an `Ascii` value.
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            ExifValue::Ascii(ref text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Returns the first value of a `Byte`, `Short` or `Long` value.
+    pub fn as_u32(&self) -> Option<u32> {
+        match *self {
+            ExifValue::Byte(ref values) => values.first().map(|&value| value.into()),
+            ExifValue::Short(ref values) => values.first().map(|&value| value.into()),
+            ExifValue::Long(ref values) => values.first().copied(),
+            _ => None,
+        }
+    }
+
+    /// Returns the first value of a `Rational` value.
+    pub fn as_rational(&self) -> Option<Rational> {
+        match *self {
+            ExifValue::Rational(ref values) => values.first().copied(),
+            _ => None,
+        }
+    }
+
+    /// Returns the first value of an `SRational` value.
+    pub fn as_srational(&self) -> Option<SRational> {
+        match *self {
+            ExifValue::SRational(ref values) => values.first().copied(),
+            _ => None,
+        }
+    }
+}
+
+/// A field of exif data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExifField {
+    /// The IFD the field is stored in.
+    pub ifd: ExifIfd,
+    /// The tag of the field.
+    pub tag: u16,
+    /// The value of the field.
+    pub value: ExifValue,
+}
+
+/// The fields of exif data, read from IFD0 and IFD1 and the Exif, GPS and Interoperability IFDs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Exif {
+    fields: Vec<ExifField>,
+}
+
+// Tags of the pointers to the Exif, GPS and Interoperability IFDs
+const EXIF_IFD_POINTER: u16 = 0x8769;
+const GPS_IFD_POINTER: u16 = 0x8825;
+const INTEROP_IFD_POINTER: u16 = 0xA005;
+
+impl Exif {
+    /// Parses exif data starting at the TIFF header, as returned by `Decoder::exif_data`.
+    ///
+    /// An error is returned if the TIFF header or IFD0 cannot be read. Other IFDs and fields
+    /// that lie outside of the data, and IFDs that were already read, are skipped.
+    pub fn parse(data: &[u8]) -> Result<Exif> {
+        let invalid = || Error::Format("invalid exif data".to_owned());
+        let tiff = Tiff::new(data).ok_or_else(invalid)?;
+        let mut exif = Exif::default();
+        let mut visited = Vec::new();
+
+        let ifd0 = tiff.first_ifd_offset().ok_or_else(invalid)?;
+        let ifd1 = exif
+            .read_ifd(&tiff, ifd0, ExifIfd::Ifd0, &mut visited)
+            .ok_or_else(invalid)?;
+
+        if ifd1 != 0 {
+            exif.read_ifd(&tiff, ifd1, ExifIfd::Ifd1, &mut visited);
+        }
+
+        Ok(exif)
+    }
+
+    // Reads the fields of an IFD and the IFDs it points to, returns the offset of the next IFD.
+    fn read_ifd(
+        &mut self,
+        tiff: &Tiff,
+        offset: usize,
+        ifd: ExifIfd,
+        visited: &mut Vec<usize>,
+    ) -> Option<usize> {
+        // Offsets pointing back to an IFD would otherwise be followed forever.
+        if visited.contains(&offset) {
+            return None;
+        }
+        visited.push(offset);
+
+        let (entries, next) = tiff.read_ifd(offset)?;
+
+        for entry in &entries {
+            let value = match read_value(tiff, entry) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            let pointer = match (ifd, entry.tag) {
+                (ExifIfd::Ifd0, EXIF_IFD_POINTER) => Some(ExifIfd::Exif),
+                (ExifIfd::Ifd0, GPS_IFD_POINTER) => Some(ExifIfd::Gps),
+                (ExifIfd::Exif, INTEROP_IFD_POINTER) => Some(ExifIfd::Interop),
+                _ => None,
+            };
+            let pointer = pointer.and_then(|pointer| Some((pointer, value.as_u32()?)));
+
+            self.fields.push(ExifField {
+                ifd,
+                tag: entry.tag,
+                value,
+            });
+
+            if let Some((pointer, offset)) = pointer {
+                self.read_ifd(tiff, offset as usize, pointer, visited);
+            }
+        }
+
+        Some(next)
+    }
+
+    /// Returns all fields, in the order they are stored.
+    pub fn fields(&self) -> &[ExifField] {
+        &self.fields
+    }
+
+    /// Returns the value of the field with the tag in the IFD.
+    pub fn get(&self, ifd: ExifIfd, tag: u16) -> Option<&ExifValue> {
+        self.fields
+            .iter()
+            .find(|field| field.ifd == ifd && field.tag == tag)
+            .map(|field| &field.value)
+    }
+
+    /// Returns the Make (0x010F) of the camera.
+    pub fn make(&self) -> Option<&str> {
+        self.get(ExifIfd::Ifd0, 0x010F)?.as_str()
+    }
+
+    /// Returns the Model (0x0110) of the camera.
+    pub fn model(&self) -> Option<&str> {
+        self.get(ExifIfd::Ifd0, 0x0110)?.as_str()
+    }
+
+    /// Returns the Orientation (0x0112), from 1 to 8.
+    pub fn orientation(&self) -> Option<u16> {
+        let orientation = self.get(ExifIfd::Ifd0, 0x0112)?.as_u32()?;
+        u16::try_from(orientation).ok()
+    }
+
+    /// Returns the Software (0x0131) that wrote the image.
+    pub fn software(&self) -> Option<&str> {
+        self.get(ExifIfd::Ifd0, 0x0131)?.as_str()
+    }
+
+    /// Returns the DateTime (0x0132) the image was last changed, as `YYYY:MM:DD HH:MM:SS`.
+    pub fn date_time(&self) -> Option<&str> {
+        self.get(ExifIfd::Ifd0, 0x0132)?.as_str()
+    }
+
+    /// Returns the DateTimeOriginal (0x9003) the image was taken, as `YYYY:MM:DD HH:MM:SS`.
+    pub fn date_time_original(&self) -> Option<&str> {
+        self.get(ExifIfd::Exif, 0x9003)?.as_str()
+    }
+
+    /// Returns the ExposureTime (0x829A) in seconds.
+    pub fn exposure_time(&self) -> Option<Rational> {
+        self.get(ExifIfd::Exif, 0x829A)?.as_rational()
+    }
+
+    /// Returns the FNumber (0x829D).
+    pub fn f_number(&self) -> Option<Rational> {
+        self.get(ExifIfd::Exif, 0x829D)?.as_rational()
+    }
+
+    /// Returns the PhotographicSensitivity (0x8827), the ISO speed.
+    pub fn iso(&self) -> Option<u32> {
+        self.get(ExifIfd::Exif, 0x8827)?.as_u32()
+    }
+
+    /// Returns the ExposureBiasValue (0x9204) in EV.
+    pub fn exposure_bias(&self) -> Option<SRational> {
+        self.get(ExifIfd::Exif, 0x9204)?.as_srational()
+    }
+
+    /// Returns the FocalLength (0x920A) of the lens in millimetres.
+    pub fn focal_length(&self) -> Option<Rational> {
+        self.get(ExifIfd::Exif, 0x920A)?.as_rational()
+    }
+
+    /// Returns the GPSLatitude (0x0002) in degrees, negative in the southern hemisphere.
+    pub fn gps_latitude(&self) -> Option<f64> {
+        self.gps_coordinate(0x0001, 0x0002, "S")
+    }
+
+    /// Returns the GPSLongitude (0x0004) in degrees, negative west of the prime meridian.
+    pub fn gps_longitude(&self) -> Option<f64> {
+        self.gps_coordinate(0x0003, 0x0004, "W")
+    }
+
+    /// Returns the GPSAltitude (0x0006) in metres, negative below sea level.
+    pub fn gps_altitude(&self) -> Option<f64> {
+        let altitude = self.get(ExifIfd::Gps, 0x0006)?.as_rational()?.to_f64();
+
+        match self.get(ExifIfd::Gps, 0x0005).and_then(ExifValue::as_u32) {
+            Some(1) => Some(-altitude),
+            _ => Some(altitude),
+        }
+    }
+
+    // The coordinate is given as degrees, minutes and seconds.
+    fn gps_coordinate(&self, reference_tag: u16, tag: u16, negative: &str) -> Option<f64> {
+        let value = match *self.get(ExifIfd::Gps, tag)? {
+            ExifValue::Rational(ref values) if values.len() == 3 => {
+                values[0].to_f64() + values[1].to_f64() / 60.0 + values[2].to_f64() / 3600.0
+            }
+            _ => return None,
+        };
+
+        match self.get(ExifIfd::Gps, reference_tag)?.as_str()? {
+            reference if reference == negative => Some(-value),
+            _ => Some(value),
+        }
+    }
+}
+
+// TIFF Revision 6.0, Section 2: Types, and the Float, Double and IFD types of TIFF Technical
+// Notes
+fn read_value(tiff: &Tiff, entry: &IfdEntry) -> Option<ExifValue> {
+    let bytes = tiff.value(entry)?;
+    let size = type_size(entry.field_type);
+    let offsets = (0..entry.count as usize).map(|i| entry.value_offset + i * size);
+
+    Some(match entry.field_type {
+        1 => ExifValue::Byte(bytes.to_vec()),
+        2 => {
+            let end = bytes.iter().position(|&byte| byte == 0);
+            let text = &bytes[..end.unwrap_or(bytes.len())];
+            ExifValue::Ascii(String::from_utf8_lossy(text).into_owned())
+        }
+        3 => ExifValue::Short(
+            offsets
+                .map(|offset| tiff.read_u16(offset))
+                .collect::<Option<_>>()?,
+        ),
+        4 | 13 => ExifValue::Long(
+            offsets
+                .map(|offset| tiff.read_u32(offset))
+                .collect::<Option<_>>()?,
+        ),
+        5 => ExifValue::Rational(
+            offsets
+                .map(|offset| {
+                    Some(Rational {
+                        numerator: tiff.read_u32(offset)?,
+                        denominator: tiff.read_u32(offset + 4)?,
+                    })
+                })
+                .collect::<Option<_>>()?,
+        ),
+        6 => ExifValue::SByte(bytes.iter().map(|&byte| byte as i8).collect()),
+        7 => ExifValue::Undefined(bytes.to_vec()),
+        8 => ExifValue::SShort(
+            offsets
+                .map(|offset| tiff.read_u16(offset).map(|value| value as i16))
+                .collect::<Option<_>>()?,
+        ),
+        9 => ExifValue::SLong(
+            offsets
+                .map(|offset| tiff.read_u32(offset).map(|value| value as i32))
+                .collect::<Option<_>>()?,
+        ),
+        10 => ExifValue::SRational(
+            offsets
+                .map(|offset| {
+                    Some(SRational {
+                        numerator: tiff.read_u32(offset)? as i32,
+                        denominator: tiff.read_u32(offset + 4)? as i32,
+                    })
+                })
+                .collect::<Option<_>>()?,
+        ),
+        11 => ExifValue::Float(
+            offsets
+                .map(|offset| tiff.read_u32(offset).map(f32::from_bits))
+                .collect::<Option<_>>()?,
+        ),
+        12 => ExifValue::Double(
+            offsets
+                .map(|offset| tiff.read_u64(offset).map(f64::from_bits))
+                .collect::<Option<_>>()?,
+        ),
+        field_type => ExifValue::Unknown {
+            field_type,
+            data: bytes.to_vec(),
+        },
+    })
+}
@@ -14,6 +14,8 @@ const LUT_BITS: u8 = 8;
 pub struct HuffmanDecoder {
     bits: u64,
     num_bits: u8,
+    // Number of zero bits at the end of `bits` that were filled in after a marker.
+    fill_bits: u8,
     marker: Option<Marker>,
 }
 
@@ -22,6 +24,7 @@ impl HuffmanDecoder {
         HuffmanDecoder {
             bits: 0,
             num_bits: 0,
+            fill_bits: 0,
             marker: None,
         }
     }
@@ -98,6 +101,23 @@ impl HuffmanDecoder {
     pub fn reset(&mut self) {
         self.bits = 0;
         self.num_bits = 0;
+        self.fill_bits = 0;
+    }
+
+    // Used when the number of lines is defined by a DNL segment after the scan, the data then
+    // ends when a marker other than RST follows and only the padding bits are left.
+    pub fn is_end_of_data<R: Read>(&mut self, reader: &mut R) -> Result<bool> {
+        self.read_bits(reader)?;
+
+        match self.marker {
+            None | Some(Marker::RST(_)) => Ok(false),
+            Some(_) => {
+                let remaining = self.num_bits.saturating_sub(self.fill_bits);
+
+                // The entropy-coded segment is padded with 1-bits to complete the final byte.
+                Ok(remaining == 0 || (remaining < 8 && self.peek_bits(remaining) == (1 << remaining) - 1))
+            }
+        }
     }
 
     pub fn take_marker<R: Read>(&mut self, reader: &mut R) -> Result<Option<Marker>> {
@@ -124,7 +144,10 @@ impl HuffmanDecoder {
         while self.num_bits <= 56 {
             // Fill with zero bits if we have reached the end.
             let byte = match self.marker {
-                Some(_) => 0,
+                Some(_) => {
+                    self.fill_bits = self.fill_bits.min(self.num_bits) + 8;
+                    0
+                }
                 None => read_u8(reader)?,
             };
 
@@ -0,0 +1,343 @@
+use crate::error::{Error, Result};
+use crate::parser::IccChunk;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::{fmt, vec};
+
+/// A problem with the APP2 chunks of an ICC profile or with the profile itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IccError {
+    /// The chunk with the sequence number is missing.
+    MissingChunk(u8),
+    /// More than one chunk has the sequence number.
+    DuplicateChunk(u8),
+    /// The sequence number is zero or larger than the number of chunks.
+    ChunkOutOfRange {
+        /// The sequence number of the chunk.
+        sequence: u8,
+        /// The number of chunks given by the first chunk.
+        count: u8,
+    },
+    /// The chunks disagree about the number of chunks, or there are more chunks than that.
+    ChunkCountMismatch {
+        /// The number of chunks given by the first chunk.
+        expected: u8,
+        /// The number of chunks given by another chunk, or the number of chunks found.
+        found: usize,
+    },
+    /// The profile is shorter than its header says or lacks the `acsp` signature.
+    InvalidHeader,
+    /// The profile cannot be used to convert colours, see `Decoder::set_color_management`.
+    UnsupportedProfile,
+}
+
+impl fmt::Display for IccError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IccError::MissingChunk(sequence) => write!(f, "missing chunk {}", sequence),
+            IccError::DuplicateChunk(sequence) => write!(f, "duplicate chunk {}", sequence),
+            IccError::ChunkOutOfRange { sequence, count } => {
+                write!(f, "chunk {} out of range 1 to {}", sequence, count)
+            }
+            IccError::ChunkCountMismatch { expected, found } => {
+                write!(f, "expected {} chunks, found {}", expected, found)
+            }
+            IccError::InvalidHeader => write!(f, "invalid profile header"),
+            IccError::UnsupportedProfile => {
+                write!(f, "profile not supported for colour conversion")
+            }
+        }
+    }
+}
+
+/// The class of an ICC profile, telling the kind of device or transform it describes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IccProfileClass {
+    /// Input device profile (`scnr`), such as of a camera or scanner
+    Input,
+    /// Display device profile (`mntr`)
+    Display,
+    /// Output device profile (`prtr`), such as of a printer
+    Output,
+    /// DeviceLink profile (`link`)
+    DeviceLink,
+    /// ColorSpace profile (`spac`)
+    ColorSpace,
+    /// Abstract profile (`abst`)
+    Abstract,
+    /// NamedColor profile (`nmcl`)
+    NamedColor,
+    /// Another signature
+    Unknown([u8; 4]),
+}
+
+/// A colour space of an ICC profile, used for the data colour space and the profile connection
+/// space (PCS).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IccColorSpace {
+    /// CIE XYZ (`XYZ `)
+    Xyz,
+    /// CIE L*a*b* (`Lab `)
+    Lab,
+    /// CIE L*u*v* (`Luv `)
+    Luv,
+    /// YCbCr (`YCbr`)
+    YCbCr,
+    /// CIE Yxy (`Yxy `)
+    Yxy,
+    /// RGB (`RGB `)
+    Rgb,
+    /// Grayscale (`GRAY`)
+    Gray,
+    /// HSV (`HSV `)
+    Hsv,
+    /// HLS (`HLS `)
+    Hls,
+    /// CMYK (`CMYK`)
+    Cmyk,
+    /// CMY (`CMY `)
+    Cmy,
+    /// Another signature, such as `6CLR` for a six colour space
+    Unknown([u8; 4]),
+}
+
+/// The rendering intent an ICC profile was made for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IccRenderingIntent {
+    /// Perceptual (0)
+    Perceptual,
+    /// Media-relative colorimetric (1)
+    RelativeColorimetric,
+    /// Saturation (2)
+    Saturation,
+    /// ICC-absolute colorimetric (3)
+    AbsoluteColorimetric,
+    /// Another value
+    Unknown(u32),
+}
+
+/// An ICC profile with the fields of its header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IccProfile {
+    /// The profile data, starting at the header.
+    pub data: Vec<u8>,
+    /// The major, minor and bug fix version of the profile format.
+    pub version: (u8, u8, u8),
+    /// The class of the profile.
+    pub class: IccProfileClass,
+    /// The colour space of the data the profile applies to.
+    pub color_space: IccColorSpace,
+    /// The profile connection space, usually `Xyz` or `Lab`.
+    pub pcs: IccColorSpace,
+    /// The rendering intent.
+    pub rendering_intent: IccRenderingIntent,
+    /// The text of the profile description (`desc`) tag.
+    pub description: Option<String>,
+}
+
+// ICC.1:2022 Section 7.2: Profile header
+const HEADER_SIZE: usize = 128;
+
+impl IccProfile {
+    /// Parses the header and the description tag of an ICC profile, as returned by
+    /// `Decoder::icc_profile`.
+    pub fn parse(data: Vec<u8>) -> Result<IccProfile> {
+        IccProfile::parse_header(data).map_err(Error::Icc)
+    }
+
+    fn parse_header(data: Vec<u8>) -> core::result::Result<IccProfile, IccError> {
+        if data.len() < HEADER_SIZE
+            || read_u32(&data, 0).unwrap() as usize > data.len()
+            || data[36..40] != *b"acsp"
+        {
+            return Err(IccError::InvalidHeader);
+        }
+
+        let signature = |offset: usize| -> [u8; 4] {
+            let bytes = &data[offset..offset + 4];
+            [bytes[0], bytes[1], bytes[2], bytes[3]]
+        };
+
+        let class = match &signature(12) {
+            b"scnr" => IccProfileClass::Input,
+            b"mntr" => IccProfileClass::Display,
+            b"prtr" => IccProfileClass::Output,
+            b"link" => IccProfileClass::DeviceLink,
+            b"spac" => IccProfileClass::ColorSpace,
+            b"abst" => IccProfileClass::Abstract,
+            b"nmcl" => IccProfileClass::NamedColor,
+            _ => IccProfileClass::Unknown(signature(12)),
+        };
+
+        let rendering_intent = match read_u32(&data, 64).unwrap() {
+            0 => IccRenderingIntent::Perceptual,
+            1 => IccRenderingIntent::RelativeColorimetric,
+            2 => IccRenderingIntent::Saturation,
+            3 => IccRenderingIntent::AbsoluteColorimetric,
+            intent => IccRenderingIntent::Unknown(intent),
+        };
+
+        let mut profile = IccProfile {
+            version: (data[8], data[9] >> 4, data[9] & 0x0F),
+            class,
+            color_space: color_space(signature(16)),
+            pcs: color_space(signature(20)),
+            rendering_intent,
+            description: None,
+            data,
+        };
+        profile.description = profile.tag(*b"desc").and_then(parse_text);
+
+        Ok(profile)
+    }
+
+    /// Returns the data of the tag with the signature, such as `rXYZ` or `rTRC`, starting at the
+    /// type signature.
+    pub fn tag(&self, signature: [u8; 4]) -> Option<&[u8]> {
+        // ICC.1:2022 Section 7.3: Tag table
+        let count = read_u32(&self.data, HEADER_SIZE)? as usize;
+        let count = count.min(self.data.len() / 12);
+
+        (0..count).find_map(|i| {
+            let entry = self
+                .data
+                .get(HEADER_SIZE + 4 + i * 12..HEADER_SIZE + 16 + i * 12)?;
+            if entry[0..4] != signature {
+                return None;
+            }
+
+            let offset = read_u32(entry, 4)? as usize;
+            let size = read_u32(entry, 8)? as usize;
+            self.data.get(offset..offset.checked_add(size)?)
+        })
+    }
+}
+
+fn color_space(signature: [u8; 4]) -> IccColorSpace {
+    match &signature {
+        b"XYZ " => IccColorSpace::Xyz,
+        b"Lab " => IccColorSpace::Lab,
+        b"Luv " => IccColorSpace::Luv,
+        b"YCbr" => IccColorSpace::YCbCr,
+        b"Yxy " => IccColorSpace::Yxy,
+        b"RGB " => IccColorSpace::Rgb,
+        b"GRAY" => IccColorSpace::Gray,
+        b"HSV " => IccColorSpace::Hsv,
+        b"HLS " => IccColorSpace::Hls,
+        b"CMYK" => IccColorSpace::Cmyk,
+        b"CMY " => IccColorSpace::Cmy,
+        _ => IccColorSpace::Unknown(signature),
+    }
+}
+
+// Reads the text of a textDescriptionType tag of version 2 profiles, or the first record of a
+// multiLocalizedUnicodeType tag of version 4 profiles, preferring English.
+fn parse_text(tag: &[u8]) -> Option<String> {
+    match tag.get(0..4)? {
+        // ICC.1:2001-04 Section 6.5.17: textDescriptionType
+        b"desc" => {
+            let count = read_u32(tag, 8)? as usize;
+            let text = tag.get(12..12usize.checked_add(count)?)?;
+            let end = text.iter().position(|&byte| byte == 0).unwrap_or(count);
+            Some(String::from_utf8_lossy(&text[..end]).into_owned())
+        }
+        // ICC.1:2022 Section 10.15: multiLocalizedUnicodeType
+        b"mluc" => {
+            let record_size = read_u32(tag, 12)? as usize;
+            if record_size < 12 {
+                return None;
+            }
+            // Only the records that fit in the tag are read.
+            let count =
+                (read_u32(tag, 8)? as usize).min(tag.len().saturating_sub(16) / record_size);
+            let records = (0..count).filter_map(|i| {
+                let start = i.checked_mul(record_size)?.checked_add(16)?;
+                tag.get(start..)?.get(..12)
+            });
+            let record = records
+                .clone()
+                .find(|record| record[0..2] == *b"en")
+                .or_else(|| records.clone().next())?;
+
+            let length = read_u32(record, 4)? as usize;
+            let offset = read_u32(record, 8)? as usize;
+            let text = tag.get(offset..offset.checked_add(length)?)?;
+            let units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                .collect();
+            Some(
+                String::from_utf16_lossy(&units)
+                    .trim_end_matches('\0')
+                    .into(),
+            )
+        }
+        _ => None,
+    }
+}
+
+// ICC.1:2022 Annex B.4: Joins the APP2 chunks of the profile in the order of their sequence
+// numbers, which run from 1 to the number of chunks.
+pub(crate) fn reassemble(chunks: &[IccChunk]) -> core::result::Result<Option<Vec<u8>>, IccError> {
+    let count = match chunks.first() {
+        Some(chunk) => chunk.num_markers,
+        None => return Ok(None),
+    };
+
+    if let Some(chunk) = chunks.iter().find(|chunk| chunk.num_markers != count) {
+        return Err(IccError::ChunkCountMismatch {
+            expected: count,
+            found: chunk.num_markers.into(),
+        });
+    }
+
+    if chunks.len() > usize::from(count) {
+        return Err(IccError::ChunkCountMismatch {
+            expected: count,
+            found: chunks.len(),
+        });
+    }
+
+    let mut ordered: Vec<Option<&IccChunk>> = vec![None; usize::from(count)];
+
+    for chunk in chunks {
+        if chunk.seq_no == 0 || chunk.seq_no > count {
+            return Err(IccError::ChunkOutOfRange {
+                sequence: chunk.seq_no,
+                count,
+            });
+        }
+
+        let slot = &mut ordered[usize::from(chunk.seq_no) - 1];
+        if slot.is_some() {
+            return Err(IccError::DuplicateChunk(chunk.seq_no));
+        }
+        *slot = Some(chunk);
+    }
+
+    let mut data = Vec::new();
+
+    for (i, chunk) in ordered.iter().enumerate() {
+        match chunk {
+            Some(chunk) => data.extend_from_slice(&chunk.data),
+            None => return Err(IccError::MissingChunk(i as u8 + 1)),
+        }
+    }
+
+    Ok(Some(data))
+}
+
+// Joins and parses the APP2 chunks of the profile.
+pub(crate) fn read_profile(
+    chunks: &[IccChunk],
+) -> core::result::Result<Option<IccProfile>, IccError> {
+    match reassemble(chunks)? {
+        Some(data) => IccProfile::parse_header(data).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
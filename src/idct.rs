@@ -272,6 +272,59 @@ fn dequantize_and_idct_block_8x8_inner<'a, I>(
         output.len()
     );
 
+    let temp = dequantize_and_idct_columns(coefficients, quantization_table, 2);
+
+    for (chunk, output_chunk) in temp.chunks_exact(8).zip(output) {
+        let chunk = <&[_; 8]>::try_from(chunk).unwrap();
+
+        // constants scaled things up by 1<<12, plus we had 1<<2 from first
+        // loop, plus horizontal and vertical each scale by sqrt(8) so together
+        // we've got an extra 1<<3, so 1<<17 total we need to remove.
+        // so we want to round that, which means adding 0.5 * 1<<17,
+        // aka 65536. Also, we'll end up with -128 to 127 that we want
+        // to encode as 0..255 by adding 128, so we'll add that before the shift
+        const X_SCALE: i32 = 65536 + (128 << 17);
+
+        // eliminate downstream bounds checks
+        let output_chunk = &mut output_chunk[..8];
+
+        // TODO When the minimum rust version supports it
+        // let [s0, rest @ ..] = chunk;
+        let (s0, rest) = chunk.split_first().unwrap();
+        if *rest == [Wrapping(0); 7] {
+            let dcterm = stbi_clamp((stbi_fsh(*s0) + Wrapping(X_SCALE)) >> 17);
+            output_chunk[0] = dcterm;
+            output_chunk[1] = dcterm;
+            output_chunk[2] = dcterm;
+            output_chunk[3] = dcterm;
+            output_chunk[4] = dcterm;
+            output_chunk[5] = dcterm;
+            output_chunk[6] = dcterm;
+            output_chunk[7] = dcterm;
+        } else {
+            let Kernel {
+                xs: [x0, x1, x2, x3],
+                ts: [t0, t1, t2, t3],
+            } = kernel(*chunk, X_SCALE);
+
+            output_chunk[0] = stbi_clamp((x0 + t3) >> 17);
+            output_chunk[7] = stbi_clamp((x0 - t3) >> 17);
+            output_chunk[1] = stbi_clamp((x1 + t2) >> 17);
+            output_chunk[6] = stbi_clamp((x1 - t2) >> 17);
+            output_chunk[2] = stbi_clamp((x2 + t1) >> 17);
+            output_chunk[5] = stbi_clamp((x2 - t1) >> 17);
+            output_chunk[3] = stbi_clamp((x3 + t0) >> 17);
+            output_chunk[4] = stbi_clamp((x3 - t0) >> 17);
+        }
+    }
+}
+
+// The columns keep `pass1_bits` extra bits of precision for the rows.
+fn dequantize_and_idct_columns(
+    coefficients: &[i16; 64],
+    quantization_table: &[u16; 64],
+    pass1_bits: usize,
+) -> [Wrapping<i32>; 64] {
     let mut temp = [Wrapping(0); 64];
 
     // columns
@@ -284,7 +337,7 @@ fn dequantize_and_idct_block_8x8_inner<'a, I>(
             && coefficients[i + 48] == 0
             && coefficients[i + 56] == 0
         {
-            let dcterm = dequantize(coefficients[i], quantization_table[i]) << 2;
+            let dcterm = dequantize(coefficients[i], quantization_table[i]) << pass1_bits;
             temp[i] = dcterm;
             temp[i + 8] = dcterm;
             temp[i + 16] = dcterm;
@@ -309,62 +362,77 @@ fn dequantize_and_idct_block_8x8_inner<'a, I>(
             } = kernel(
                 [s0, s1, s2, s3, s4, s5, s6, s7],
                 // constants scaled things up by 1<<12; let's bring them back
-                // down, but keep 2 extra bits of precision
-                512,
+                // down, but keep pass1_bits extra bits of precision
+                1 << (12 - pass1_bits - 1),
             );
 
-            temp[i] = (x0 + t3) >> 10;
-            temp[i + 56] = (x0 - t3) >> 10;
-            temp[i + 8] = (x1 + t2) >> 10;
-            temp[i + 48] = (x1 - t2) >> 10;
-            temp[i + 16] = (x2 + t1) >> 10;
-            temp[i + 40] = (x2 - t1) >> 10;
-            temp[i + 24] = (x3 + t0) >> 10;
-            temp[i + 32] = (x3 - t0) >> 10;
+            let shift = 12 - pass1_bits;
+            temp[i] = (x0 + t3) >> shift;
+            temp[i + 56] = (x0 - t3) >> shift;
+            temp[i + 8] = (x1 + t2) >> shift;
+            temp[i + 48] = (x1 - t2) >> shift;
+            temp[i + 16] = (x2 + t1) >> shift;
+            temp[i + 40] = (x2 - t1) >> shift;
+            temp[i + 24] = (x3 + t0) >> shift;
+            temp[i + 32] = (x3 - t0) >> shift;
         }
     }
 
-    for (chunk, output_chunk) in temp.chunks_exact(8).zip(output) {
-        let chunk = <&[_; 8]>::try_from(chunk).unwrap();
+    temp
+}
 
-        // constants scaled things up by 1<<12, plus we had 1<<2 from first
-        // loop, plus horizontal and vertical each scale by sqrt(8) so together
-        // we've got an extra 1<<3, so 1<<17 total we need to remove.
-        // so we want to round that, which means adding 0.5 * 1<<17,
-        // aka 65536. Also, we'll end up with -128 to 127 that we want
-        // to encode as 0..255 by adding 128, so we'll add that before the shift
-        const X_SCALE: i32 = 65536 + (128 << 17);
+/// Dequantizes and transforms a block like `dequantize_and_idct_block_8x8`, but without the level
+/// shift and clamping. Differential frames of hierarchical JPEGs code signed sample differences.
+pub(crate) fn dequantize_and_idct_block_8x8_signed(
+    coefficients: &[i16; 64],
+    quantization_table: &[u16; 64],
+    precision: u8,
+    output: &mut [i32; 64],
+) {
+    // Like libjpeg, keep one bit less between the passes for 12-bit samples so that the larger
+    // coefficients do not overflow.
+    let pass1_bits = if precision > 8 { 1 } else { 2 };
+    let temp = dequantize_and_idct_columns(coefficients, quantization_table, pass1_bits);
 
-        // eliminate downstream bounds checks
-        let output_chunk = &mut output_chunk[..8];
+    for (chunk, output_chunk) in temp.chunks_exact(8).zip(output.chunks_exact_mut(8)) {
+        let chunk = <&[_; 8]>::try_from(chunk).unwrap();
 
-        // TODO When the minimum rust version supports it
-        // let [s0, rest @ ..] = chunk;
-        let (s0, rest) = chunk.split_first().unwrap();
-        if *rest == [Wrapping(0); 7] {
-            let dcterm = stbi_clamp((stbi_fsh(*s0) + Wrapping(X_SCALE)) >> 17);
-            output_chunk[0] = dcterm;
-            output_chunk[1] = dcterm;
-            output_chunk[2] = dcterm;
-            output_chunk[3] = dcterm;
-            output_chunk[4] = dcterm;
-            output_chunk[5] = dcterm;
-            output_chunk[6] = dcterm;
-            output_chunk[7] = dcterm;
-        } else {
-            let Kernel {
-                xs: [x0, x1, x2, x3],
-                ts: [t0, t1, t2, t3],
-            } = kernel(*chunk, X_SCALE);
+        // Same scaling as in the level shifted version, only rounding is added before the shift.
+        let shift = 12 + pass1_bits + 3;
+        let x_scale = 1 << (shift - 1);
+
+        let Kernel {
+            xs: [x0, x1, x2, x3],
+            ts: [t0, t1, t2, t3],
+        } = kernel(*chunk, x_scale);
+
+        output_chunk[0] = ((x0 + t3) >> shift).0;
+        output_chunk[7] = ((x0 - t3) >> shift).0;
+        output_chunk[1] = ((x1 + t2) >> shift).0;
+        output_chunk[6] = ((x1 - t2) >> shift).0;
+        output_chunk[2] = ((x2 + t1) >> shift).0;
+        output_chunk[5] = ((x2 - t1) >> shift).0;
+        output_chunk[3] = ((x3 + t0) >> shift).0;
+        output_chunk[4] = ((x3 - t0) >> shift).0;
+    }
+}
 
-            output_chunk[0] = stbi_clamp((x0 + t3) >> 17);
-            output_chunk[7] = stbi_clamp((x0 - t3) >> 17);
-            output_chunk[1] = stbi_clamp((x1 + t2) >> 17);
-            output_chunk[6] = stbi_clamp((x1 - t2) >> 17);
-            output_chunk[2] = stbi_clamp((x2 + t1) >> 17);
-            output_chunk[5] = stbi_clamp((x2 - t1) >> 17);
-            output_chunk[3] = stbi_clamp((x3 + t0) >> 17);
-            output_chunk[4] = stbi_clamp((x3 - t0) >> 17);
+/// Dequantizes and transforms a block of a 12-bit frame into samples of 0..4095.
+pub(crate) fn dequantize_and_idct_block_8x8_12bit(
+    coefficients: &[i16; 64],
+    quantization_table: &[u16; 64],
+    output_linestride: usize,
+    output: &mut [u16],
+) {
+    let mut temp = [0i32; 64];
+    dequantize_and_idct_block_8x8_signed(coefficients, quantization_table, 12, &mut temp);
+
+    for (chunk, output_chunk) in temp
+        .chunks_exact(8)
+        .zip(output.chunks_mut(output_linestride))
+    {
+        for (&value, output) in chunk.iter().zip(&mut output_chunk[..8]) {
+            *output = (value + 2048).clamp(0, 4095) as u16;
         }
     }
 }
@@ -655,3 +723,42 @@ fn test_dequantize_and_idct_block_8x8_saturated() {
     ];
     assert_eq!(&output[..], &expected[..]);
 }
+
+#[test]
+fn test_dequantize_and_idct_block_8x8_signed() {
+    let mut coefficients = [0i16; 8 * 8];
+    coefficients[0] = -14;
+    coefficients[1] = 11;
+    coefficients[9] = -3;
+    let quantization_table = [8u16; 8 * 8];
+
+    let mut output = [0u8; 8 * 8];
+    dequantize_and_idct_block_8x8(&coefficients, &quantization_table, 8, &mut output);
+    let mut signed = [0i32; 8 * 8];
+    dequantize_and_idct_block_8x8_signed(&coefficients, &quantization_table, 8, &mut signed);
+
+    for i in 0..64 {
+        assert!((output[i] as i32 - (signed[i] + 128)).abs() <= 1);
+    }
+}
+
+#[test]
+fn test_dequantize_and_idct_block_8x8_12bit() {
+    let mut coefficients = [0i16; 8 * 8];
+    coefficients[0] = -14;
+    coefficients[1] = 11;
+    coefficients[9] = -3;
+    let quantization_table = [8u16; 8 * 8];
+
+    let mut output = [0u8; 8 * 8];
+    dequantize_and_idct_block_8x8(&coefficients, &quantization_table, 8, &mut output);
+
+    // The same block with 16 times the amplitude.
+    let quantization_table = [128u16; 8 * 8];
+    let mut output_12bit = [0u16; 8 * 8];
+    dequantize_and_idct_block_8x8_12bit(&coefficients, &quantization_table, 8, &mut output_12bit);
+
+    for i in 0..64 {
+        assert!((i32::from(output[i]) * 16 - i32::from(output_12bit[i])).abs() <= 16);
+    }
+}
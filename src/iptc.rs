@@ -0,0 +1,163 @@
+use crate::error::{Error, Result};
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// A dataset of IPTC-IIM metadata, stored in the IPTC-NAA Photoshop image resource.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IptcDataset<'a> {
+    /// The record number, such as 1 for the envelope record and 2 for the application record.
+    pub record: u8,
+    /// The dataset number within the record.
+    pub number: u8,
+    /// The data of the dataset.
+    pub data: &'a [u8],
+}
+
+/// IPTC-IIM metadata, such as the caption, keywords and copyright of news photos.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Iptc<'a> {
+    datasets: Vec<IptcDataset<'a>>,
+    utf8: bool,
+}
+
+impl<'a> Iptc<'a> {
+    /// Parses the datasets of an IPTC-NAA record, as returned by `ImageResource::iptc_data`.
+    pub fn parse(data: &'a [u8]) -> Result<Iptc<'a>> {
+        let truncated = || Error::Format("truncated IPTC dataset".to_owned());
+        let mut datasets = Vec::new();
+        let mut data = data;
+
+        // IPTC-IIM Version 4.2, Chapter 6: Envelope and Application Records. Photoshop pads the
+        // resource with zeros.
+        while data.iter().any(|&byte| byte != 0) {
+            if data.len() < 5 || data[0] != 0x1C {
+                return Err(Error::Format("IPTC dataset without tag marker".to_owned()));
+            }
+
+            let mut length = usize::from(u16::from_be_bytes([data[3], data[4]]));
+            let mut start = 5;
+
+            // Extended datasets give the size of their length field instead.
+            if length & 0x8000 != 0 {
+                let size = length & 0x7FFF;
+                if size > core::mem::size_of::<usize>() {
+                    return Err(Error::Format("IPTC dataset too large".to_owned()));
+                }
+
+                let field = data.get(5..5 + size).ok_or_else(truncated)?;
+                length = field
+                    .iter()
+                    .fold(0, |length, &byte| length << 8 | usize::from(byte));
+                start += size;
+            }
+
+            let end = start.checked_add(length).ok_or_else(truncated)?;
+            datasets.push(IptcDataset {
+                record: data[1],
+                number: data[2],
+                data: data.get(start..end).ok_or_else(truncated)?,
+            });
+            data = &data[end..];
+        }
+
+        // CodedCharacterSet, the ISO 2022 escape sequence of UTF-8
+        let utf8 = datasets.iter().any(|dataset| {
+            (dataset.record, dataset.number) == (1, 90) && dataset.data == b"\x1B%G"
+        });
+
+        Ok(Iptc { datasets, utf8 })
+    }
+
+    /// Returns all datasets in the order they are stored.
+    pub fn datasets(&self) -> &[IptcDataset<'a>] {
+        &self.datasets
+    }
+
+    /// Returns the datasets with the record and dataset number, of which some can be repeated.
+    pub fn get(&self, record: u8, number: u8) -> impl Iterator<Item = &IptcDataset<'a>> {
+        self.datasets
+            .iter()
+            .filter(move |dataset| dataset.record == record && dataset.number == number)
+    }
+
+    /// Returns the text of the first dataset with the record and dataset number.
+    ///
+    /// The text is decoded as UTF-8 if the CodedCharacterSet (1:90) dataset says so. Otherwise
+    /// it is decoded as UTF-8 if it is valid UTF-8, and as ISO 8859-1 if it is not.
+    pub fn text(&self, record: u8, number: u8) -> Option<String> {
+        self.get(record, number)
+            .next()
+            .map(|dataset| self.decode_text(dataset.data))
+    }
+
+    /// Returns the texts of all datasets with the record and dataset number, see `text`.
+    pub fn texts(&self, record: u8, number: u8) -> Vec<String> {
+        self.get(record, number)
+            .map(|dataset| self.decode_text(dataset.data))
+            .collect()
+    }
+
+    /// Returns the Object Name (2:05), a shorthand reference for the object.
+    pub fn title(&self) -> Option<String> {
+        self.text(2, 5)
+    }
+
+    /// Returns the Keywords (2:25).
+    pub fn keywords(&self) -> Vec<String> {
+        self.texts(2, 25)
+    }
+
+    /// Returns the Date Created (2:55) in the form `CCYYMMDD`.
+    pub fn date_created(&self) -> Option<String> {
+        self.text(2, 55)
+    }
+
+    /// Returns the By-lines (2:80), the names of the creators.
+    pub fn by_lines(&self) -> Vec<String> {
+        self.texts(2, 80)
+    }
+
+    /// Returns the City (2:90).
+    pub fn city(&self) -> Option<String> {
+        self.text(2, 90)
+    }
+
+    /// Returns the Country/Primary Location Name (2:101).
+    pub fn country(&self) -> Option<String> {
+        self.text(2, 101)
+    }
+
+    /// Returns the Headline (2:105).
+    pub fn headline(&self) -> Option<String> {
+        self.text(2, 105)
+    }
+
+    /// Returns the Credit (2:110), the provider of the object.
+    pub fn credit(&self) -> Option<String> {
+        self.text(2, 110)
+    }
+
+    /// Returns the Source (2:115), the original owner of the object.
+    pub fn source(&self) -> Option<String> {
+        self.text(2, 115)
+    }
+
+    /// Returns the Copyright Notice (2:116).
+    pub fn copyright_notice(&self) -> Option<String> {
+        self.text(2, 116)
+    }
+
+    /// Returns the Caption/Abstract (2:120).
+    pub fn caption(&self) -> Option<String> {
+        self.text(2, 120)
+    }
+
+    fn decode_text(&self, data: &[u8]) -> String {
+        match core::str::from_utf8(data) {
+            Ok(text) => text.to_owned(),
+            Err(_) if self.utf8 => String::from_utf8_lossy(data).into_owned(),
+            Err(_) => data.iter().map(|&byte| char::from(byte)).collect(),
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::error::{Error, Result};
+use crate::parser::JumbfSegment;
+use alloc::borrow::ToOwned;
+use alloc::vec::Vec;
+use core::str;
+
+/// A box of JPEG universal metadata box format (JUMBF) data, such as the C2PA manifest store.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JumbfBox<'a> {
+    /// The box type, `jumb` for superboxes.
+    pub box_type: [u8; 4],
+    /// The contents of the box, following the box header.
+    pub data: &'a [u8],
+    /// The description box of a superbox.
+    pub description: Option<JumbfDescription<'a>>,
+    /// The boxes contained in a superbox, following the description box.
+    pub children: Vec<JumbfBox<'a>>,
+}
+
+/// The description box (`jumd`) of a JUMBF superbox.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct JumbfDescription<'a> {
+    /// The UUID of the content type of the superbox, such as `6332706100110010800000AA00389B71`
+    /// for a C2PA manifest store.
+    pub content_type: [u8; 16],
+    /// The label of the superbox, such as `c2pa` for the C2PA manifest store.
+    pub label: Option<&'a str>,
+    /// The ID of the superbox.
+    pub id: Option<u32>,
+}
+
+// Superboxes nested deeper than this are rejected.
+const MAX_DEPTH: usize = 32;
+
+impl<'a> JumbfBox<'a> {
+    /// Parses a sequence of boxes, such as the one returned by `Decoder::jumbf_data`, descending
+    /// into superboxes.
+    pub fn parse_all(data: &'a [u8]) -> Result<Vec<JumbfBox<'a>>> {
+        parse_boxes(data, 0)
+    }
+
+    /// Returns the label of a superbox.
+    pub fn label(&self) -> Option<&'a str> {
+        self.description.and_then(|description| description.label)
+    }
+
+    /// Returns the first superbox contained in this one with the label.
+    pub fn find(&self, label: &str) -> Option<&JumbfBox<'a>> {
+        self.children
+            .iter()
+            .find(|child| child.label() == Some(label))
+    }
+}
+
+// ISO/IEC 19566-5 Section 4: Box structure
+fn parse_boxes(mut data: &[u8], depth: usize) -> Result<Vec<JumbfBox<'_>>> {
+    if depth > MAX_DEPTH {
+        return Err(Error::Format(
+            "JUMBF superboxes nested too deeply".to_owned(),
+        ));
+    }
+
+    let mut boxes = Vec::new();
+
+    while !data.is_empty() {
+        let (box_type, contents, size) = read_box(data)?;
+        let mut jumbf_box = JumbfBox {
+            box_type,
+            data: contents,
+            description: None,
+            children: Vec::new(),
+        };
+
+        if box_type == *b"jumb" {
+            let mut children = parse_boxes(contents, depth + 1)?;
+
+            if children.is_empty() || children[0].box_type != *b"jumd" {
+                return Err(Error::Format(
+                    "JUMBF superbox without description box".to_owned(),
+                ));
+            }
+
+            jumbf_box.description = Some(parse_description(children[0].data)?);
+            children.remove(0);
+            jumbf_box.children = children;
+        }
+
+        boxes.push(jumbf_box);
+        data = &data[size..];
+    }
+
+    Ok(boxes)
+}
+
+// Returns the type, the contents and the total size of the box at the start of the data.
+fn read_box(data: &[u8]) -> Result<([u8; 4], &[u8], usize)> {
+    let truncated = || Error::Format("truncated JUMBF box".to_owned());
+    let header = data.get(0..8).ok_or_else(truncated)?;
+    let box_type = [header[4], header[5], header[6], header[7]];
+
+    let (header_size, size) = match u32::from_be_bytes([header[0], header[1], header[2], header[3]])
+    {
+        // The box extends to the end of the data.
+        0 => (8, data.len()),
+        // The size is given by the XLBox field.
+        1 => {
+            let size = data.get(8..16).ok_or_else(truncated)?;
+            let size = u64::from_be_bytes(size.try_into().unwrap());
+            (16, usize::try_from(size).map_err(|_| truncated())?)
+        }
+        size => (8, size as usize),
+    };
+
+    if size < header_size || size > data.len() {
+        return Err(truncated());
+    }
+
+    Ok((box_type, &data[header_size..size], size))
+}
+
+// ISO/IEC 19566-5 Section B.2: JUMBF Description box
+fn parse_description(data: &[u8]) -> Result<JumbfDescription<'_>> {
+    let invalid = || Error::Format("invalid JUMBF description box".to_owned());
+    let content_type = data.get(0..16).ok_or_else(invalid)?.try_into().unwrap();
+    let toggles = *data.get(16).ok_or_else(invalid)?;
+    let mut rest = &data[17..];
+
+    let label = if toggles & 0x02 != 0 {
+        let end = rest
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(invalid)?;
+        let label = str::from_utf8(&rest[..end]).map_err(|_| invalid())?;
+        rest = &rest[end + 1..];
+        Some(label)
+    } else {
+        None
+    };
+
+    let id = if toggles & 0x04 != 0 {
+        let id = rest.get(0..4).ok_or_else(invalid)?;
+        Some(u32::from_be_bytes(id.try_into().unwrap()))
+    } else {
+        None
+    };
+
+    Ok(JumbfDescription {
+        content_type,
+        label,
+        id,
+    })
+}
+
+// ISO/IEC 19566-5 Annex B.2: Joins the segments of each box instance in the order of their
+// packet sequence numbers. Every segment repeats the box header, which is only kept once.
+pub(crate) fn reassemble(segments: &[JumbfSegment]) -> Result<Vec<u8>> {
+    let mut segments: Vec<&JumbfSegment> = segments.iter().collect();
+    segments.sort_by_key(|segment| (segment.instance, segment.sequence));
+
+    let mut data = Vec::new();
+    let mut start = 0;
+
+    for (index, segment) in segments.iter().enumerate() {
+        let first = index == 0 || segments[index - 1].instance != segment.instance;
+
+        if first {
+            if segment.sequence != 1 {
+                return Err(Error::Format(
+                    "missing first segment of JUMBF box".to_owned(),
+                ));
+            }
+
+            start = data.len();
+            data.extend_from_slice(&segment.data);
+        } else {
+            if segments[index - 1].sequence.checked_add(1) != Some(segment.sequence) {
+                return Err(Error::Format(
+                    "missing or duplicate segment of JUMBF box".to_owned(),
+                ));
+            }
+
+            let header_size = if data.get(start..start + 4) == Some(&[0, 0, 0, 1]) {
+                16
+            } else {
+                8
+            };
+            data.extend_from_slice(segment.data.get(header_size..).unwrap_or(&[]));
+        }
+
+        let last = segments
+            .get(index + 1)
+            .map_or(true, |next| next.instance != segment.instance);
+
+        if last {
+            let (_, _, size) = read_box(&data[start..])?;
+
+            if size != data.len() - start {
+                return Err(Error::Format(
+                    "JUMBF box size does not match its segments".to_owned(),
+                ));
+            }
+        }
+    }
+
+    Ok(data)
+}
@@ -36,22 +36,44 @@ extern crate core;
 #[cfg(feature = "rayon")]
 extern crate rayon;
 
-pub use decoder::{ColorTransform, Decoder, ImageInfo, PixelFormat};
+pub use decoder::{
+    AppSegment, ColorManagement, ColorTransform, Decoder, GainMap, GainMapMetadata, HdrFormat,
+    ImageInfo, Orientation, OutputLayout, PixelFormat,
+};
 pub use error::{Error, UnsupportedFeature};
-pub use parser::CodingProcess;
+pub use exif::{Exif, ExifField, ExifIfd, ExifValue, Rational, SRational};
+pub use icc::{IccColorSpace, IccError, IccProfile, IccProfileClass, IccRenderingIntent};
+pub use iptc::{Iptc, IptcDataset};
+pub use jumbf::{JumbfBox, JumbfDescription};
+pub use parser::{
+    AdobeColorTransform, CodingProcess, DensityUnit, JfifHeader, JfifThumbnail, MpImage,
+    MpImageType, PixelDensity,
+};
+pub use psir::{
+    BezierKnot, ImageResource, ResolutionInfo, ResourcePath, ResourceThumbnail, Subpath,
+};
 
 use std::io;
 
 #[cfg(not(feature = "platform_independent"))]
 mod arch;
+mod arithmetic;
+mod cms;
 mod decoder;
 mod error;
+mod exif;
 mod huffman;
+mod icc;
 mod idct;
+mod iptc;
+mod jumbf;
 mod marker;
 mod parser;
+mod psir;
+mod tiff;
 mod upsampler;
 mod worker;
+mod xmp;
 
 fn read_u8<R: io::Read>(reader: &mut R) -> io::Result<u8> {
     let mut buf = [0];
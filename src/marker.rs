@@ -18,6 +18,7 @@ pub enum Marker {
     /// - SOF(13): Differential sequential DCT (arithmetic coding)
     /// - SOF(14): Differential progressive DCT (arithmetic coding)
     /// - SOF(15): Differential lossless (sequential) (arithmetic coding)
+    /// - SOF(55): JPEG-LS (ITU-T T.87)
     SOF(u8),
     /// Reserved for JPEG extensions
     JPG,
@@ -45,6 +46,8 @@ pub enum Marker {
     EXP,
     /// Reserved for application segments
     APP(u8),
+    /// JPEG-LS preset parameters (ITU-T T.87)
+    LSE,
     /// Reserved for JPEG extensions
     JPGn(u8),
     /// Comment
@@ -122,8 +125,8 @@ impl Marker {
             0xF4 => Some(JPGn(4)),
             0xF5 => Some(JPGn(5)),
             0xF6 => Some(JPGn(6)),
-            0xF7 => Some(JPGn(7)),
-            0xF8 => Some(JPGn(8)),
+            0xF7 => Some(SOF(55)),
+            0xF8 => Some(LSE),
             0xF9 => Some(JPGn(9)),
             0xFA => Some(JPGn(10)),
             0xFB => Some(JPGn(11)),
@@ -4,10 +4,12 @@ use alloc::vec::Vec;
 use core::ops::{self, Range};
 use std::io::{self, Read};
 use crate::{read_u16_from_be, read_u8};
+use crate::arithmetic::{AcConditioning, DcConditioning};
 use crate::error::{Error, Result, UnsupportedFeature};
 use crate::huffman::{HuffmanTable, HuffmanTableClass};
 use crate::marker::Marker;
 use crate::marker::Marker::*;
+use crate::tiff::{Tiff, TYPE_UNDEFINED};
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Dimensions {
@@ -19,6 +21,8 @@ pub struct Dimensions {
 pub enum EntropyCoding {
     Huffman,
     Arithmetic,
+    // ITU-T T.87
+    JpegLs,
 }
 
 /// Represents the coding process of an image.
@@ -28,7 +32,7 @@ pub enum CodingProcess {
     DctSequential,
     /// Progressive Discrete Cosine Transform
     DctProgressive,
-    /// Lossless
+    /// Lossless, also used for JPEG-LS frames including near-lossless ones
     Lossless,
 }
 
@@ -60,6 +64,14 @@ pub struct FrameInfo {
     pub components: Vec<Component>,
 }
 
+// Section B.3.2
+#[derive(Clone, Debug)]
+pub struct HierarchicalInfo {
+    pub precision: u8,
+    pub image_size: Dimensions,
+    pub components: Vec<Component>,
+}
+
 #[derive(Debug)]
 pub struct ScanInfo {
     pub component_indices: Vec<usize>,
@@ -71,6 +83,17 @@ pub struct ScanInfo {
     pub successive_approximation_high: u8,
     pub successive_approximation_low: u8,
     pub point_transform: u8, // for lossless
+    pub near_lossless: u8, // for JPEG-LS
+    pub interleave_mode: InterleaveMode, // for JPEG-LS
+    pub mapping_table_indices: Vec<u8>, // for JPEG-LS
+}
+
+// ITU-T T.87 Section C.2.3
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InterleaveMode {
+    None,
+    Line,
+    Sample,
 }
 
 #[derive(Clone, Debug)]
@@ -91,24 +114,167 @@ pub struct Component {
 #[derive(Debug)]
 pub enum AppData {
     Adobe(AdobeColorTransform),
-    Jfif,
+    // The header of the JFIF APP0 segment and its thumbnail, if it has one.
+    Jfif(JfifHeader, Option<JfifThumbnail>),
+    Jfxx(JfifThumbnail),
     Avi1,
     Icc(IccChunk),
     Exif(Vec<u8>),
     Xmp(Vec<u8>),
+    ExtendedXmp(ExtendedXmpChunk),
     Psir(Vec<u8>),
+    Jumbf(JumbfSegment),
+    // Images of the MP Index IFD, their offsets are relative to the start of the MP header.
+    Mpf(Vec<MpImage>),
 }
 
 // http://www.sno.phy.queensu.ca/~phil/exiftool/TagNames/JPEG.html#Adobe
+/// The colour transform given by the Adobe APP14 segment.
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum AdobeColorTransform {
-    // RGB or CMYK
+    /// No transform, the components are RGB or CMYK (0).
     Unknown,
+    /// YCbCr (1)
     YCbCr,
-    // YCbCrK
+    /// YCbCr and K, stored like CMYK by Adobe applications (2).
     YCCK,
 }
+
+// ITU-T T.87 Section C.2.4.1
+#[derive(Debug)]
+pub enum LseData {
+    PresetParameters(PresetParameters),
+    MappingTable {
+        table_id: u8,
+        entry_width: u8,
+        entries: Vec<u8>,
+        continuation: bool,
+    },
+    // libjpeg 9's reversible colour transform, red and blue are stored minus green
+    SubtractGreen,
+}
+
+// ITU-T T.87 Section C.2.4.1.1, zero selects the default value of a parameter.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PresetParameters {
+    pub max_value: u16,
+    pub thresholds: [u16; 3],
+    pub reset: u16,
+}
+
+/// The version and pixel density given by the JFIF APP0 segment.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JfifHeader {
+    /// The major version of the JFIF specification, 1 for all published versions.
+    pub major_version: u8,
+    /// The minor version of the JFIF specification, such as 2 for version 1.02.
+    pub minor_version: u8,
+    /// The pixel density of the image.
+    pub density: PixelDensity,
+}
+
+/// The number of pixels per unit of length, or the pixel aspect ratio if there is no unit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PixelDensity {
+    /// The unit the density is given in.
+    pub unit: DensityUnit,
+    /// The horizontal pixel density.
+    pub x: f32,
+    /// The vertical pixel density.
+    pub y: f32,
+}
+
+/// The unit of a `PixelDensity`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DensityUnit {
+    /// No unit, the densities only give the pixel aspect ratio
+    None,
+    /// Pixels per inch
+    PixelsPerInch,
+    /// Pixels per centimetre
+    PixelsPerCentimeter,
+}
+
+/// A thumbnail stored in the JFIF APP0 segment or in a JFXX extension APP0 segment.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JfifThumbnail {
+    /// A JPEG compressed thumbnail, starting with the SOI marker.
+    Jpeg(Vec<u8>),
+    /// A thumbnail stored with one byte palette indices, expanded to 3 bytes RGB per pixel.
+    Palette {
+        /// The width of the thumbnail.
+        width: u8,
+        /// The height of the thumbnail.
+        height: u8,
+        /// The pixels of the thumbnail in `PixelFormat::RGB24` format.
+        data: Vec<u8>,
+    },
+    /// A thumbnail stored with 3 bytes RGB per pixel.
+    Rgb {
+        /// The width of the thumbnail.
+        width: u8,
+        /// The height of the thumbnail.
+        height: u8,
+        /// The pixels of the thumbnail in `PixelFormat::RGB24` format.
+        data: Vec<u8>,
+    },
+}
+
+/// An image listed in the MP Index IFD of a Multi-Picture Format (CIPA DC-007) file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MpImage {
+    /// The type of the image.
+    pub image_type: MpImageType,
+    /// Whether this is the image to display by default.
+    pub is_representative: bool,
+    /// The offset of the image in bytes from the start of the file, which is also the start of the
+    /// primary image.
+    pub offset: u64,
+    /// The size of the image in bytes.
+    pub size: u32,
+}
+
+/// The MP Type of an image in a Multi-Picture Format file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MpImageType {
+    /// Baseline MP primary image
+    BaselinePrimary,
+    /// Large thumbnail, the class from 1 to 5 stands for a size equivalent to VGA, full HD, 4K,
+    /// 8K and 16K
+    LargeThumbnail(u8),
+    /// Multi-frame image, one of a panorama
+    Panorama,
+    /// Multi-frame image, one view of a disparity (stereo) image
+    Disparity,
+    /// Multi-frame image, one view of a multi-angle image
+    MultiAngle,
+    /// Original preservation image
+    OriginalPreservation,
+    /// Gain map image
+    GainMap,
+    /// Undefined type, used by the gain map of Ultra HDR images
+    Undefined,
+    /// Any other MP Type code
+    Other(u32),
+}
+
+impl MpImageType {
+    fn from_code(code: u32) -> MpImageType {
+        match code {
+            0x030000 => MpImageType::BaselinePrimary,
+            0x010001 ..= 0x010005 => MpImageType::LargeThumbnail(code as u8),
+            0x020001 => MpImageType::Panorama,
+            0x020002 => MpImageType::Disparity,
+            0x020003 => MpImageType::MultiAngle,
+            0x040000 => MpImageType::OriginalPreservation,
+            0x050000 => MpImageType::GainMap,
+            0x000000 => MpImageType::Undefined,
+            code => MpImageType::Other(code),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct IccChunk {
     pub num_markers: u8,
@@ -116,13 +282,31 @@ pub struct IccChunk {
     pub data: Vec<u8>,
 }
 
+// A part of the extended XMP packet identified by the GUID, the MD5 digest of the whole packet
+// written as hexadecimal digits.
+#[derive(Debug)]
+pub struct ExtendedXmpChunk {
+    pub guid: [u8; 32],
+    pub full_length: u32,
+    pub offset: u32,
+    pub data: Vec<u8>,
+}
+
+// ISO/IEC 19566-5 Annex B.2: A part of a JUMBF box stored in an APP11 segment.
+#[derive(Debug)]
+pub struct JumbfSegment {
+    pub instance: u16,
+    pub sequence: u32,
+    pub data: Vec<u8>,
+}
+
 impl FrameInfo {
     pub(crate) fn update_idct_size(&mut self, idct_size: usize) -> Result<()> {
         for component in &mut self.components {
             component.dct_scale = idct_size;
         }
 
-        update_component_sizes(self.image_size, &mut self.components)?;
+        self.mcu_size = update_component_sizes(self.image_size, &mut self.components)?;
 
         self.output_size = Dimensions {
             width: (self.image_size.width as f32 * idct_size as f32 / 8.0).ceil() as u16,
@@ -167,19 +351,20 @@ pub fn parse_sof<R: Read>(reader: &mut R, marker: Marker) -> Result<FrameInfo> {
 
     let is_baseline = marker == SOF(0);
     let is_differential = match marker {
-        SOF(0 ..= 3) | SOF(9 ..= 11)  => false,
-        SOF(5 ..= 7) | SOF(13 ..= 15) => true,
+        SOF(0 ..= 3) | SOF(9 ..= 11) | SOF(55) => false,
+        SOF(5 ..= 7) | SOF(13 ..= 15)          => true,
         _ => panic!(),
     };
     let coding_process = match marker {
-        SOF(0) | SOF(1) | SOF(5) | SOF(9) | SOF(13) => CodingProcess::DctSequential,
-        SOF(2) | SOF(6) | SOF(10) | SOF(14)         => CodingProcess::DctProgressive,
-        SOF(3) | SOF(7) | SOF(11) | SOF(15)         => CodingProcess::Lossless,
+        SOF(0) | SOF(1) | SOF(5) | SOF(9) | SOF(13)   => CodingProcess::DctSequential,
+        SOF(2) | SOF(6) | SOF(10) | SOF(14)           => CodingProcess::DctProgressive,
+        SOF(3) | SOF(7) | SOF(11) | SOF(15) | SOF(55) => CodingProcess::Lossless,
         _ => panic!(),
     };
     let entropy_coding = match marker {
         SOF(0 ..= 3) | SOF(5 ..= 7)     => EntropyCoding::Huffman,
         SOF(9 ..= 11) | SOF(13 ..= 15)  => EntropyCoding::Arithmetic,
+        SOF(55)                         => EntropyCoding::JpegLs,
         _ => panic!(),
     };
 
@@ -205,9 +390,7 @@ pub fn parse_sof<R: Read>(reader: &mut R, marker: Marker) -> Result<FrameInfo> {
     // height:
     // "Value 0 indicates that the number of lines shall be defined by the DNL marker and
     //     parameters at the end of the first scan (see B.2.5)."
-    if height == 0 {
-        return Err(Error::Unsupported(UnsupportedFeature::DNL));
-    }
+    // The heights of the frame and its components stay zero until the DNL marker is read.
 
     if width == 0 {
         return Err(Error::Format("zero width in frame header".to_owned()));
@@ -289,18 +472,21 @@ fn ceil_div(x: u32, y: u32) -> Result<u16> {
     Ok((1 + ((x - 1) / y)) as u16)
 }
 
-fn update_component_sizes(size: Dimensions, components: &mut [Component]) -> Result<Dimensions> {
+pub fn update_component_sizes(size: Dimensions, components: &mut [Component]) -> Result<Dimensions> {
     let h_max = components.iter().map(|c| c.horizontal_sampling_factor).max().unwrap() as u32;
     let v_max = components.iter().map(|c| c.vertical_sampling_factor).max().unwrap() as u32;
 
+    // A height of zero is not known yet, see parse_sof.
+    let ceil_div_height = |x: u32, y: u32| if size.height == 0 { Ok(0) } else { ceil_div(x, y) };
+
     let mcu_size = Dimensions {
         width: ceil_div(size.width as u32, h_max * 8)?,
-        height: ceil_div(size.height as u32, v_max * 8)?,
+        height: ceil_div_height(size.height as u32, v_max * 8)?,
     };
 
     for component in components {
         component.size.width = ceil_div(size.width as u32 * component.horizontal_sampling_factor as u32 * component.dct_scale as u32, h_max * 8)?;
-        component.size.height = ceil_div(size.height as u32 * component.vertical_sampling_factor as u32 * component.dct_scale as u32, v_max * 8)?;
+        component.size.height = ceil_div_height(size.height as u32 * component.vertical_sampling_factor as u32 * component.dct_scale as u32, v_max * 8)?;
 
         component.block_size.width = mcu_size.width * component.horizontal_sampling_factor as u16;
         component.block_size.height = mcu_size.height * component.vertical_sampling_factor as u16;
@@ -348,6 +534,7 @@ pub fn parse_sos<R: Read>(reader: &mut R, frame: &FrameInfo) -> Result<ScanInfo>
     let mut component_indices = Vec::with_capacity(component_count as usize);
     let mut dc_table_indices = Vec::with_capacity(component_count as usize);
     let mut ac_table_indices = Vec::with_capacity(component_count as usize);
+    let mut mapping_table_indices = Vec::new();
 
     for _ in 0 .. component_count {
         let identifier = read_u8(reader)?;
@@ -368,6 +555,15 @@ pub fn parse_sos<R: Read>(reader: &mut R, frame: &FrameInfo) -> Result<ScanInfo>
         }
 
         let byte = read_u8(reader)?;
+
+        // ITU-T T.87 Section C.2.3
+        // JPEG-LS scans select a mapping table instead of entropy coding tables.
+        if frame.entropy_coding == EntropyCoding::JpegLs {
+            component_indices.push(component_index);
+            mapping_table_indices.push(byte);
+            continue;
+        }
+
         let dc_table_index = byte >> 4;
         let ac_table_index = byte & 0x0f;
 
@@ -404,6 +600,8 @@ pub fn parse_sos<R: Read>(reader: &mut R, frame: &FrameInfo) -> Result<ScanInfo>
     // coding. Don't confuse with the JPEG-LS parameter coded using the same scan info portion.
     let predictor_selection;
     let point_transform = successive_approximation_low;
+    let mut near_lossless = 0;
+    let mut interleave_mode = InterleaveMode::None;
 
     if point_transform >= frame.precision {
         return Err(Error::Format(
@@ -411,7 +609,30 @@ pub fn parse_sos<R: Read>(reader: &mut R, frame: &FrameInfo) -> Result<ScanInfo>
         ));
     }
 
-    if frame.coding_process == CodingProcess::DctProgressive {
+    if frame.entropy_coding == EntropyCoding::JpegLs {
+        // ITU-T T.87 Section C.2.3
+        predictor_selection = Predictor::NoPrediction;
+        near_lossless = spectral_selection_start;
+        interleave_mode = match spectral_selection_end {
+            0 => InterleaveMode::None,
+            1 => InterleaveMode::Line,
+            2 => InterleaveMode::Sample,
+            _ => {
+                return Err(Error::Format(format!("invalid interleave mode {} in JPEG-LS scan", spectral_selection_end)));
+            },
+        };
+
+        if interleave_mode == InterleaveMode::None && component_count != 1 {
+            return Err(Error::Format("non-interleaved JPEG-LS scan with more than one component".to_owned()));
+        }
+        if successive_approximation_high != 0 {
+            return Err(Error::Format("successive approximation high shall be zero in JPEG-LS scan".to_owned()));
+        }
+        if point_transform != 0 {
+            return Err(Error::Unsupported(UnsupportedFeature::PointTransform));
+        }
+    }
+    else if frame.coding_process == CodingProcess::DctProgressive {
         predictor_selection = Predictor::NoPrediction;
         if spectral_selection_end > 63 || spectral_selection_start > spectral_selection_end ||
                 (spectral_selection_start == 0 && spectral_selection_end != 0) {
@@ -478,6 +699,9 @@ pub fn parse_sos<R: Read>(reader: &mut R, frame: &FrameInfo) -> Result<ScanInfo>
         successive_approximation_high,
         successive_approximation_low,
         point_transform,
+        near_lossless,
+        interleave_mode,
+        mapping_table_indices,
     })
 }
 
@@ -588,6 +812,169 @@ pub fn parse_dht<R: Read>(reader: &mut R, is_baseline: Option<bool>) -> Result<(
     Ok((dc_tables, ac_tables))
 }
 
+// Section B.2.4.3
+#[allow(clippy::type_complexity)]
+pub fn parse_dac<R: Read>(reader: &mut R) -> Result<([Option<DcConditioning>; 4], [Option<AcConditioning>; 4])> {
+    let mut length = read_length(reader, DAC)?;
+    let mut dc_tables = [None; 4];
+    let mut ac_tables = [None; 4];
+
+    if length % 2 != 0 {
+        return Err(Error::Format("invalid length in DAC".to_owned()));
+    }
+
+    // Each DAC segment may contain multiple conditioning tables.
+    while length > 0 {
+        let byte = read_u8(reader)?;
+        let class = byte >> 4;
+        let index = (byte & 0x0f) as usize;
+        let value = read_u8(reader)?;
+
+        if index > 3 {
+            return Err(Error::Format(format!("invalid destination identifier {} in DAC", index)));
+        }
+
+        match class {
+            0 => {
+                let lower = value & 0x0f;
+                let upper = value >> 4;
+
+                if lower > upper {
+                    return Err(Error::Format(format!("invalid DC conditioning L={}, U={} in DAC", lower, upper)));
+                }
+
+                dc_tables[index] = Some(DcConditioning { lower, upper });
+            },
+            1 => {
+                if value == 0 || value > 63 {
+                    return Err(Error::Format(format!("invalid AC conditioning Kx={} in DAC", value)));
+                }
+
+                ac_tables[index] = Some(AcConditioning { kx: value });
+            },
+            _ => return Err(Error::Format(format!("invalid class {} in DAC", class))),
+        }
+
+        length -= 2;
+    }
+
+    Ok((dc_tables, ac_tables))
+}
+
+// Section B.3.2
+pub fn parse_dhp<R: Read>(reader: &mut R) -> Result<HierarchicalInfo> {
+    let length = read_length(reader, DHP)?;
+
+    if length <= 6 {
+        return Err(Error::Format("invalid length in DHP".to_owned()));
+    }
+
+    // The coding process is only known once the frames are parsed, they are checked to have the
+    // same precision as the DHP segment.
+    let precision = read_u8(reader)?;
+
+    if !(2..=16).contains(&precision) {
+        return Err(Error::Format(format!("invalid precision {} in DHP", precision)));
+    }
+
+    let height = read_u16_from_be(reader)?;
+    let width = read_u16_from_be(reader)?;
+
+    if height == 0 || width == 0 {
+        return Err(Error::Format("zero dimension in DHP".to_owned()));
+    }
+
+    let component_count = read_u8(reader)?;
+
+    if component_count == 0 {
+        return Err(Error::Format("zero component count in DHP".to_owned()));
+    }
+
+    if length != 6 + 3 * component_count as usize {
+        return Err(Error::Format("invalid length in DHP".to_owned()));
+    }
+
+    let mut components: Vec<Component> = Vec::with_capacity(component_count as usize);
+
+    for _ in 0 .. component_count {
+        let identifier = read_u8(reader)?;
+
+        if components.iter().any(|c| c.identifier == identifier) {
+            return Err(Error::Format(format!("duplicate DHP component identifier {}", identifier)));
+        }
+
+        let byte = read_u8(reader)?;
+        let horizontal_sampling_factor = byte >> 4;
+        let vertical_sampling_factor = byte & 0x0f;
+
+        if horizontal_sampling_factor == 0 || horizontal_sampling_factor > 4 {
+            return Err(Error::Format(format!("invalid horizontal sampling factor {}", horizontal_sampling_factor)));
+        }
+        if vertical_sampling_factor == 0 || vertical_sampling_factor > 4 {
+            return Err(Error::Format(format!("invalid vertical sampling factor {}", vertical_sampling_factor)));
+        }
+
+        // "Tqi: ... This parameter shall be set to zero in the DHP segment."
+        if read_u8(reader)? != 0 {
+            return Err(Error::Format("non-zero quantization table index in DHP".to_owned()));
+        }
+
+        components.push(Component {
+            identifier,
+            horizontal_sampling_factor,
+            vertical_sampling_factor,
+            quantization_table_index: 0,
+            dct_scale: 8,
+            size: Dimensions {width: 0, height: 0},
+            block_size: Dimensions {width: 0, height: 0},
+        });
+    }
+
+    update_component_sizes(Dimensions { width, height }, &mut components)?;
+
+    Ok(HierarchicalInfo {
+        precision,
+        image_size: Dimensions { width, height },
+        components,
+    })
+}
+
+// Section B.3.3
+pub fn parse_exp<R: Read>(reader: &mut R) -> Result<(bool, bool)> {
+    let length = read_length(reader, EXP)?;
+
+    if length != 1 {
+        return Err(Error::Format("EXP with invalid length".to_owned()));
+    }
+
+    let byte = read_u8(reader)?;
+    let horizontal = byte >> 4;
+    let vertical = byte & 0x0f;
+
+    if horizontal > 1 || vertical > 1 {
+        return Err(Error::Format(format!("invalid expansion {:#04x} in EXP", byte)));
+    }
+
+    Ok((horizontal == 1, vertical == 1))
+}
+
+// Section B.2.5
+pub fn parse_dnl<R: Read>(reader: &mut R) -> Result<u16> {
+    let length = read_length(reader, DNL)?;
+
+    if length != 2 {
+        return Err(Error::Format("DNL with invalid length".to_owned()));
+    }
+
+    let height = read_u16_from_be(reader)?;
+
+    if height == 0 {
+        return Err(Error::Format("zero number of lines in DNL".to_owned()));
+    }
+
+    Ok(height)
+}
+
 // Section B.2.4.4
 pub fn parse_dri<R: Read>(reader: &mut R) -> Result<u16> {
     let length = read_length(reader, DRI)?;
@@ -599,6 +986,235 @@ pub fn parse_dri<R: Read>(reader: &mut R) -> Result<u16> {
     Ok(read_u16_from_be(reader)?)
 }
 
+// ITU-T T.87 Section C.2.4.1
+pub fn parse_lse<R: Read>(reader: &mut R, frame: Option<&FrameInfo>) -> Result<LseData> {
+    let length = read_length(reader, LSE)?;
+
+    if length < 1 {
+        return Err(Error::Format("LSE with invalid length".to_owned()));
+    }
+
+    match read_u8(reader)? {
+        // Section C.2.4.1.1
+        1 => {
+            if length != 11 {
+                return Err(Error::Format("invalid length of preset coding parameters in LSE".to_owned()));
+            }
+
+            let max_value = read_u16_from_be(reader)?;
+            let threshold1 = read_u16_from_be(reader)?;
+            let threshold2 = read_u16_from_be(reader)?;
+            let threshold3 = read_u16_from_be(reader)?;
+            let reset = read_u16_from_be(reader)?;
+
+            Ok(LseData::PresetParameters(PresetParameters {
+                max_value,
+                thresholds: [threshold1, threshold2, threshold3],
+                reset,
+            }))
+        },
+        // Section C.2.4.1.2 and C.2.4.1.3
+        id @ (2 | 3) => {
+            if length < 3 {
+                return Err(Error::Format("invalid length of mapping table in LSE".to_owned()));
+            }
+
+            let table_id = read_u8(reader)?;
+            let entry_width = read_u8(reader)?;
+
+            if table_id == 0 {
+                return Err(Error::Format("invalid mapping table ID 0 in LSE".to_owned()));
+            }
+            if entry_width == 0 || (length - 3) % usize::from(entry_width) != 0 {
+                return Err(Error::Format(format!("invalid mapping table entry width {} in LSE", entry_width)));
+            }
+
+            let mut entries = vec![0u8; length - 3];
+            reader.read_exact(&mut entries)?;
+
+            Ok(LseData::MappingTable {
+                table_id,
+                entry_width,
+                entries,
+                continuation: id == 3,
+            })
+        },
+        // Section C.2.4.1.4
+        4 => Err(Error::Unsupported(UnsupportedFeature::OversizeDimensions)),
+        // Inverse colour transform specification of ITU-T T.870. libjpeg 9 writes it for its
+        // reversible colour transform and accepts only that one matrix: red and blue are stored
+        // with green subtracted.
+        13 => {
+            let frame = match frame {
+                Some(frame) => frame,
+                None => return Err(Error::Format("inverse colour transform in LSE before SOF".to_owned())),
+            };
+
+            if length != 22 || frame.components.len() < 3 {
+                return Err(Error::Format("invalid inverse colour transform in LSE".to_owned()));
+            }
+
+            let mut buffer = [0u8; 21];
+            reader.read_exact(&mut buffer)?;
+
+            let max_trans = (u16::from(buffer[0]) << 8) | u16::from(buffer[1]);
+            let identifiers = [frame.components[1].identifier, frame.components[0].identifier, frame.components[2].identifier];
+            // Flags and coefficients of the three transformed components, in the order given above.
+            let transform = [0x80, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0];
+
+            if max_trans != (1 << frame.precision) - 1
+                || buffer[2] != 3
+                || buffer[3..6] != identifiers
+                || buffer[6..] != transform
+            {
+                return Err(Error::Format("unsupported inverse colour transform in LSE".to_owned()));
+            }
+
+            Ok(LseData::SubtractGreen)
+        },
+        id => Err(Error::Format(format!("invalid ID {} in LSE", id))),
+    }
+}
+
+// CIPA DC-007 Section 5.2.3 MP Index IFD
+// Returns `None` for the MP Attribute IFD of images other than the first one, which has no index,
+// and for malformed ones.
+fn parse_mp_index(data: &[u8]) -> Option<Vec<MpImage>> {
+    let tiff = Tiff::new(data)?;
+    let (entries, _) = tiff.read_ifd(tiff.first_ifd_offset()?)?;
+
+    // MP Entry
+    let entry = entries.iter().find(|entry| entry.tag == 0xB002)?;
+    if entry.field_type != TYPE_UNDEFINED || entry.count % 16 != 0 {
+        return None;
+    }
+
+    let value = tiff.value(entry)?;
+    let images = (0 .. value.len()).step_by(16).map(|offset| {
+        let offset = entry.value_offset + offset;
+        let attribute = tiff.read_u32(offset)?;
+
+        Some(MpImage {
+            image_type: MpImageType::from_code(attribute & 0xFF_FFFF),
+            is_representative: attribute & (1 << 29) != 0,
+            offset: tiff.read_u32(offset + 8)?.into(),
+            size: tiff.read_u32(offset + 4)?,
+        })
+    });
+
+    images.collect()
+}
+
+// CIPA DC-008 Section 4.5.2: Returns the byte range of the JPEG compressed thumbnail of IFD1,
+// relative to the TIFF header.
+pub fn parse_exif_thumbnail(exif: &[u8]) -> Result<Option<Range<usize>>> {
+    let invalid = || Error::Format("invalid EXIF IFD".to_owned());
+    let tiff = Tiff::new(exif).ok_or_else(invalid)?;
+    let ifd0 = tiff.first_ifd_offset().ok_or_else(invalid)?;
+    let (_, ifd1) = tiff.read_ifd(ifd0).ok_or_else(invalid)?;
+
+    if ifd1 == 0 {
+        return Ok(None);
+    }
+
+    let (entries, _) = tiff.read_ifd(ifd1).ok_or_else(invalid)?;
+    let value = |tag| -> Result<Option<u32>> {
+        match entries.iter().find(|entry| entry.tag == tag) {
+            Some(entry) => tiff.value_u32(entry).map(Some).ok_or_else(invalid),
+            None => Ok(None),
+        }
+    };
+
+    // JPEGInterchangeFormat and JPEGInterchangeFormatLength
+    let (offset, length) = match (value(0x0201)?, value(0x0202)?) {
+        (Some(offset), Some(length)) => (offset as usize, length as usize),
+        _ => return Ok(None),
+    };
+
+    match offset.checked_add(length) {
+        Some(end) if offset > 0 && end <= exif.len() => Ok(Some(offset .. end)),
+        _ => Err(Error::Format("EXIF thumbnail lies outside of the APP1 segment".to_owned())),
+    }
+}
+
+// CIPA DC-008 Section 4.6.5: Returns the XResolution, YResolution and ResolutionUnit of IFD0.
+pub fn parse_exif_density(exif: &[u8]) -> Option<PixelDensity> {
+    let tiff = Tiff::new(exif)?;
+    let (entries, _) = tiff.read_ifd(tiff.first_ifd_offset()?)?;
+    let entry = |tag| entries.iter().find(|entry| entry.tag == tag);
+    let resolution = |tag| {
+        let (numerator, denominator) = tiff.value_rational(entry(tag)?)?;
+        if denominator == 0 {
+            return None;
+        }
+        Some(numerator as f32 / denominator as f32)
+    };
+
+    // The default ResolutionUnit is inches.
+    let unit = match entry(0x0128).map(|entry| tiff.value_u32(entry)) {
+        None => DensityUnit::PixelsPerInch,
+        Some(Some(1)) => DensityUnit::None,
+        Some(Some(2)) => DensityUnit::PixelsPerInch,
+        Some(Some(3)) => DensityUnit::PixelsPerCentimeter,
+        Some(_) => return None,
+    };
+
+    Some(PixelDensity {
+        unit,
+        x: resolution(0x011A)?,
+        y: resolution(0x011B)?,
+    })
+}
+
+// JFIF Version 1.02
+// Reads the width, height and 3 bytes RGB pixels of an uncompressed JFIF thumbnail, which are
+// absent if either dimension is zero.
+fn parse_rgb_thumbnail(data: &[u8]) -> Option<JfifThumbnail> {
+    let (width, height) = (data[0], data[1]);
+    let pixels = data.get(2..2 + usize::from(width) * usize::from(height) * 3)?;
+
+    if pixels.is_empty() {
+        return None;
+    }
+
+    Some(JfifThumbnail::Rgb {
+        width,
+        height,
+        data: pixels.to_vec(),
+    })
+}
+
+// JFIF Version 1.02, JFIF Extension: Thumbnail Coded Using JPEG, 1 Byte/Pixel and 3 Bytes/Pixel
+fn parse_jfxx_thumbnail(extension_code: u8, data: &[u8]) -> Option<JfifThumbnail> {
+    match extension_code {
+        0x10 => Some(JfifThumbnail::Jpeg(data.to_vec())),
+        0x11 => {
+            let (width, height) = (*data.first()?, *data.get(1)?);
+            let palette = data.get(2..2 + 256 * 3)?;
+            let pixel_count = usize::from(width) * usize::from(height);
+            let indices = data.get(2 + 256 * 3..2 + 256 * 3 + pixel_count)?;
+
+            if indices.is_empty() {
+                return None;
+            }
+
+            let pixels = indices
+                .iter()
+                .flat_map(|&index| &palette[usize::from(index) * 3..][..3])
+                .copied()
+                .collect();
+
+            Some(JfifThumbnail::Palette {
+                width,
+                height,
+                data: pixels,
+            })
+        }
+        0x13 if data.len() >= 2 => parse_rgb_thumbnail(data),
+        _ => None,
+    }
+}
+
 // Section B.2.4.5
 pub fn parse_com<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
     let length = read_length(reader, COM)?;
@@ -617,18 +1233,35 @@ pub fn parse_app<R: Read>(reader: &mut R, marker: Marker) -> Result<Option<AppDa
 
     match marker {
         APP(0) => {
-            if length >= 5 {
-                let mut buffer = [0u8; 5];
-                reader.read_exact(&mut buffer)?;
-                bytes_read = buffer.len();
+            let mut buffer = vec![0u8; length];
+            reader.read_exact(&mut buffer)?;
+            bytes_read = buffer.len();
 
-                // http://www.w3.org/Graphics/JPEG/jfif3.pdf
-                if buffer[0..5] == *b"JFIF\0" {
-                    result = Some(AppData::Jfif);
-                // https://sno.phy.queensu.ca/~phil/exiftool/TagNames/JPEG.html#AVI1
-                } else if buffer[0..5] == *b"AVI1\0" {
-                    result = Some(AppData::Avi1);
-                }
+            // http://www.w3.org/Graphics/JPEG/jfif3.pdf
+            if length >= 14 && buffer[0..5] == *b"JFIF\0" {
+                let header = JfifHeader {
+                    major_version: buffer[5],
+                    minor_version: buffer[6],
+                    density: PixelDensity {
+                        unit: match buffer[7] {
+                            1 => DensityUnit::PixelsPerInch,
+                            2 => DensityUnit::PixelsPerCentimeter,
+                            _ => DensityUnit::None,
+                        },
+                        x: f32::from(u16::from_be_bytes([buffer[8], buffer[9]])),
+                        y: f32::from(u16::from_be_bytes([buffer[10], buffer[11]])),
+                    },
+                };
+
+                result = Some(AppData::Jfif(header, parse_rgb_thumbnail(&buffer[12..])));
+            }
+            // JFIF Version 1.02, JFIF Extension APP0 Marker Segment
+            else if length >= 6 && buffer[0..5] == *b"JFXX\0" {
+                result = parse_jfxx_thumbnail(buffer[5], &buffer[6..]).map(AppData::Jfxx);
+            }
+            // https://sno.phy.queensu.ca/~phil/exiftool/TagNames/JPEG.html#AVI1
+            else if length >= 5 && buffer[0..5] == *b"AVI1\0" {
+                result = Some(AppData::Avi1);
             }
         }
         APP(1) => {
@@ -646,6 +1279,15 @@ pub fn parse_app<R: Read>(reader: &mut R, marker: Marker) -> Result<Option<AppDa
             else if length >= 29 && buffer[0..29] == *b"http://ns.adobe.com/xap/1.0/\0" {
                 result = Some(AppData::Xmp(buffer[29..].to_vec()));
             }
+            // Extended XMP, XMP Specification Part 3 Section 1.1.3.1
+            else if length >= 75 && buffer[0..35] == *b"http://ns.adobe.com/xmp/extension/\0" {
+                result = Some(AppData::ExtendedXmp(ExtendedXmpChunk {
+                    guid: buffer[35..67].try_into().unwrap(),
+                    full_length: u32::from_be_bytes(buffer[67..71].try_into().unwrap()),
+                    offset: u32::from_be_bytes(buffer[71..75].try_into().unwrap()),
+                    data: buffer[75..].to_vec(),
+                }));
+            }
         }
         APP(2) => {
             if length > 14 {
@@ -665,6 +1307,29 @@ pub fn parse_app<R: Read>(reader: &mut R, marker: Marker) -> Result<Option<AppDa
                         data,
                     }));
                 }
+                // CIPA DC-007 Section 5.2 Format of the MP Extensions
+                else if buffer[0..4] == *b"MPF\0" {
+                    let mut data = buffer[4..].to_vec();
+                    data.resize(length - 4, 0);
+                    reader.read_exact(&mut data[buffer.len() - 4..])?;
+                    bytes_read = length;
+                    result = parse_mp_index(&data).map(AppData::Mpf);
+                }
+            }
+        }
+        APP(11) => {
+            let mut buffer = vec![0u8; length];
+            reader.read_exact(&mut buffer)?;
+            bytes_read = buffer.len();
+
+            // ISO/IEC 19566-5 Annex B.2: The common identifier is followed by the box instance
+            // number and the packet sequence number.
+            if length >= 8 && buffer[0..2] == *b"JP" {
+                result = Some(AppData::Jumbf(JumbfSegment {
+                    instance: u16::from_be_bytes([buffer[2], buffer[3]]),
+                    sequence: u32::from_be_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]),
+                    data: buffer[8..].to_vec(),
+                }));
             }
         }
         APP(13) => {
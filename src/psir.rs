@@ -0,0 +1,252 @@
+use crate::error::{Error, Result};
+use alloc::borrow::ToOwned;
+use alloc::vec::Vec;
+
+/// A Photoshop image resource block, stored with the `8BIM` signature in APP13 segments.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageResource<'a> {
+    /// The resource ID.
+    pub id: u16,
+    /// The name of the resource, usually empty. Clipping paths are named after the path.
+    pub name: &'a [u8],
+    /// The data of the resource.
+    pub data: &'a [u8],
+}
+
+/// The resolution of an image, from the `ResolutionInfo` image resource.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResolutionInfo {
+    /// The horizontal resolution in pixels per inch.
+    pub horizontal_resolution: f32,
+    /// The unit the horizontal resolution is displayed in, 1 for pixels per inch and 2 for pixels
+    /// per centimetre.
+    pub horizontal_resolution_unit: u16,
+    /// The unit the width is displayed in, from 1 to 5 for inches, centimetres, points, picas and
+    /// columns.
+    pub width_unit: u16,
+    /// The vertical resolution in pixels per inch.
+    pub vertical_resolution: f32,
+    /// The unit the vertical resolution is displayed in.
+    pub vertical_resolution_unit: u16,
+    /// The unit the height is displayed in.
+    pub height_unit: u16,
+}
+
+/// A thumbnail image resource.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResourceThumbnail<'a> {
+    /// The width of the thumbnail.
+    pub width: u32,
+    /// The height of the thumbnail.
+    pub height: u32,
+    /// Whether the data is a JPEG image, otherwise it holds 24 bits per pixel RGB rows padded to
+    /// a multiple of 4 bytes.
+    pub is_jpeg: bool,
+    /// Whether the red and blue channels are swapped, as in the thumbnails of Photoshop 4.0.
+    pub is_bgr: bool,
+    /// The data of the thumbnail.
+    pub data: &'a [u8],
+}
+
+/// A path stored in an image resource, such as a clipping path.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResourcePath {
+    /// The subpaths of the path.
+    pub subpaths: Vec<Subpath>,
+}
+
+/// A subpath made of Bézier curves joining its knots.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Subpath {
+    /// Whether the last knot is joined to the first one.
+    pub closed: bool,
+    /// The knots of the subpath.
+    pub knots: Vec<BezierKnot>,
+}
+
+/// A knot of a subpath. The points are given as `(x, y)` fractions of the image width and height.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BezierKnot {
+    /// Whether the control points are linked, keeping the curve smooth when either is moved.
+    pub linked: bool,
+    /// The control point of the curve ending at the knot.
+    pub preceding: (f32, f32),
+    /// The anchor point of the knot.
+    pub anchor: (f32, f32),
+    /// The control point of the curve starting at the knot.
+    pub leaving: (f32, f32),
+}
+
+// Adobe Photoshop File Formats Specification, Image Resource IDs
+const RESOLUTION_INFO: u16 = 0x03ED;
+const IPTC_NAA: u16 = 0x0404;
+const THUMBNAIL_PHOTOSHOP_4: u16 = 0x0409;
+const THUMBNAIL: u16 = 0x040C;
+const PATHS: core::ops::RangeInclusive<u16> = 0x07D0..=0x0BB6;
+const CLIPPING_PATH_NAME: u16 = 0x0BB7;
+
+impl<'a> ImageResource<'a> {
+    /// Parses the image resource blocks of Photoshop APP13 data, as returned by
+    /// `Decoder::psir_data`.
+    pub fn parse_all(data: &'a [u8]) -> Result<Vec<ImageResource<'a>>> {
+        let truncated = || Error::Format("truncated image resource block".to_owned());
+        let mut resources = Vec::new();
+        let mut data = data;
+
+        // Some writers pad the APP13 segment with zeros.
+        while data.iter().any(|&byte| byte != 0) {
+            if data.len() < 4 || data[0..4] != *b"8BIM" {
+                return Err(Error::Format(
+                    "image resource block without 8BIM signature".to_owned(),
+                ));
+            }
+
+            let id = read_u16(data, 4).ok_or_else(truncated)?;
+            let name_length = usize::from(*data.get(6).ok_or_else(truncated)?);
+            let name = data.get(7..7 + name_length).ok_or_else(truncated)?;
+            // The name is a Pascal string padded to an even size.
+            let position = 7 + name_length + (name_length + 1) % 2;
+            let size = read_u32(data, position).ok_or_else(truncated)? as usize;
+            let start = position + 4;
+            let end = start.checked_add(size).ok_or_else(truncated)?;
+            let resource = data.get(start..end).ok_or_else(truncated)?;
+
+            resources.push(ImageResource {
+                id,
+                name,
+                data: resource,
+            });
+            // The data is padded to an even size as well.
+            data = end
+                .checked_add(size % 2)
+                .and_then(|next| data.get(next..))
+                .unwrap_or(&[]);
+        }
+
+        Ok(resources)
+    }
+
+    /// Returns the resolution if this is a `ResolutionInfo` (0x03ED) resource.
+    pub fn resolution_info(&self) -> Option<ResolutionInfo> {
+        if self.id != RESOLUTION_INFO {
+            return None;
+        }
+
+        // The resolutions are 16.16 fixed point numbers.
+        let fixed = |offset| read_u32(self.data, offset).map(|value| value as f32 / 65536.0);
+
+        Some(ResolutionInfo {
+            horizontal_resolution: fixed(0)?,
+            horizontal_resolution_unit: read_u16(self.data, 4)?,
+            width_unit: read_u16(self.data, 6)?,
+            vertical_resolution: fixed(8)?,
+            vertical_resolution_unit: read_u16(self.data, 12)?,
+            height_unit: read_u16(self.data, 14)?,
+        })
+    }
+
+    /// Returns the IPTC-NAA record if this is an `IPTC-NAA` (0x0404) resource.
+    pub fn iptc_data(&self) -> Option<&'a [u8]> {
+        if self.id != IPTC_NAA {
+            return None;
+        }
+
+        Some(self.data)
+    }
+
+    /// Returns the thumbnail if this is a thumbnail (0x0409 or 0x040C) resource.
+    pub fn thumbnail(&self) -> Option<ResourceThumbnail<'a>> {
+        if self.id != THUMBNAIL && self.id != THUMBNAIL_PHOTOSHOP_4 {
+            return None;
+        }
+
+        // Format 1 is kJpegRGB and 0 kRawRGB.
+        let is_jpeg = match read_u32(self.data, 0)? {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+
+        // The total size of raw data, or the compressed size of JPEG data
+        let size = read_u32(self.data, if is_jpeg { 20 } else { 16 })? as usize;
+
+        Some(ResourceThumbnail {
+            width: read_u32(self.data, 4)?,
+            height: read_u32(self.data, 8)?,
+            is_jpeg,
+            is_bgr: self.id == THUMBNAIL_PHOTOSHOP_4,
+            data: 28usize.checked_add(size).and_then(|end| self.data.get(28..end))?,
+        })
+    }
+
+    /// Returns the path if this is a path (0x07D0 to 0x0BB6) resource. The path used as the
+    /// clipping path is named by the resource 0x0BB7, see `clipping_path_name`.
+    pub fn path(&self) -> Option<ResourcePath> {
+        if !PATHS.contains(&self.id) || self.data.len() % 26 != 0 {
+            return None;
+        }
+
+        // Adobe Photoshop File Formats Specification, Path resource format
+        let mut path = ResourcePath::default();
+        let mut remaining = 0;
+
+        for record in self.data.chunks_exact(26) {
+            let point = |offset| {
+                // Signed 8.24 fixed point numbers, vertical component first
+                let coordinate =
+                    |offset| read_u32(record, offset).unwrap() as i32 as f32 / 16777216.0;
+                (coordinate(offset + 4), coordinate(offset))
+            };
+
+            match read_u16(record, 0)? {
+                selector @ (0 | 3) => {
+                    remaining = read_u16(record, 2)?;
+                    path.subpaths.push(Subpath {
+                        closed: selector == 0,
+                        knots: Vec::with_capacity(usize::from(remaining)),
+                    });
+                }
+                selector @ (1 | 2 | 4 | 5) => {
+                    let subpath = path.subpaths.last_mut()?;
+                    if remaining == 0 || subpath.closed != (selector <= 2) {
+                        return None;
+                    }
+
+                    remaining -= 1;
+                    subpath.knots.push(BezierKnot {
+                        linked: selector == 1 || selector == 4,
+                        preceding: point(2),
+                        anchor: point(10),
+                        leaving: point(18),
+                    });
+                }
+                // Fill rule, clipboard and initial fill records
+                6..=8 => {}
+                _ => return None,
+            }
+        }
+
+        Some(path)
+    }
+
+    /// Returns the name of the clipping path if this is a clipping path name (0x0BB7) resource.
+    pub fn clipping_path_name(&self) -> Option<&'a [u8]> {
+        if self.id != CLIPPING_PATH_NAME {
+            return None;
+        }
+
+        // A Pascal string followed by the flatness
+        let length = usize::from(*self.data.first()?);
+        self.data.get(1..1 + length)
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
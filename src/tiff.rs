@@ -0,0 +1,155 @@
+use alloc::vec::Vec;
+
+// TIFF Revision 6.0, Section 2: TIFF Structure
+// Reader of the image file directories (IFD) used by EXIF and the Multi-Picture Format. Offsets
+// are relative to the start of the TIFF header, reading past the end of the data yields `None`.
+#[derive(Clone, Copy)]
+pub struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+// TIFF Revision 6.0, Section 2: Image File Directory
+#[derive(Clone, Copy, Debug)]
+pub struct IfdEntry {
+    pub tag: u16,
+    pub field_type: u16,
+    pub count: u32,
+    // Offset of the value, or of the field holding it if it fits into four bytes.
+    pub value_offset: usize,
+}
+
+// TIFF Revision 6.0, Section 2: Image File Directory
+pub const TYPE_SHORT: u16 = 3;
+pub const TYPE_LONG: u16 = 4;
+pub const TYPE_RATIONAL: u16 = 5;
+pub const TYPE_UNDEFINED: u16 = 7;
+
+impl<'a> Tiff<'a> {
+    pub fn new(data: &'a [u8]) -> Option<Tiff<'a>> {
+        let big_endian = match data.get(0..4)? {
+            b"II*\0" => false,
+            b"MM\0*" => true,
+            _ => return None,
+        };
+
+        Some(Tiff { data, big_endian })
+    }
+
+    pub fn first_ifd_offset(&self) -> Option<usize> {
+        self.read_u32(4).map(|offset| offset as usize)
+    }
+
+    pub fn read_u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self
+            .data
+            .get(offset..offset.checked_add(2)?)?
+            .try_into()
+            .ok()?;
+
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    pub fn read_u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self
+            .data
+            .get(offset..offset.checked_add(4)?)?
+            .try_into()
+            .ok()?;
+
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    pub fn read_u64(&self, offset: usize) -> Option<u64> {
+        let bytes = self
+            .data
+            .get(offset..offset.checked_add(8)?)?
+            .try_into()
+            .ok()?;
+
+        Some(if self.big_endian {
+            u64::from_be_bytes(bytes)
+        } else {
+            u64::from_le_bytes(bytes)
+        })
+    }
+
+    // Returns the entries of the IFD at the offset and the offset of the next IFD, zero if there
+    // is none.
+    pub fn read_ifd(&self, offset: usize) -> Option<(Vec<IfdEntry>, usize)> {
+        let count = usize::from(self.read_u16(offset)?);
+        let mut entries = Vec::with_capacity(count);
+
+        for i in 0..count {
+            let position = offset + 2 + i * 12;
+            let field_type = self.read_u16(position + 2)?;
+            let count = self.read_u32(position + 4)?;
+            let size = type_size(field_type).checked_mul(count as usize)?;
+
+            let value_offset = if size <= 4 {
+                position + 8
+            } else {
+                self.read_u32(position + 8)? as usize
+            };
+
+            entries.push(IfdEntry {
+                tag: self.read_u16(position)?,
+                field_type,
+                count,
+                value_offset,
+            });
+        }
+
+        let next = self.read_u32(offset + 2 + count * 12)? as usize;
+        Some((entries, next))
+    }
+
+    // Returns the first value of a SHORT or LONG entry.
+    pub fn value_u32(&self, entry: &IfdEntry) -> Option<u32> {
+        if entry.count == 0 {
+            return None;
+        }
+
+        match entry.field_type {
+            TYPE_SHORT => self.read_u16(entry.value_offset).map(u32::from),
+            TYPE_LONG => self.read_u32(entry.value_offset),
+            _ => None,
+        }
+    }
+
+    // Returns the first value of a RATIONAL entry as numerator and denominator.
+    pub fn value_rational(&self, entry: &IfdEntry) -> Option<(u32, u32)> {
+        if entry.field_type != TYPE_RATIONAL || entry.count == 0 {
+            return None;
+        }
+
+        let numerator = self.read_u32(entry.value_offset)?;
+        let denominator = self.read_u32(entry.value_offset + 4)?;
+        Some((numerator, denominator))
+    }
+
+    // Returns the bytes of the value of an entry.
+    pub fn value(&self, entry: &IfdEntry) -> Option<&'a [u8]> {
+        let size = type_size(entry.field_type).checked_mul(entry.count as usize)?;
+        self.data
+            .get(entry.value_offset..entry.value_offset.checked_add(size)?)
+    }
+}
+
+// Size in bytes of a value of the field type, unknown types are treated as bytes.
+pub fn type_size(field_type: u16) -> usize {
+    match field_type {
+        3 | 8 => 2,
+        4 | 9 | 11 | 13 => 4,
+        5 | 10 | 12 => 8,
+        _ => 1,
+    }
+}
@@ -1,23 +1,23 @@
 use alloc::boxed::Box;
 use alloc::vec;
 use alloc::vec::Vec;
-use crate::error::{Error, Result, UnsupportedFeature};
+use crate::decoder::Sample;
 use crate::parser::Component;
 
-pub struct Upsampler {
-    components: Vec<UpsamplerComponent>,
+pub struct Upsampler<T> {
+    components: Vec<UpsamplerComponent<T>>,
     line_buffer_size: usize
 }
 
-struct UpsamplerComponent {
-    upsampler: Box<dyn Upsample + Sync>,
+struct UpsamplerComponent<T> {
+    upsampler: Box<dyn Upsample<T> + Sync>,
     width: usize,
     height: usize,
     row_stride: usize,
 }
 
-impl Upsampler {
-    pub fn new(components: &[Component], output_width: u16, output_height: u16) -> Result<Upsampler> {
+impl<T: Sample> Upsampler<T> {
+    pub fn new(components: &[Component], output_width: u16, output_height: u16) -> Upsampler<T> {
         let h_max = components.iter().map(|c| c.horizontal_sampling_factor).max().unwrap();
         let v_max = components.iter().map(|c| c.vertical_sampling_factor).max().unwrap();
         let mut upsampler_components = Vec::with_capacity(components.len());
@@ -27,7 +27,7 @@ impl Upsampler {
                                               component.vertical_sampling_factor),
                                              (h_max, v_max),
                                              output_width,
-                                             output_height)?;
+                                             output_height);
             upsampler_components.push(UpsamplerComponent {
                 upsampler,
                 width: component.size.width as usize,
@@ -38,15 +38,15 @@ impl Upsampler {
 
         let buffer_size = components.iter().map(|c| c.size.width).max().unwrap() as usize * h_max as usize;
 
-        Ok(Upsampler {
+        Upsampler {
             components: upsampler_components,
             line_buffer_size: buffer_size
-        })
+        }
     }
 
-    pub fn upsample_and_interleave_row(&self, component_data: &[Vec<u8>], row: usize, output_width: usize, output: &mut [u8], color_convert: fn(&[Vec<u8>], &mut [u8])) {
+    pub fn upsample_and_interleave_row(&self, component_data: &[Vec<T>], row: usize, output_width: usize, output: &mut [T], color_convert: fn(&[Vec<T>], &mut [T])) {
         let component_count = component_data.len();
-        let mut line_buffers = vec![vec![0u8; self.line_buffer_size]; component_count];
+        let mut line_buffers = vec![vec![T::default(); self.line_buffer_size]; component_count];
 
         debug_assert_eq!(component_count, self.components.len());
 
@@ -61,6 +61,25 @@ impl Upsampler {
         }
         color_convert(&line_buffers, output);
     }
+
+    pub fn upsample_component(&self, index: usize, component_data: &[T], output_width: usize, output_height: usize) -> Vec<T> {
+        let component = &self.components[index];
+        let mut line_buffer = vec![T::default(); self.line_buffer_size];
+        let mut output = Vec::with_capacity(output_width * output_height);
+
+        for row in 0 .. output_height {
+            component.upsampler.upsample_row(component_data,
+                                             component.width,
+                                             component.height,
+                                             component.row_stride,
+                                             row,
+                                             output_width,
+                                             &mut line_buffer);
+            output.extend_from_slice(&line_buffer[..output_width]);
+        }
+
+        output
+    }
 }
 
 struct UpsamplerH1V1;
@@ -73,73 +92,79 @@ struct UpsamplerGeneric {
     vertical_scaling_factor: u8
 }
 
-fn choose_upsampler(sampling_factors: (u8, u8),
-                    max_sampling_factors: (u8, u8),
-                    output_width: u16,
-                    output_height: u16) -> Result<Box<dyn Upsample + Sync>> {
+struct UpsamplerRational {
+    sampling_factors: (u8, u8),
+    max_sampling_factors: (u8, u8),
+}
+
+fn choose_upsampler<T: Sample>(sampling_factors: (u8, u8),
+                               max_sampling_factors: (u8, u8),
+                               output_width: u16,
+                               output_height: u16) -> Box<dyn Upsample<T> + Sync> {
     let h1 = sampling_factors.0 == max_sampling_factors.0 || output_width == 1;
     let v1 = sampling_factors.1 == max_sampling_factors.1 || output_height == 1;
     let h2 = sampling_factors.0 * 2 == max_sampling_factors.0;
     let v2 = sampling_factors.1 * 2 == max_sampling_factors.1;
 
     if h1 && v1 {
-        Ok(Box::new(UpsamplerH1V1))
+        Box::new(UpsamplerH1V1)
     } else if h2 && v1 {
-        Ok(Box::new(UpsamplerH2V1))
+        Box::new(UpsamplerH2V1)
     } else if h1 && v2 {
-        Ok(Box::new(UpsamplerH1V2))
+        Box::new(UpsamplerH1V2)
     } else if h2 && v2 {
-        Ok(Box::new(UpsamplerH2V2))
+        Box::new(UpsamplerH2V2)
     } else if max_sampling_factors.0 % sampling_factors.0 != 0
         || max_sampling_factors.1 % sampling_factors.1 != 0
     {
-        Err(Error::Unsupported(
-            UnsupportedFeature::NonIntegerSubsamplingRatio,
-        ))
+        Box::new(UpsamplerRational {
+            sampling_factors,
+            max_sampling_factors,
+        })
     } else {
-        Ok(Box::new(UpsamplerGeneric {
+        Box::new(UpsamplerGeneric {
             horizontal_scaling_factor: max_sampling_factors.0 / sampling_factors.0,
             vertical_scaling_factor: max_sampling_factors.1 / sampling_factors.1,
-        }))
+        })
     }
 }
 
 #[allow(clippy::too_many_arguments)]
-trait Upsample {
+trait Upsample<T> {
     fn upsample_row(&self,
-                    input: &[u8],
+                    input: &[T],
                     input_width: usize,
                     input_height: usize,
                     row_stride: usize,
                     row: usize,
                     output_width: usize,
-                    output: &mut [u8]);
+                    output: &mut [T]);
 }
 
-impl Upsample for UpsamplerH1V1 {
+impl<T: Sample> Upsample<T> for UpsamplerH1V1 {
     fn upsample_row(&self,
-                    input: &[u8],
+                    input: &[T],
                     _input_width: usize,
                     _input_height: usize,
                     row_stride: usize,
                     row: usize,
                     output_width: usize,
-                    output: &mut [u8]) {
+                    output: &mut [T]) {
         let input = &input[row * row_stride ..];
 
         output[..output_width].copy_from_slice(&input[..output_width]);
     }
 }
 
-impl Upsample for UpsamplerH2V1 {
+impl<T: Sample> Upsample<T> for UpsamplerH2V1 {
     fn upsample_row(&self,
-                    input: &[u8],
+                    input: &[T],
                     input_width: usize,
                     _input_height: usize,
                     row_stride: usize,
                     row: usize,
                     _output_width: usize,
-                    output: &mut [u8]) {
+                    output: &mut [T]) {
         let input = &input[row * row_stride ..];
 
         if input_width == 1 {
@@ -149,28 +174,28 @@ impl Upsample for UpsamplerH2V1 {
         }
 
         output[0] = input[0];
-        output[1] = ((input[0] as u32 * 3 + input[1] as u32 + 2) >> 2) as u8;
+        output[1] = T::from_u32((input[0].to_u32() * 3 + input[1].to_u32() + 2) >> 2);
 
         for i in 1 .. input_width - 1 {
-            let sample = 3 * input[i] as u32 + 2;
-            output[i * 2]     = ((sample + input[i - 1] as u32) >> 2) as u8;
-            output[i * 2 + 1] = ((sample + input[i + 1] as u32) >> 2) as u8;
+            let sample = 3 * input[i].to_u32() + 2;
+            output[i * 2]     = T::from_u32((sample + input[i - 1].to_u32()) >> 2);
+            output[i * 2 + 1] = T::from_u32((sample + input[i + 1].to_u32()) >> 2);
         }
 
-        output[(input_width - 1) * 2] = ((input[input_width - 1] as u32 * 3 + input[input_width - 2] as u32 + 2) >> 2) as u8;
+        output[(input_width - 1) * 2] = T::from_u32((input[input_width - 1].to_u32() * 3 + input[input_width - 2].to_u32() + 2) >> 2);
         output[(input_width - 1) * 2 + 1] = input[input_width - 1];
     }
 }
 
-impl Upsample for UpsamplerH1V2 {
+impl<T: Sample> Upsample<T> for UpsamplerH1V2 {
     fn upsample_row(&self,
-                    input: &[u8],
+                    input: &[T],
                     _input_width: usize,
                     input_height: usize,
                     row_stride: usize,
                     row: usize,
                     output_width: usize,
-                    output: &mut [u8]) {
+                    output: &mut [T]) {
         let row_near = row as f32 / 2.0;
         // If row_near's fractional is 0.0 we want row_far to be the previous row and if it's 0.5 we
         // want it to be the next row.
@@ -183,20 +208,20 @@ impl Upsample for UpsamplerH1V2 {
         let input_near = &input_near[..output_width];
         let input_far = &input_far[..output_width];
         for i in 0..output_width {
-            output[i] = ((3 * input_near[i] as u32 + input_far[i] as u32 + 2) >> 2) as u8;
+            output[i] = T::from_u32((3 * input_near[i].to_u32() + input_far[i].to_u32() + 2) >> 2);
         }
     }
 }
 
-impl Upsample for UpsamplerH2V2 {
+impl<T: Sample> Upsample<T> for UpsamplerH2V2 {
     fn upsample_row(&self,
-                    input: &[u8],
+                    input: &[T],
                     input_width: usize,
                     input_height: usize,
                     row_stride: usize,
                     row: usize,
                     _output_width: usize,
-                    output: &mut [u8]) {
+                    output: &mut [T]) {
         let row_near = row as f32 / 2.0;
         // If row_near's fractional is 0.0 we want row_far to be the previous row and if it's 0.5 we
         // want it to be the next row.
@@ -206,37 +231,37 @@ impl Upsample for UpsamplerH2V2 {
         let input_far = &input[row_far as usize * row_stride ..];
 
         if input_width == 1 {
-            let value = ((3 * input_near[0] as u32 + input_far[0] as u32 + 2) >> 2) as u8;
+            let value = T::from_u32((3 * input_near[0].to_u32() + input_far[0].to_u32() + 2) >> 2);
             output[0] = value;
             output[1] = value;
             return;
         }
 
-        let mut t1 = 3 * input_near[0] as u32 + input_far[0] as u32;
-        output[0] = ((t1 + 2) >> 2) as u8;
+        let mut t1 = 3 * input_near[0].to_u32() + input_far[0].to_u32();
+        output[0] = T::from_u32((t1 + 2) >> 2);
 
         for i in 1 .. input_width {
             let t0 = t1;
-            t1 = 3 * input_near[i] as u32 + input_far[i] as u32;
+            t1 = 3 * input_near[i].to_u32() + input_far[i].to_u32();
 
-            output[i * 2 - 1] = ((3 * t0 + t1 + 8) >> 4) as u8;
-            output[i * 2]     = ((3 * t1 + t0 + 8) >> 4) as u8;
+            output[i * 2 - 1] = T::from_u32((3 * t0 + t1 + 8) >> 4);
+            output[i * 2]     = T::from_u32((3 * t1 + t0 + 8) >> 4);
         }
 
-        output[input_width * 2 - 1] = ((t1 + 2) >> 2) as u8;
+        output[input_width * 2 - 1] = T::from_u32((t1 + 2) >> 2);
     }
 }
 
-impl Upsample for UpsamplerGeneric {
+impl<T: Sample> Upsample<T> for UpsamplerGeneric {
     // Uses nearest neighbor sampling
     fn upsample_row(&self,
-                    input: &[u8],
+                    input: &[T],
                     input_width: usize,
                     _input_height: usize,
                     row_stride: usize,
                     row: usize,
                     _output_width: usize,
-                    output: &mut [u8]) {
+                    output: &mut [T]) {
         let mut index = 0;
         let start = (row / self.vertical_scaling_factor as usize) * row_stride;
         let input = &input[start..(start + input_width)];
@@ -248,3 +273,53 @@ impl Upsample for UpsamplerGeneric {
         }
     }
 }
+
+impl<T: Sample> Upsample<T> for UpsamplerRational {
+    // Interpolates linearly between the samples surrounding the centre of each output sample,
+    // like the upsamplers above do for a factor of 2.
+    fn upsample_row(&self,
+                    input: &[T],
+                    input_width: usize,
+                    input_height: usize,
+                    row_stride: usize,
+                    row: usize,
+                    output_width: usize,
+                    output: &mut [T]) {
+        let (row_near, row_far, row_weight) = interpolation_position(row,
+                                                                     self.sampling_factors.1,
+                                                                     self.max_sampling_factors.1,
+                                                                     input_height);
+        let vertical_scale = 2 * self.max_sampling_factors.1 as u32;
+        let horizontal_scale = 2 * self.max_sampling_factors.0 as u32;
+        let scale = vertical_scale * horizontal_scale;
+
+        let input_near = &input[row_near * row_stride .. row_near * row_stride + input_width];
+        let input_far = &input[row_far * row_stride .. row_far * row_stride + input_width];
+
+        for (x, output) in output[..output_width].iter_mut().enumerate() {
+            let (left, right, weight) = interpolation_position(x,
+                                                               self.sampling_factors.0,
+                                                               self.max_sampling_factors.0,
+                                                               input_width);
+            let near = input_near[left].to_u32() * (horizontal_scale - weight) + input_near[right].to_u32() * weight;
+            let far = input_far[left].to_u32() * (horizontal_scale - weight) + input_far[right].to_u32() * weight;
+
+            *output = T::from_u32((near * (vertical_scale - row_weight) + far * row_weight + scale / 2) / scale);
+        }
+    }
+}
+
+// Returns the input samples before and after the centre of the output sample at `index`, and
+// the weight of the latter in units of 1 / (2 * max_sampling_factor).
+fn interpolation_position(index: usize,
+                          sampling_factor: u8,
+                          max_sampling_factor: u8,
+                          input_length: usize) -> (usize, usize, u32) {
+    // The centre of output sample i lies at (i + 0.5) * factor / max_factor - 0.5 input samples.
+    let position = (2 * index as isize + 1) * sampling_factor as isize - max_sampling_factor as isize;
+    let scale = 2 * max_sampling_factor as isize;
+    let before = position.div_euclid(scale);
+    let clamp = |i: isize| i.clamp(0, input_length as isize - 1) as usize;
+
+    (clamp(before), clamp(before + 1), position.rem_euclid(scale) as u32)
+}
@@ -1,37 +1,45 @@
-use alloc::vec;
 use alloc::vec::Vec;
 use core::mem;
-use crate::decoder::MAX_COMPONENTS;
 use crate::error::Result;
 use crate::idct::dequantize_and_idct_block;
 use crate::alloc::sync::Arc;
 use crate::parser::Component;
-use super::{RowData, Worker};
+use super::{append_row_12bit, RowData, Worker};
 
+#[derive(Default)]
 pub struct ImmediateWorker {
-    offsets: [usize; MAX_COMPONENTS],
+    offsets: Vec<usize>,
     results: Vec<Vec<u8>>,
+    results_u16: Vec<Vec<u16>>,
     components: Vec<Option<Component>>,
     quantization_tables: Vec<Option<Arc<[u16; 64]>>>,
-}
-
-impl Default for ImmediateWorker {
-    fn default() -> Self {
-        ImmediateWorker {
-            offsets: [0; MAX_COMPONENTS],
-            results: vec![Vec::new(); MAX_COMPONENTS],
-            components: vec![None; MAX_COMPONENTS],
-            quantization_tables: vec![None; MAX_COMPONENTS],
-        }
-    }
+    precisions: Vec<u8>,
 }
 
 impl ImmediateWorker {
     pub fn start_immediate(&mut self, data: RowData) {
-        assert!(self.results[data.index].is_empty());
+        // A frame can have up to 255 components, make room for the ones seen so far.
+        if data.index >= self.results.len() {
+            let len = data.index + 1;
+            self.offsets.resize(len, 0);
+            self.results.resize(len, Vec::new());
+            self.results_u16.resize(len, Vec::new());
+            self.components.resize(len, None);
+            self.quantization_tables.resize(len, None);
+            self.precisions.resize(len, 0);
+        }
+
+        assert!(self.results[data.index].is_empty() && self.results_u16[data.index].is_empty());
+
+        let elements = data.component.block_size.width as usize * data.component.block_size.height as usize * data.component.dct_scale * data.component.dct_scale;
 
         self.offsets[data.index] = 0;
-        self.results[data.index].resize(data.component.block_size.width as usize * data.component.block_size.height as usize * data.component.dct_scale * data.component.dct_scale, 0u8);
+        if data.precision > 8 {
+            self.results_u16[data.index].resize(elements, 0u16);
+        } else {
+            self.results[data.index].resize(elements, 0u8);
+        }
+        self.precisions[data.index] = data.precision;
         self.components[data.index] = Some(data.component);
         self.quantization_tables[data.index] = Some(data.quantization_table);
     }
@@ -44,6 +52,12 @@ impl ImmediateWorker {
         let block_count = component.block_size.width as usize * component.vertical_sampling_factor as usize;
         let line_stride = component.block_size.width as usize * component.dct_scale;
 
+        if self.precisions[index] > 8 {
+            append_row_12bit(component, quantization_table, &data, &mut self.results_u16[index][self.offsets[index]..]);
+            self.offsets[index] += block_count * 64;
+            return;
+        }
+
         assert_eq!(data.len(), block_count * 64);
 
         for i in 0..block_count {
@@ -62,6 +76,10 @@ impl ImmediateWorker {
     pub fn get_result_immediate(&mut self, index: usize) -> Vec<u8> {
         mem::take(&mut self.results[index])
     }
+
+    pub fn get_result_u16_immediate(&mut self, index: usize) -> Vec<u16> {
+        mem::take(&mut self.results_u16[index])
+    }
 }
 
 impl Worker for ImmediateWorker {
@@ -76,4 +94,7 @@ impl Worker for ImmediateWorker {
     fn get_result(&mut self, index: usize) -> Result<Vec<u8>> {
         Ok(self.get_result_immediate(index))
     }
+    fn get_result_u16(&mut self, index: usize) -> Result<Vec<u16>> {
+        Ok(self.get_result_u16_immediate(index))
+    }
 }
@@ -6,8 +6,9 @@ mod multithreaded;
 ))]
 mod rayon;
 
-use crate::decoder::{choose_color_convert_func, ColorTransform};
+use crate::decoder::{ColorTransform, OutputLayout, Sample};
 use crate::error::Result;
+use crate::idct::dequantize_and_idct_block_8x8_12bit;
 use crate::parser::{Component, Dimensions};
 use crate::upsampler::Upsampler;
 
@@ -19,12 +20,15 @@ pub struct RowData {
     pub index: usize,
     pub component: Component,
     pub quantization_table: Arc<[u16; 64]>,
+    pub precision: u8,
 }
 
 pub trait Worker {
     fn start(&mut self, row_data: RowData) -> Result<()>;
     fn append_row(&mut self, row: (usize, Vec<i16>)) -> Result<()>;
     fn get_result(&mut self, index: usize) -> Result<Vec<u8>>;
+    /// Returns the samples of a component started with a precision of 12 bits.
+    fn get_result_u16(&mut self, index: usize) -> Result<Vec<u16>>;
     /// Default implementation for spawning multiple tasks.
     fn append_rows(&mut self, row: &mut dyn Iterator<Item = (usize, Vec<i16>)>) -> Result<()> {
         for item in row {
@@ -94,24 +98,26 @@ impl WorkerScope {
     }
 }
 
-pub fn compute_image_parallel(
+pub fn compute_image_parallel<T: Sample>(
     components: &[Component],
-    data: Vec<Vec<u8>>,
+    data: Vec<Vec<T>>,
     output_size: Dimensions,
     color_transform: ColorTransform,
-) -> Result<Vec<u8>> {
+    layout: OutputLayout,
+) -> Result<Vec<T>> {
     #[cfg(all(
         not(any(target_arch = "asmjs", target_arch = "wasm32")),
         feature = "rayon"
     ))]
-    return rayon::compute_image_parallel(components, data, output_size, color_transform);
+    return rayon::compute_image_parallel(components, data, output_size, color_transform, layout);
 
     #[allow(unreachable_code)]
     {
-        let color_convert_func = choose_color_convert_func(components.len(), color_transform)?;
-        let upsampler = Upsampler::new(components, output_size.width, output_size.height)?;
-        let line_size = output_size.width as usize * components.len();
-        let mut image = vec![0u8; line_size * output_size.height as usize];
+        let color_convert_func = T::color_convert_func(components.len(), color_transform, layout)?;
+        let upsampler = Upsampler::new(components, output_size.width, output_size.height);
+        let pixel_size = layout.samples_per_pixel(components.len());
+        let line_size = output_size.width as usize * pixel_size;
+        let mut image = vec![T::default(); line_size * output_size.height as usize];
 
         for (row, line) in image.chunks_mut(line_size).enumerate() {
             upsampler.upsample_and_interleave_row(
@@ -126,3 +132,29 @@ pub fn compute_image_parallel(
         Ok(image)
     }
 }
+
+/// Converts the coefficients of a MCU row of a 12-bit component to samples.
+fn append_row_12bit(
+    component: &Component,
+    quantization_table: &[u16; 64],
+    data: &[i16],
+    output: &mut [u16],
+) {
+    let block_count =
+        component.block_size.width as usize * component.vertical_sampling_factor as usize;
+    let line_stride = component.block_size.width as usize * 8;
+
+    assert_eq!(data.len(), block_count * 64);
+    // Scaling is not supported for 12-bit frames.
+    debug_assert_eq!(component.dct_scale, 8);
+
+    for i in 0..block_count {
+        let x = (i % component.block_size.width as usize) * 8;
+        let y = (i / component.block_size.width as usize) * 8;
+
+        let coefficients = data[i * 64..(i + 1) * 64].try_into().unwrap();
+        let output = &mut output[y * line_stride + x..];
+
+        dequantize_and_idct_block_8x8_12bit(coefficients, quantization_table, line_stride, output);
+    }
+}
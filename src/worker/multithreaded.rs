@@ -1,4 +1,5 @@
 //! This module implements per-component parallelism.
+//! The components are shared between at most as many threads as can run in parallel.
 //! It should be possible to implement per-row parallelism as well,
 //! which should also boost performance of grayscale images
 //! and allow scaling to more cores.
@@ -6,41 +7,50 @@
 
 use super::immediate::ImmediateWorker;
 use super::{RowData, Worker};
-use crate::decoder::MAX_COMPONENTS;
 use crate::error::Result;
 use std::{
-    mem,
+    num::NonZeroUsize,
     sync::mpsc::{self, Receiver, Sender},
+    thread,
 };
 
 enum WorkerMsg {
     Start(RowData),
-    AppendRow(Vec<i16>),
-    GetResult(Sender<Vec<u8>>),
+    AppendRow((usize, Vec<i16>)),
+    GetResult(usize, Sender<Vec<u8>>),
+    GetResultU16(usize, Sender<Vec<u16>>),
 }
 
 #[derive(Default)]
 pub struct MpscWorker {
-    senders: [Option<Sender<WorkerMsg>>; MAX_COMPONENTS],
+    senders: Vec<Option<Sender<WorkerMsg>>>,
 }
 
 impl MpscWorker {
+    // A frame can have up to 255 components, which share as many threads as can run in parallel.
+    // Component `i` is handled by the thread `i % thread_count`.
+    fn thread_index(&mut self, component: usize) -> usize {
+        if self.senders.is_empty() {
+            let thread_count = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+            self.senders.resize_with(thread_count, || None);
+        }
+
+        component % self.senders.len()
+    }
+
     fn start_with(
         &mut self,
         row_data: RowData,
         spawn_worker: impl FnOnce(usize) -> Result<Sender<WorkerMsg>>,
     ) -> Result<()> {
         // if there is no worker thread for this component yet, start one
-        let component = row_data.index;
-        if self.senders[component].is_none() {
-            let sender = spawn_worker(component)?;
-            self.senders[component] = Some(sender);
+        let index = self.thread_index(row_data.index);
+        if self.senders[index].is_none() {
+            let sender = spawn_worker(index)?;
+            self.senders[index] = Some(sender);
         }
 
-        // we do the "take out value and put it back in once we're done" dance here
-        // and in all other message-passing methods because there's not that many rows
-        // and this should be cheaper than spawning MAX_COMPONENTS many threads up front
-        let sender = self.senders[component].as_mut().unwrap();
+        let sender = self.senders[index].as_mut().unwrap();
         sender
             .send(WorkerMsg::Start(row_data))
             .expect("jpeg-decoder worker thread error");
@@ -48,23 +58,25 @@ impl MpscWorker {
     }
 
     fn append_row(&mut self, row: (usize, Vec<i16>)) -> Result<()> {
-        let component = row.0;
-        let sender = self.senders[component].as_mut().unwrap();
+        let index = self.thread_index(row.0);
+        let sender = self.senders[index].as_mut().unwrap();
         sender
-            .send(WorkerMsg::AppendRow(row.1))
+            .send(WorkerMsg::AppendRow(row))
             .expect("jpeg-decoder worker thread error");
         Ok(())
     }
 
-    fn get_result_with(
+    fn get_result_with<T>(
         &mut self,
-        index: usize,
-        collect: impl FnOnce(Receiver<Vec<u8>>) -> Vec<u8>,
-    ) -> Result<Vec<u8>> {
+        component: usize,
+        message: impl FnOnce(usize, Sender<T>) -> WorkerMsg,
+        collect: impl FnOnce(Receiver<T>) -> T,
+    ) -> Result<T> {
         let (tx, rx) = mpsc::channel();
-        let sender = mem::take(&mut self.senders[index]).unwrap();
+        let index = self.thread_index(component);
+        let sender = self.senders[index].as_mut().unwrap();
         sender
-            .send(WorkerMsg::GetResult(tx))
+            .send(message(component, tx))
             .expect("jpeg-decoder worker thread error");
         Ok(collect(rx))
     }
@@ -78,7 +90,10 @@ impl Worker for MpscWorker {
         MpscWorker::append_row(self, row)
     }
     fn get_result(&mut self, index: usize) -> Result<Vec<u8>> {
-        self.get_result_with(index, collect_worker_thread)
+        self.get_result_with(index, WorkerMsg::GetResult, collect_worker_thread)
+    }
+    fn get_result_u16(&mut self, index: usize) -> Result<Vec<u16>> {
+        self.get_result_with(index, WorkerMsg::GetResultU16, collect_worker_thread)
     }
 }
 
@@ -87,21 +102,20 @@ fn create_worker() -> (Sender<WorkerMsg>, impl FnOnce() + 'static) {
     let closure = move || {
         let mut worker = ImmediateWorker::default();
 
+        // the thread handles its components until the decoder drops the sender
         while let Ok(message) = rx.recv() {
             match message {
-                WorkerMsg::Start(mut data) => {
-                    // we always set component index to 0 for worker threads
-                    // because they only ever handle one per thread and we don't want them
-                    // to attempt to access nonexistent components
-                    data.index = 0;
+                WorkerMsg::Start(data) => {
                     worker.start_immediate(data);
                 }
                 WorkerMsg::AppendRow(row) => {
-                    worker.append_row_immediate((0, row));
+                    worker.append_row_immediate(row);
+                }
+                WorkerMsg::GetResult(index, chan) => {
+                    let _ = chan.send(worker.get_result_immediate(index));
                 }
-                WorkerMsg::GetResult(chan) => {
-                    let _ = chan.send(worker.get_result_immediate(0));
-                    break;
+                WorkerMsg::GetResultU16(index, chan) => {
+                    let _ = chan.send(worker.get_result_u16_immediate(index));
                 }
             }
         }
@@ -110,14 +124,14 @@ fn create_worker() -> (Sender<WorkerMsg>, impl FnOnce() + 'static) {
     (tx, closure)
 }
 
-fn spawn_worker_thread(component: usize) -> Result<Sender<WorkerMsg>> {
+fn spawn_worker_thread(index: usize) -> Result<Sender<WorkerMsg>> {
     let (tx, worker) = create_worker();
     let thread_builder =
-        std::thread::Builder::new().name(format!("worker thread for component {}", component));
+        thread::Builder::new().name(format!("jpeg-decoder worker thread {}", index));
     thread_builder.spawn(worker)?;
     Ok(tx)
 }
 
-fn collect_worker_thread(rx: Receiver<Vec<u8>>) -> Vec<u8> {
+fn collect_worker_thread<T>(rx: Receiver<T>) -> T {
     rx.recv().expect("jpeg-decoder worker thread error")
 }
@@ -1,26 +1,28 @@
 use rayon::iter::{IndexedParallelIterator, ParallelIterator};
 use rayon::slice::ParallelSliceMut;
 
-use crate::decoder::{choose_color_convert_func, ColorTransform};
+use crate::decoder::{ColorTransform, OutputLayout, Sample};
 use crate::error::Result;
 use crate::idct::dequantize_and_idct_block;
 use crate::parser::Component;
+use crate::parser::Dimensions;
 use crate::upsampler::Upsampler;
-use crate::{decoder::MAX_COMPONENTS, parser::Dimensions};
 
 use std::sync::Arc;
 
-use super::{RowData, Worker};
+use super::{append_row_12bit, RowData, Worker};
 
 /// Technically similar to `immediate::ImmediateWorker` but we copy it since we may prefer
 /// different style of managing the memory allocation, something that multiple actors can access in
 /// parallel.
 #[derive(Default)]
 struct ImmediateWorker {
-    offsets: [usize; MAX_COMPONENTS],
-    results: [Vec<u8>; MAX_COMPONENTS],
-    components: [Option<Component>; MAX_COMPONENTS],
-    quantization_tables: [Option<Arc<[u16; 64]>>; MAX_COMPONENTS],
+    offsets: Vec<usize>,
+    results: Vec<Vec<u8>>,
+    results_u16: Vec<Vec<u16>>,
+    components: Vec<Option<Component>>,
+    quantization_tables: Vec<Option<Arc<[u16; 64]>>>,
+    precisions: Vec<u8>,
 }
 
 #[derive(Clone, Copy)]
@@ -38,12 +40,28 @@ pub struct Scoped {
 
 impl ImmediateWorker {
     pub fn start_immediate(&mut self, data: RowData) {
+        // A frame can have up to 255 components, make room for the ones seen so far.
+        if data.index >= self.results.len() {
+            let len = data.index + 1;
+            self.offsets.resize(len, 0);
+            self.results.resize(len, Vec::new());
+            self.results_u16.resize(len, Vec::new());
+            self.components.resize(len, None);
+            self.quantization_tables.resize(len, None);
+            self.precisions.resize(len, 0);
+        }
+
         let elements = data.component.block_size.width as usize
             * data.component.block_size.height as usize
             * data.component.dct_scale
             * data.component.dct_scale;
         self.offsets[data.index] = 0;
-        self.results[data.index].resize(elements, 0u8);
+        if data.precision > 8 {
+            self.results_u16[data.index].resize(elements, 0u16);
+        } else {
+            self.results[data.index].resize(elements, 0u8);
+        }
+        self.precisions[data.index] = data.precision;
         self.components[data.index] = Some(data.component);
         self.quantization_tables[data.index] = Some(data.quantization_table);
     }
@@ -52,6 +70,10 @@ impl ImmediateWorker {
         core::mem::take(&mut self.results[index])
     }
 
+    pub fn get_result_u16_immediate(&mut self, index: usize) -> Vec<u16> {
+        core::mem::take(&mut self.results_u16[index])
+    }
+
     pub fn component_metadata(&self, index: usize) -> Option<ComponentMetadata> {
         let component = self.components[index].as_ref()?;
         let block_size = component.block_size;
@@ -122,6 +144,16 @@ impl Worker for Scoped {
         let inner = &mut self.inner;
         let (index, data) = row;
 
+        if inner.precisions[index] > 8 {
+            let component = inner.components[index].as_ref().unwrap();
+            let quantization_table = inner.quantization_tables[index].as_ref().unwrap();
+            let result_block = &mut inner.results_u16[index][inner.offsets[index]..];
+            inner.offsets[index] += data.len();
+
+            append_row_12bit(component, quantization_table, &data, result_block);
+            return Ok(());
+        }
+
         let quantization_table = inner.quantization_tables[index].as_ref().unwrap().clone();
         let metadata = inner.component_metadata(index).unwrap();
         let result_block = &mut inner.results[index][inner.offsets[index]..];
@@ -136,28 +168,36 @@ impl Worker for Scoped {
         Ok(result)
     }
 
+    fn get_result_u16(&mut self, index: usize) -> Result<Vec<u16>> {
+        let result = self.inner.get_result_u16_immediate(index);
+        Ok(result)
+    }
+
     // Magic sauce, these _may_ run in parallel.
     fn append_rows(&mut self, iter: &mut dyn Iterator<Item = (usize, Vec<i16>)>) -> Result<()> {
+        // Rows of 12-bit components are transformed one after another.
+        if self.inner.precisions.iter().any(|&precision| precision > 8) {
+            for row in iter {
+                self.append_row(row)?;
+            }
+            return Ok(());
+        }
+
         let inner = &mut self.inner;
         rayon::in_place_scope(|scope| {
-            let metadatas = [
-                inner.component_metadata(0),
-                inner.component_metadata(1),
-                inner.component_metadata(2),
-                inner.component_metadata(3),
-            ];
-
-            let [res0, res1, res2, res3] = &mut inner.results;
+            let metadatas: Vec<_> = (0..inner.components.len())
+                .map(|index| inner.component_metadata(index))
+                .collect();
 
             // Lazily get the blocks. Note: if we've already collected results from a component
             // then the result vector has already been deallocated/taken. But no more tasks should
             // be created for it.
-            let mut result_blocks = [
-                res0.get_mut(inner.offsets[0]..).unwrap_or(&mut []),
-                res1.get_mut(inner.offsets[1]..).unwrap_or(&mut []),
-                res2.get_mut(inner.offsets[2]..).unwrap_or(&mut []),
-                res3.get_mut(inner.offsets[3]..).unwrap_or(&mut []),
-            ];
+            let mut result_blocks: Vec<&mut [u8]> = inner
+                .results
+                .iter_mut()
+                .zip(&inner.offsets)
+                .map(|(result, &offset)| result.get_mut(offset..).unwrap_or(&mut []))
+                .collect();
 
             // First we schedule everything, making sure their index is right etc.
             for (index, data) in iter {
@@ -190,16 +230,18 @@ impl ComponentMetadata {
     }
 }
 
-pub fn compute_image_parallel(
+pub fn compute_image_parallel<T: Sample>(
     components: &[Component],
-    data: Vec<Vec<u8>>,
+    data: Vec<Vec<T>>,
     output_size: Dimensions,
     color_transform: ColorTransform,
-) -> Result<Vec<u8>> {
-    let color_convert_func = choose_color_convert_func(components.len(), color_transform)?;
-    let upsampler = Upsampler::new(components, output_size.width, output_size.height)?;
-    let line_size = output_size.width as usize * components.len();
-    let mut image = vec![0u8; line_size * output_size.height as usize];
+    layout: OutputLayout,
+) -> Result<Vec<T>> {
+    let color_convert_func = T::color_convert_func(components.len(), color_transform, layout)?;
+    let upsampler = Upsampler::new(components, output_size.width, output_size.height);
+    let pixel_size = layout.samples_per_pixel(components.len());
+    let line_size = output_size.width as usize * pixel_size;
+    let mut image = vec![T::default(); line_size * output_size.height as usize];
 
     image
         .par_chunks_mut(line_size)
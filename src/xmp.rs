@@ -0,0 +1,75 @@
+use alloc::vec::Vec;
+use alloc::{format, vec};
+
+// The namespaces of the properties read from XMP packets
+pub const HDR_GAIN_MAP_NAMESPACE: &str = "http://ns.adobe.com/hdr-gain-map/1.0/";
+pub const XMP_NOTE_NAMESPACE: &str = "http://ns.adobe.com/xmp/note/";
+
+// Returns the values of a property of an XMP packet, given by its namespace URI and name such as
+// `Version`. The property is looked up with every prefix the packet declares for the namespace
+// with an `xmlns:prefix` attribute. It is either an attribute or an element holding a value or an
+// array of rdf:li items.
+pub fn property<'a>(xmp: &'a str, namespace: &str, name: &str) -> Option<Vec<&'a str>> {
+    prefixes(xmp, namespace)
+        .find_map(|prefix| qualified_property(xmp, &format!("{}:{}", prefix, name)))
+}
+
+// Returns the prefixes declared for the namespace by `xmlns:prefix="namespace"` attributes.
+fn prefixes<'a>(xmp: &'a str, namespace: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+    xmp.match_indices("xmlns:")
+        .filter_map(move |(index, declaration)| {
+            let (prefix, value) = xmp[index + declaration.len()..].split_once('=')?;
+            let prefix = prefix.trim_end();
+            if prefix.is_empty() || prefix.contains(|c: char| c.is_ascii_whitespace() || c == '>') {
+                return None;
+            }
+
+            let value = value.trim_start();
+            let quote = value.chars().next().filter(|&c| c == '"' || c == '\'')?;
+            let value = &value[1..];
+            (value[..value.find(quote)?] == *namespace).then(|| prefix)
+        })
+}
+
+// Returns the values of a property given with its namespace prefix such as `hdrgm:Version`.
+fn qualified_property<'a>(xmp: &'a str, name: &str) -> Option<Vec<&'a str>> {
+    for (index, _) in xmp.match_indices(name) {
+        let after = &xmp[index + name.len()..];
+
+        // The name must not be the end of a longer name.
+        if !xmp[..index].ends_with(|c: char| c == '<' || c.is_ascii_whitespace()) {
+            continue;
+        }
+
+        if xmp[..index].ends_with('<') {
+            if !after.starts_with(|c: char| c == '>' || c.is_ascii_whitespace()) {
+                continue;
+            }
+
+            let content = &after[after.find('>')? + 1..];
+            let content = &content[..content.find(&format!("</{}>", name))?];
+
+            if !content.contains("<rdf:li") {
+                return Some(vec![content]);
+            }
+
+            let mut values = Vec::new();
+            let mut items = content;
+            while let Some(index) = items.find("<rdf:li") {
+                let item = &items[index..];
+                let item = &item[item.find('>')? + 1..];
+                let end = item.find("</rdf:li>")?;
+                values.push(&item[..end]);
+                items = &item[end..];
+            }
+            return Some(values);
+        } else if let Some(value) = after.trim_start().strip_prefix('=') {
+            let value = value.trim_start();
+            let quote = value.chars().next().filter(|&c| c == '"' || c == '\'')?;
+            let value = &value[1..];
+            return Some(vec![&value[..value.find(quote)?]]);
+        }
+    }
+
+    None
+}
@@ -9,6 +9,16 @@ mod common;
 mod crashtest;
 mod reftest;
 
+// Returns the largest difference between the samples of two images of the same size.
+fn max_difference(a: &[u8], b: &[u8]) -> i32 {
+    assert_eq!(a.len(), b.len());
+    a.iter()
+        .zip(b)
+        .map(|(&a, &b)| (i32::from(a) - i32::from(b)).abs())
+        .max()
+        .unwrap()
+}
+
 #[test]
 #[cfg(all(target_family="wasm", target_os="unknown"))]
 #[wasm_bindgen_test::wasm_bindgen_test]
@@ -65,6 +75,92 @@ fn read_icc_profile() {
     assert_eq!(&profile[36..40], b"acsp");
 }
 
+#[test]
+fn parse_icc_profile() {
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("mozilla")
+        .join("jpg-srgb-icc.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+
+    let profile = decoder.icc().unwrap().unwrap();
+    assert_eq!(profile.data.len(), 3048);
+    assert_eq!(profile.version, (2, 0, 0));
+    assert_eq!(profile.class, jpeg::IccProfileClass::Display);
+    assert_eq!(profile.color_space, jpeg::IccColorSpace::Rgb);
+    assert_eq!(profile.pcs, jpeg::IccColorSpace::Xyz);
+    assert_eq!(profile.rendering_intent, jpeg::IccRenderingIntent::Perceptual);
+    assert_eq!(profile.description.as_deref(), Some("sRGB IEC61966-2-1 black scaled"));
+    assert_eq!(&profile.tag(*b"rXYZ").unwrap()[0..4], b"XYZ ");
+    assert!(profile.tag(*b"gamt").is_none());
+}
+
+#[test]
+fn read_icc_profile_header() {
+    let path = Path::new("tests").join("icc").join("icc_v4.jpeg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+
+    let profile = decoder.icc().unwrap().unwrap();
+    assert_eq!(profile.version, (4, 3, 0));
+    assert_eq!(profile.class, jpeg::IccProfileClass::Input);
+    assert_eq!(profile.color_space, jpeg::IccColorSpace::Rgb);
+    assert_eq!(profile.pcs, jpeg::IccColorSpace::Lab);
+    assert_eq!(profile.rendering_intent, jpeg::IccRenderingIntent::Saturation);
+    // The English record of the multiLocalizedUnicodeType tag is preferred.
+    assert_eq!(profile.description.as_deref(), Some("Example RGB – v4"));
+
+    let path = Path::new("tests").join("icc").join("icc_invalid_header.jpeg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+
+    assert!(decoder.icc_profile().is_some());
+    assert!(matches!(
+        decoder.icc(),
+        Err(jpeg::Error::Icc(jpeg::IccError::InvalidHeader))
+    ));
+
+    // No ICC profile
+    let path = Path::new("tests").join("exif").join("orientation_1.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+    assert!(decoder.icc().unwrap().is_none());
+}
+
+#[test]
+fn parse_icc_profile_mluc_records() {
+    let profile = |count: u32, record_size: u32| {
+        let mut data = vec![0; 128];
+        data[36..40].copy_from_slice(b"acsp");
+        // A tag table with a multiLocalizedUnicodeType description, its record not in English.
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(b"desc");
+        data.extend_from_slice(&144u32.to_be_bytes());
+        data.extend_from_slice(&32u32.to_be_bytes());
+        data.extend_from_slice(b"mluc\0\0\0\0");
+        data.extend_from_slice(&count.to_be_bytes());
+        data.extend_from_slice(&record_size.to_be_bytes());
+        data.extend_from_slice(b"deDE");
+        data.extend_from_slice(&4u32.to_be_bytes());
+        data.extend_from_slice(&28u32.to_be_bytes());
+        data.extend_from_slice(&[0, b'A', 0, b'B']);
+        let size = data.len() as u32;
+        data[0..4].copy_from_slice(&size.to_be_bytes());
+        jpeg::IccProfile::parse(data).unwrap()
+    };
+
+    // Only the records that fit in the tag are searched for an English one.
+    assert_eq!(profile(1, 12).description.as_deref(), Some("AB"));
+    assert_eq!(profile(u32::MAX, 12).description.as_deref(), Some("AB"));
+    assert_eq!(profile(1, 4).description, None);
+}
+
 // Test if chunks are concatenated in the correct order
 #[test]
 fn read_icc_profile_random_order() {
@@ -140,6 +236,298 @@ fn read_icc_profile_missing_chunk() {
     assert!(profile.is_none());
 }
 
+// Check that the ICC profiles discarded by `icc_profile` are reported by `icc`
+#[test]
+fn read_icc_profile_errors() {
+    let icc = |name: &str| {
+        let path = Path::new("tests").join("icc").join(name);
+
+        let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+        decoder.read_info().unwrap();
+        decoder.icc()
+    };
+
+    assert!(matches!(
+        icc("icc_chunk_seq_no_0.jpeg"),
+        Err(jpeg::Error::Icc(jpeg::IccError::ChunkOutOfRange {
+            sequence: 0,
+            count: 254
+        }))
+    ));
+    assert!(matches!(
+        icc("icc_chunk_double_seq_no.jpeg"),
+        Err(jpeg::Error::Icc(jpeg::IccError::DuplicateChunk(100)))
+    ));
+    assert!(matches!(
+        icc("icc_chunk_count_mismatch.jpeg"),
+        Err(jpeg::Error::Icc(jpeg::IccError::ChunkCountMismatch {
+            expected: 250,
+            found: 254
+        }))
+    ));
+    assert!(matches!(
+        icc("icc_missing_chunk.jpeg"),
+        Err(jpeg::Error::Icc(jpeg::IccError::MissingChunk(100)))
+    ));
+}
+
+#[test]
+fn convert_icc_colors() {
+    let decode = |path: &Path, color_management: jpeg::ColorManagement| {
+        let mut decoder = jpeg::Decoder::new(File::open(path).unwrap());
+        decoder.set_color_management(color_management);
+        let data = decoder.decode().unwrap();
+        (data, decoder.info().unwrap().pixel_format)
+    };
+    let profile = |path: &Path| {
+        let mut decoder = jpeg::Decoder::new(File::open(path).unwrap());
+        decoder.read_info().unwrap();
+        decoder.icc().unwrap().unwrap()
+    };
+    // IEC 61966-2-1 tone curve
+    let srgb_encode = |sample: u8| {
+        let value = f64::from(sample) / 255.0;
+        let value = if value <= 0.0031308 {
+            12.92 * value
+        } else {
+            1.055 * value.powf(1.0 / 2.4) - 0.055
+        };
+        (value * 255.0).round() as u8
+    };
+    let srgb_decode = |sample: u8| {
+        let value = f64::from(sample) / 255.0;
+        let value = if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        };
+        (value * 255.0).round() as u8
+    };
+
+    let icc = Path::new("tests").join("icc");
+    let (data, _) = decode(&icc.join("cms_srgb.jpeg"), jpeg::ColorManagement::None);
+
+    // sRGB as a matrix/TRC profile, a lut16Type and a lutAtoBType tag
+    for name in ["cms_srgb.jpeg", "cms_lut16.jpeg", "cms_lutab.jpeg"] {
+        let (converted, pixel_format) = decode(&icc.join(name), jpeg::ColorManagement::Srgb);
+        assert_eq!(pixel_format, jpeg::PixelFormat::RGB24);
+        assert!(max_difference(&data, &converted) <= 1, "{}", name);
+    }
+
+    // sRGB primaries with linear tone curves
+    let (converted, _) = decode(&icc.join("cms_linear.jpeg"), jpeg::ColorManagement::Srgb);
+    let expected: Vec<u8> = data.iter().map(|&sample| srgb_encode(sample)).collect();
+    assert!(max_difference(&expected, &converted) <= 1);
+
+    let linear = jpeg::ColorManagement::Profile(profile(&icc.join("cms_linear.jpeg")));
+    let (converted, pixel_format) = decode(&icc.join("cms_srgb.jpeg"), linear);
+    let expected: Vec<u8> = data.iter().map(|&sample| srgb_decode(sample)).collect();
+    assert_eq!(pixel_format, jpeg::PixelFormat::RGB24);
+    assert!(max_difference(&expected, &converted) <= 1);
+
+    // Grayscale images keep a single channel
+    let (data, _) = decode(&icc.join("cms_gray.jpeg"), jpeg::ColorManagement::None);
+    let (converted, pixel_format) =
+        decode(&icc.join("cms_gray.jpeg"), jpeg::ColorManagement::Srgb);
+    let expected: Vec<u8> = data.iter().map(|&sample| srgb_encode(sample)).collect();
+    assert_eq!(pixel_format, jpeg::PixelFormat::L8);
+    assert!(max_difference(&expected, &converted) <= 1);
+
+    // A CMYK output profile with lut16Type tags and a Lab PCS
+    let ycck = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("ycck.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&ycck).unwrap());
+    decoder.set_color_management(jpeg::ColorManagement::Srgb);
+    decoder.read_info().unwrap();
+    assert_eq!(decoder.info().unwrap().pixel_format, jpeg::PixelFormat::RGB24);
+    assert_eq!(decoder.decode().unwrap().len(), 500 * 333 * 3);
+
+    let cmyk = jpeg::ColorManagement::Profile(profile(&ycck));
+    let (converted, pixel_format) = decode(&icc.join("cms_srgb.jpeg"), cmyk);
+    assert_eq!(pixel_format, jpeg::PixelFormat::CMYK32);
+    assert_eq!(converted.len(), 99 * 67 * 4);
+
+    // The RGB profile of a grayscale image is ignored.
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("mozilla")
+        .join("jpg-srgb-icc.jpg");
+
+    let (data, _) = decode(&path, jpeg::ColorManagement::None);
+    let (converted, pixel_format) = decode(&path, jpeg::ColorManagement::Srgb);
+    assert_eq!(pixel_format, jpeg::PixelFormat::L8);
+    assert_eq!(converted, data);
+
+    // Broken profiles are reported.
+    let path = icc.join("icc_missing_chunk.jpeg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.set_color_management(jpeg::ColorManagement::Srgb);
+    assert!(matches!(
+        decoder.decode(),
+        Err(jpeg::Error::Icc(jpeg::IccError::MissingChunk(100)))
+    ));
+}
+
+#[test]
+fn convert_cmyk_to_rgb() {
+    let decode = |path: &Path| {
+        let mut decoder = jpeg::Decoder::new(File::open(path).unwrap());
+        decoder.set_cmyk_to_rgb(true);
+        let data = decoder.decode().unwrap();
+        (data, decoder)
+    };
+
+    // The same image, stored inverted with an Adobe segment and as plain CMYK without one
+    let path = Path::new("tests").join("cmyk").join("cmyk_adobe.jpg");
+    let (adobe, decoder) = decode(&path);
+    assert_eq!(
+        decoder.adobe_color_transform(),
+        Some(jpeg::AdobeColorTransform::Unknown)
+    );
+    assert_eq!(decoder.info().unwrap().pixel_format, jpeg::PixelFormat::RGB24);
+
+    let path = Path::new("tests").join("cmyk").join("cmyk_plain.jpg");
+    let (plain, decoder) = decode(&path);
+    assert_eq!(decoder.adobe_color_transform(), None);
+    assert_eq!(decoder.info().unwrap().pixel_format, jpeg::PixelFormat::RGB24);
+    assert!(max_difference(&adobe, &plain) <= 2);
+
+    // The embedded profile is used if there is one.
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("ycck.jpg");
+    let (data, decoder) = decode(&path);
+    assert_eq!(
+        decoder.adobe_color_transform(),
+        Some(jpeg::AdobeColorTransform::YCCK)
+    );
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.set_color_management(jpeg::ColorManagement::Srgb);
+    assert_eq!(decoder.decode().unwrap(), data);
+
+    // Without the option CMYK is returned as before.
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    assert_eq!(decoder.decode().unwrap().len(), 500 * 333 * 4);
+    assert_eq!(decoder.info().unwrap().pixel_format, jpeg::PixelFormat::CMYK32);
+}
+
+#[test]
+fn decode_output_layouts() {
+    use jpeg::{ColorTransform, OutputLayout, PixelFormat};
+
+    let decode = |path: &Path, transform: Option<ColorTransform>, layout: OutputLayout| {
+        let mut decoder = jpeg::Decoder::new(File::open(path).unwrap());
+        if let Some(transform) = transform {
+            decoder.set_color_transform(transform);
+        }
+        decoder.set_cmyk_to_rgb(true);
+        decoder.set_output_layout(layout);
+        let data = decoder.decode().unwrap();
+        (data, decoder.info().unwrap().pixel_format)
+    };
+    // Arranges RGB samples in a layout with an alpha channel of `alpha`
+    let arrange = |rgb: &[u16], layout: OutputLayout, alpha: u16| -> Vec<u16> {
+        rgb.chunks_exact(3)
+            .flat_map(|pixel| {
+                let [r, g, b] = [pixel[0], pixel[1], pixel[2]];
+                match layout {
+                    OutputLayout::Rgba => vec![r, g, b, alpha],
+                    OutputLayout::Bgr => vec![b, g, r],
+                    OutputLayout::Bgra => vec![b, g, r, alpha],
+                    OutputLayout::Argb => vec![alpha, r, g, b],
+                    _ => unreachable!(),
+                }
+            })
+            .collect()
+    };
+    let widen = |data: &[u8]| -> Vec<u16> { data.iter().map(|&sample| sample.into()).collect() };
+    let layouts = [
+        (OutputLayout::Rgba, PixelFormat::RGBA32),
+        (OutputLayout::Bgr, PixelFormat::BGR24),
+        (OutputLayout::Bgra, PixelFormat::BGRA32),
+        (OutputLayout::Argb, PixelFormat::ARGB32),
+    ];
+
+    // YCbCr, RGB and bg-sYCC images are arranged by the colour conversion, CMYK images after
+    // their conversion to RGB.
+    let images = [
+        Path::new("tests").join("reftest").join("images").join("restarts.jpg"),
+        Path::new("tests").join("reftest").join("images").join("rgb.jpg"),
+        Path::new("tests")
+            .join("reftest")
+            .join("images")
+            .join("colorspaces")
+            .join("bg-sycc.jpg"),
+        Path::new("tests").join("cmyk").join("cmyk_adobe.jpg"),
+    ];
+
+    for path in &images {
+        let (rgb, pixel_format) = decode(path, None, OutputLayout::Native);
+        assert_eq!(pixel_format, PixelFormat::RGB24);
+
+        for &(layout, layout_format) in &layouts {
+            let (data, pixel_format) = decode(path, None, layout);
+            assert_eq!(pixel_format, layout_format);
+            assert_eq!(widen(&data), arrange(&widen(&rgb), layout, 255), "{:?}", path);
+        }
+    }
+
+    // The luma of YCbCr images is their Y component, the luma of RGB images is computed.
+    let path = Path::new("tests").join("reftest").join("images").join("restarts.jpg");
+    let (data, pixel_format) = decode(&path, None, OutputLayout::Luma);
+    assert_eq!(pixel_format, PixelFormat::L8);
+    let (ycbcr, _) = decode(&path, Some(ColorTransform::None), OutputLayout::Native);
+    let y: Vec<u8> = ycbcr.iter().step_by(3).copied().collect();
+    assert_eq!(data, y);
+
+    let path = Path::new("tests").join("reftest").join("images").join("rgb.jpg");
+    let (rgb, _) = decode(&path, None, OutputLayout::Native);
+    let (data, _) = decode(&path, None, OutputLayout::Luma);
+    let luma: Vec<u8> = rgb
+        .chunks_exact(3)
+        .map(|pixel| {
+            let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(u32::from);
+            ((19595 * r + 38470 * g + 7471 * b + 32768) >> 16) as u8
+        })
+        .collect();
+    assert_eq!(data, luma);
+
+    // 12-bit samples are arranged after decoding, with an alpha of the largest sample value.
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("12bit")
+        .join("12bit-ycbcr.jpg");
+    let samples = |data: &[u8]| -> Vec<u16> {
+        data.chunks_exact(2)
+            .map(|sample| u16::from_ne_bytes([sample[0], sample[1]]))
+            .collect()
+    };
+    let (rgb, _) = decode(&path, None, OutputLayout::Native);
+    let (data, pixel_format) = decode(&path, None, OutputLayout::Bgra);
+    assert_eq!(pixel_format, PixelFormat::BGRA64);
+    assert_eq!(samples(&data), arrange(&samples(&rgb), OutputLayout::Bgra, 4095));
+
+    // Grayscale pixels are repeated for each colour channel.
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("grayscale_square.jpg");
+    let (gray, _) = decode(&path, None, OutputLayout::Native);
+    let (data, pixel_format) = decode(&path, None, OutputLayout::Rgba);
+    assert_eq!(pixel_format, PixelFormat::RGBA32);
+    let rgb: Vec<u16> = gray.iter().flat_map(|&sample| [sample.into(); 3]).collect();
+    assert_eq!(widen(&data), arrange(&rgb, OutputLayout::Rgba, 255));
+    assert_eq!(decode(&path, None, OutputLayout::Luma), (gray, PixelFormat::L8));
+}
+
 #[test]
 fn read_exif_data() {
     let path = Path::new("tests")
@@ -155,6 +543,529 @@ fn read_exif_data() {
     assert_eq!(&exif_data[0..8], b"\x49\x49\x2A\x00\x08\x00\x00\x00");
 }
 
+#[test]
+fn read_exif_thumbnail() {
+    for name in ["thumbnail_little_endian.jpg", "thumbnail_big_endian.jpg"] {
+        let path = Path::new("tests").join("exif").join(name);
+
+        let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+        decoder.read_info().unwrap();
+
+        let range = decoder.exif_thumbnail_range().unwrap().unwrap();
+        let exif_data = decoder.exif_data().unwrap();
+        assert_eq!(&exif_data[range.start..range.start + 2], b"\xFF\xD8");
+        assert_eq!(range.end, exif_data.len());
+
+        let mut thumbnail = decoder.exif_thumbnail_decoder().unwrap().unwrap();
+        let data = thumbnail.decode().unwrap();
+        let info = thumbnail.info().unwrap();
+        assert_eq!((info.width, info.height), (50, 34));
+        assert_eq!(data.len(), 50 * 34 * 3);
+    }
+
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("ycck.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+    assert_eq!(decoder.exif_thumbnail_range().unwrap(), Some(1062..1062 + 8474));
+    let mut thumbnail = decoder.exif_thumbnail_decoder().unwrap().unwrap();
+    thumbnail.decode().unwrap();
+
+    // No exif data
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("mpf")
+        .join("mpf.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+    assert!(decoder.exif_thumbnail_range().unwrap().is_none());
+    assert!(decoder.exif_thumbnail_decoder().unwrap().is_none());
+}
+
+#[test]
+fn read_exif_fields() {
+    use jpeg::{ExifIfd, ExifValue, Rational, SRational};
+
+    for name in ["fields_little_endian.jpg", "fields_big_endian.jpg"] {
+        let path = Path::new("tests").join("exif").join(name);
+
+        let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+        decoder.read_info().unwrap();
+
+        let exif = decoder.exif().unwrap().unwrap();
+        assert_eq!(exif.make(), Some("Maker"));
+        assert_eq!(exif.model(), Some("Model 1"));
+        assert_eq!(exif.orientation(), Some(6));
+        assert_eq!(exif.software(), Some("Test"));
+        assert_eq!(exif.date_time(), Some("2024:05:06 07:08:09"));
+        assert_eq!(exif.date_time_original(), Some("2024:05:06 07:08:00"));
+        assert_eq!(exif.exposure_time(), Some(Rational { numerator: 1, denominator: 125 }));
+        assert_eq!(exif.f_number().unwrap().to_f64(), 2.8);
+        assert_eq!(exif.iso(), Some(200));
+        assert_eq!(exif.exposure_bias(), Some(SRational { numerator: -1, denominator: 3 }));
+        assert_eq!(exif.focal_length().unwrap().to_f64(), 50.0);
+
+        let latitude = exif.gps_latitude().unwrap();
+        assert!((latitude + (48.0 + 51.0 / 60.0 + 29.94 / 3600.0)).abs() < 1e-9);
+        let longitude = exif.gps_longitude().unwrap();
+        assert!((longitude + (2.0 + 17.0 / 60.0 + 40.0 / 3600.0)).abs() < 1e-9);
+        assert_eq!(exif.gps_altitude(), Some(-17.5));
+
+        let get = |ifd, tag| exif.get(ifd, tag).cloned();
+        assert_eq!(get(ExifIfd::Interop, 0x0001), Some(ExifValue::Ascii("R98".to_owned())));
+        assert_eq!(get(ExifIfd::Ifd1, 0x0103), Some(ExifValue::Short(vec![6])));
+        assert_eq!(get(ExifIfd::Exif, 0xC000), Some(ExifValue::Byte(vec![1, 2, 3])));
+        assert_eq!(get(ExifIfd::Exif, 0xC001), Some(ExifValue::SByte(vec![-1, 2])));
+        assert_eq!(get(ExifIfd::Exif, 0xC002), Some(ExifValue::Undefined(vec![0, 1, 2, 3, 4])));
+        assert_eq!(get(ExifIfd::Exif, 0xC003), Some(ExifValue::SShort(vec![-2, 3])));
+        assert_eq!(get(ExifIfd::Exif, 0xC004), Some(ExifValue::SLong(vec![-100000])));
+        assert_eq!(get(ExifIfd::Exif, 0xC005), Some(ExifValue::Float(vec![1.5, -0.25])));
+        assert_eq!(get(ExifIfd::Exif, 0xC006), Some(ExifValue::Double(vec![3.125])));
+        assert_eq!(
+            get(ExifIfd::Exif, 0xC007),
+            Some(ExifValue::Unknown { field_type: 99, data: b"ab".to_vec() })
+        );
+        assert_eq!(get(ExifIfd::Exif, 0xC008), None);
+    }
+
+    // The GPS and Interoperability IFD pointers lead back to IFDs that were already read.
+    let path = Path::new("tests").join("exif").join("fields_cycle.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+
+    let exif = decoder.exif().unwrap().unwrap();
+    assert_eq!(exif.make(), Some("Maker"));
+    assert_eq!(exif.iso(), Some(200));
+    assert!(exif
+        .fields()
+        .iter()
+        .all(|field| field.ifd == ExifIfd::Ifd0 || field.ifd == ExifIfd::Exif));
+    assert_eq!(exif.fields().iter().filter(|field| field.tag == 0x010F).count(), 1);
+
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("ycck.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+    let exif = decoder.exif().unwrap().unwrap();
+    assert_eq!(exif.get(ExifIfd::Ifd1, 0x0202).and_then(ExifValue::as_u32), Some(8474));
+}
+
+#[test]
+fn read_exif_thumbnail_outside_segment() {
+    let path = Path::new("tests")
+        .join("exif")
+        .join("thumbnail_outside_segment.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.decode().unwrap();
+
+    assert!(decoder.exif_thumbnail_range().is_err());
+    assert!(decoder.exif_thumbnail_decoder().is_err());
+}
+
+#[test]
+fn read_jfif_thumbnails() {
+    let path = Path::new("tests").join("jfif").join("thumbnails.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+
+    let rgb: Vec<u8> = (0..36).collect();
+    let thumbnails = decoder.jfif_thumbnails();
+    assert_eq!(thumbnails.len(), 4);
+    assert_eq!(
+        thumbnails[0],
+        jpeg::JfifThumbnail::Rgb {
+            width: 4,
+            height: 3,
+            data: rgb.clone(),
+        }
+    );
+
+    match thumbnails[1] {
+        jpeg::JfifThumbnail::Jpeg(ref data) => {
+            let mut thumbnail = jpeg::Decoder::new(&data[..]);
+            thumbnail.decode().unwrap();
+            let info = thumbnail.info().unwrap();
+            assert_eq!((info.width, info.height), (50, 34));
+        }
+        ref thumbnail => panic!("unexpected thumbnail {:?}", thumbnail),
+    }
+
+    // The palette entries hold i * 3, i * 3 + 1 and i * 3 + 2, wrapping around.
+    let data = [0u32, 1, 255, 128, 7, 9]
+        .iter()
+        .flat_map(|&index| (0..3).map(move |c| ((index * 3 + c) % 256) as u8))
+        .collect::<Vec<u8>>();
+    assert_eq!(
+        thumbnails[2],
+        jpeg::JfifThumbnail::Palette {
+            width: 3,
+            height: 2,
+            data,
+        }
+    );
+
+    assert_eq!(
+        thumbnails[3],
+        jpeg::JfifThumbnail::Rgb {
+            width: 4,
+            height: 3,
+            data: rgb.into_iter().rev().collect(),
+        }
+    );
+
+    let data = decoder.decode().unwrap();
+    assert_eq!(data.len(), 99 * 67 * 3);
+}
+
+#[test]
+fn read_pixel_density() {
+    let path = Path::new("tests").join("jfif").join("thumbnails.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+
+    let density = jpeg::PixelDensity {
+        unit: jpeg::DensityUnit::PixelsPerInch,
+        x: 72.0,
+        y: 72.0,
+    };
+    let header = decoder.jfif_header().unwrap();
+    assert_eq!((header.major_version, header.minor_version), (1, 2));
+    assert_eq!(header.density, density);
+    assert_eq!(decoder.pixel_density(), Some(density));
+
+    // JFIF segment giving the pixel aspect ratio only, without exif data
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("mpf")
+        .join("mpf.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+    assert_eq!(
+        decoder.pixel_density(),
+        Some(jpeg::PixelDensity {
+            unit: jpeg::DensityUnit::None,
+            x: 1.0,
+            y: 1.0,
+        })
+    );
+
+    // Exif data without JFIF segment
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("ycck.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+    assert!(decoder.jfif_header().is_none());
+    assert_eq!(
+        decoder.pixel_density(),
+        Some(jpeg::PixelDensity {
+            unit: jpeg::DensityUnit::PixelsPerInch,
+            x: 180.0,
+            y: 180.0,
+        })
+    );
+}
+
+#[test]
+fn read_image_resources() {
+    let path = Path::new("tests").join("psir").join("photoshop.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+
+    // The resources are split over two APP13 segments.
+    assert_eq!(&decoder.psir_data().unwrap()[0..4], b"8BIM");
+    let resources = decoder.image_resources().unwrap();
+    let ids: Vec<u16> = resources.iter().map(|resource| resource.id).collect();
+    assert_eq!(ids, [0x03ED, 0x0404, 0x040C, 0x07D0, 0x0BB7]);
+
+    assert_eq!(
+        resources[0].resolution_info(),
+        Some(jpeg::ResolutionInfo {
+            horizontal_resolution: 300.0,
+            horizontal_resolution_unit: 1,
+            width_unit: 2,
+            vertical_resolution: 300.0,
+            vertical_resolution_unit: 1,
+            height_unit: 2,
+        })
+    );
+    assert_eq!(&resources[1].iptc_data().unwrap()[0..3], b"\x1C\x01\x5A");
+    assert!(resources[1].resolution_info().is_none());
+
+    let thumbnail = resources[2].thumbnail().unwrap();
+    assert_eq!((thumbnail.width, thumbnail.height), (50, 34));
+    assert!(thumbnail.is_jpeg);
+    assert!(!thumbnail.is_bgr);
+    let mut decoder = jpeg::Decoder::new(thumbnail.data);
+    let data = decoder.decode().unwrap();
+    assert_eq!(data.len(), 50 * 34 * 3);
+
+    assert_eq!(resources[3].name, b"Path 1");
+    assert_eq!(resources[4].clipping_path_name(), Some(&b"Path 1"[..]));
+    let path = resources[3].path().unwrap();
+    assert_eq!(path.subpaths.len(), 2);
+    assert!(path.subpaths[0].closed);
+    let anchors: Vec<(f32, f32)> = path.subpaths[0]
+        .knots
+        .iter()
+        .map(|knot| knot.anchor)
+        .collect();
+    assert_eq!(anchors, [(0.25, 0.25), (0.75, 0.25), (0.75, 0.75), (0.25, 0.75)]);
+    assert!(path.subpaths[0].knots[2].linked);
+    assert!(!path.subpaths[0].knots[3].linked);
+    assert!(!path.subpaths[1].closed);
+    assert_eq!(
+        path.subpaths[1].knots[0],
+        jpeg::BezierKnot {
+            linked: false,
+            preceding: (0.5, -0.125),
+            anchor: (0.5, -0.125),
+            leaving: (0.5, -0.125),
+        }
+    );
+    assert_eq!(path.subpaths[1].knots[1].anchor, (1.5, 0.5));
+}
+
+#[test]
+fn read_iptc() {
+    let path = Path::new("tests").join("psir").join("photoshop.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+
+    let iptc = decoder.iptc().unwrap().unwrap();
+    assert_eq!(iptc.datasets().len(), 9);
+    assert_eq!(iptc.title().as_deref(), Some("Sunset"));
+    assert_eq!(iptc.keywords(), ["beach", "Côte d’Azur"]);
+    assert_eq!(iptc.by_lines(), ["Jane Doe"]);
+    assert_eq!(iptc.copyright_notice().as_deref(), Some("© 2026 Example"));
+    assert_eq!(
+        iptc.caption().as_deref(),
+        Some("Évening light — over the sea")
+    );
+    assert!(iptc.headline().is_none());
+    // Record version
+    assert_eq!(iptc.get(2, 0).next().unwrap().data, [0, 4]);
+    // Extended dataset
+    assert_eq!(iptc.get(2, 202).next().unwrap().data, b"abc");
+
+    // ISO 8859-1 text without CodedCharacterSet
+    let path = Path::new("tests").join("psir").join("iptc_latin1.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+
+    let iptc = decoder.iptc().unwrap().unwrap();
+    assert_eq!(iptc.title().as_deref(), Some("Café"));
+    assert_eq!(iptc.by_lines(), ["John Smith"]);
+
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("mpf")
+        .join("mpf.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+    assert!(decoder.iptc().unwrap().is_none());
+}
+
+#[test]
+fn read_comments() {
+    let path = Path::new("tests").join("comments").join("comments.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.decode().unwrap();
+
+    let comments = decoder.comments();
+    assert_eq!(comments.len(), 4);
+    assert_eq!(comments[0], b"encoder: libjpeg-turbo 2.1.5");
+    assert_eq!(comments[2].len(), 1000);
+    assert_eq!(comments[3], b"last");
+    assert_eq!(decoder.comments_lossy()[1], "provenance: café \u{FFFD}");
+
+    // The long comment does not fit, the ones after it still do.
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.set_max_comments_size(100);
+    decoder.read_info().unwrap();
+
+    let comments = decoder.comments_lossy();
+    assert_eq!(comments.len(), 3);
+    assert_eq!(comments[2], "last");
+}
+
+#[test]
+fn read_app_segments() {
+    let path = Path::new("tests").join("app").join("segments.jpg");
+    let file = std::fs::read(&path).unwrap();
+
+    let mut decoder = jpeg::Decoder::new(&file[..]);
+    decoder.set_app_segment_filter(|marker| marker != 0);
+    decoder.decode().unwrap();
+
+    let segments = decoder.app_segments();
+    let markers: Vec<u8> = segments.iter().map(|segment| segment.marker).collect();
+    assert_eq!(markers, [1, 3, 11, 12, 15]);
+
+    for segment in segments {
+        let offset = segment.offset as usize;
+        assert_eq!(file[offset..offset + 2], [0xFF, 0xE0 + segment.marker]);
+        assert_eq!(
+            file[offset + 4..offset + 4 + segment.data.len()],
+            segment.data[..]
+        );
+    }
+
+    assert_eq!(&segments[0].data[0..6], b"Exif\0\0");
+    assert_eq!(segments[1].data, b"META\0\0vendor");
+    assert!(segments[4].data.is_empty());
+
+    // Collected segments are still interpreted by the decoder.
+    assert!(decoder.exif_thumbnail_range().unwrap().is_some());
+
+    // Nothing is collected by default.
+    let mut decoder = jpeg::Decoder::new(&file[..]);
+    decoder.read_info().unwrap();
+    assert!(decoder.app_segments().is_empty());
+}
+
+#[test]
+fn read_jumbf_data() {
+    let path = Path::new("tests").join("jumbf").join("c2pa.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+
+    // The manifest store is split over three segments, stored out of order.
+    let data = decoder.jumbf_data().unwrap().unwrap();
+    assert_eq!(data.len(), 2937);
+    let boxes = jpeg::JumbfBox::parse_all(&data).unwrap();
+    assert_eq!(boxes.len(), 2);
+
+    let store = &boxes[0];
+    assert_eq!(&store.box_type, b"jumb");
+    let description = store.description.unwrap();
+    assert_eq!(description.label, Some("c2pa"));
+    assert_eq!(&description.content_type[0..4], b"c2pa");
+    assert_eq!(store.children.len(), 1);
+
+    let manifest = &store.children[0];
+    assert_eq!(
+        manifest.label(),
+        Some("urn:uuid:0b6e8a8d-5c6a-4e84-9c7a-2f4d1e3a7b10")
+    );
+    let labels: Vec<&str> = manifest
+        .children
+        .iter()
+        .map(|child| child.label().unwrap())
+        .collect();
+    assert_eq!(labels, ["c2pa.assertions", "c2pa.claim", "c2pa.signature"]);
+
+    let assertion = manifest
+        .find("c2pa.assertions")
+        .and_then(|assertions| assertions.find("stds.schema-org.CreativeWork"))
+        .unwrap();
+    assert_eq!(&assertion.children[0].box_type, b"json");
+    assert!(assertion.children[0].data.starts_with(b"{\"author\": \"Jane Doe\""));
+    assert_eq!(assertion.children[0].data.len(), 2534);
+
+    assert_eq!(boxes[1].label(), Some("vendor"));
+    assert_eq!(boxes[1].description.unwrap().id, Some(7));
+
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("mpf")
+        .join("mpf.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+    assert!(decoder.jumbf_data().unwrap().is_none());
+}
+
+#[test]
+fn apply_exif_orientation() {
+    let path = Path::new("tests").join("exif").join("orientation_1.jpg");
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    let reference = decoder.decode().unwrap();
+    let (width, height) = (50, 34);
+
+    for value in 1..=8 {
+        let path = Path::new("tests")
+            .join("exif")
+            .join(format!("orientation_{}.jpg", value));
+
+        // The orientation is only applied when asked for.
+        let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+        assert_eq!(decoder.decode().unwrap(), reference);
+        assert_eq!(decoder.orientation(), jpeg::Orientation::from_exif(value));
+
+        let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+        decoder.set_apply_orientation(true);
+        decoder.read_info().unwrap();
+        let info = decoder.info().unwrap();
+        let data = decoder.decode().unwrap();
+
+        let transposed = value >= 5;
+        let (output_width, output_height) = if transposed {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        assert_eq!(usize::from(info.width), output_width);
+        assert_eq!(usize::from(info.height), output_height);
+
+        for y in 0..output_height {
+            for x in 0..output_width {
+                // Position of the pixel in the stored image
+                let (source_x, source_y) = match value {
+                    1 => (x, y),
+                    2 => (width - 1 - x, y),
+                    3 => (width - 1 - x, height - 1 - y),
+                    4 => (x, height - 1 - y),
+                    5 => (y, x),
+                    6 => (y, height - 1 - x),
+                    7 => (width - 1 - y, height - 1 - x),
+                    _ => (width - 1 - y, x),
+                };
+                let index = (y * output_width + x) * 3;
+                let source_index = (source_y * width + source_x) * 3;
+                assert_eq!(data[index..index + 3], reference[source_index..source_index + 3]);
+            }
+        }
+
+        let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+        decoder.set_apply_orientation(true);
+        decoder.set_color_transform(jpeg::ColorTransform::None);
+        let interleaved = decoder.decode().unwrap();
+
+        let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+        decoder.set_apply_orientation(true);
+        let planes = decoder.decode_planar().unwrap();
+        for (i, plane) in planes.iter().enumerate() {
+            let samples = interleaved.iter().skip(i).step_by(3);
+            assert!(plane.iter().zip(samples).all(|(a, b)| a == b));
+        }
+    }
+}
+
 #[test]
 fn read_xmp_data() {
     let path = Path::new("tests")
@@ -168,3 +1079,334 @@ fn read_xmp_data() {
     let xmp_data = decoder.xmp_data().unwrap();
     assert_eq!(&xmp_data[0..9], b"<?xpacket");
 }
+
+#[test]
+fn read_extended_xmp_data() {
+    let path = Path::new("tests").join("xmp").join("extended.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+
+    let xmp_data = decoder.xmp_data().unwrap();
+    assert!(std::str::from_utf8(xmp_data)
+        .unwrap()
+        .contains("xmpNote:HasExtendedXMP=\"B1ABD547A780BA538B996231C2E49E45\""));
+
+    // The chunks are stored out of order, next to a chunk of another packet.
+    let extended = decoder.extended_xmp_data().unwrap().unwrap();
+    assert_eq!(extended.len(), 6213);
+    assert!(extended.starts_with(b"<x:xmpmeta"));
+    assert!(extended.ends_with(b"</x:xmpmeta>"));
+
+    let path = Path::new("tests")
+        .join("xmp")
+        .join("extended_missing_chunk.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+    assert!(decoder.extended_xmp_data().is_err());
+
+    // XMP without extended packet
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("ycck.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+    assert!(decoder.extended_xmp_data().unwrap().is_none());
+}
+
+#[test]
+fn hierarchical_max_levels() {
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("hierarchical")
+        .join("hierarchical-lossless.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.set_max_hierarchical_levels(1);
+    decoder.read_info().unwrap();
+    let info = decoder.info().unwrap();
+    assert_eq!((info.width, info.height), (50, 34));
+
+    let data = decoder.decode().unwrap();
+    assert_eq!(decoder.info().unwrap(), info);
+    assert_eq!(data.len(), 50 * 34);
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.set_max_hierarchical_levels(2);
+    decoder.read_info().unwrap();
+    let info = decoder.info().unwrap();
+    assert_eq!((info.width, info.height), (99, 67));
+
+    let data = decoder.decode().unwrap();
+    assert_eq!(decoder.info().unwrap(), info);
+    assert_eq!(data.len(), 99 * 67);
+}
+
+#[test]
+fn read_info_dnl() {
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("dnl")
+        .join("dnl-color.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+    let info = decoder.info().unwrap();
+    assert_eq!((info.width, info.height), (99, 0));
+
+    let data = decoder.decode().unwrap();
+    let info = decoder.info().unwrap();
+    assert_eq!((info.width, info.height), (99, 67));
+    assert_eq!(data.len(), 99 * 67 * 3);
+}
+
+#[test]
+fn dnl_decoding_buffer_size_limit() {
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("dnl")
+        .join("dnl-color.jpg");
+
+    // The coefficients of the first scan grow until the DNL segment defines the height.
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.set_max_decoding_buffer_size(99 * 16 * 3);
+    assert!(matches!(decoder.decode(), Err(jpeg::Error::Format(_))));
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.set_max_decoding_buffer_size(99 * 67 * 3);
+    assert!(decoder.decode().is_ok());
+}
+
+#[test]
+fn upsample_rational_sampling_factors() {
+    // Encoded by libjpeg from raw components: Y and Cr are 128, and the 16x16 Cb component of
+    // sampling factors 2x1 in a 3x2 MCU has blocks of 32, 96, 160 and 224 in raster order.
+    let path = Path::new("tests").join("sampling").join("rational.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    let planes = decoder.decode_planar().unwrap();
+    assert!(planes[0].iter().chain(&planes[2]).all(|&sample| sample == 128));
+
+    // Output sample i is centred at (i + 0.5) * 2 / 3 - 0.5 input samples horizontally and
+    // (i + 0.5) / 2 - 0.5 vertically, clamped to the component. It interpolates linearly between
+    // the surrounding input samples and is rounded to the nearest integer.
+    let cb = &planes[1];
+    let row = |y: usize| cb[y * 24..(y + 1) * 24].to_vec();
+    let column = |x: usize| -> Vec<u8> { (0..32).map(|y| cb[y * 24 + x]).collect() };
+    let expected_row = |left: u8, right: u8, between: [u8; 2]| -> Vec<u8> {
+        let mut row = vec![left; 11];
+        row.extend_from_slice(&between);
+        row.resize(24, right);
+        row
+    };
+    let expected_column = |top: u8, bottom: u8, between: [u8; 2]| -> Vec<u8> {
+        let mut column = vec![top; 15];
+        column.extend_from_slice(&between);
+        column.resize(32, bottom);
+        column
+    };
+    assert_eq!(row(0), expected_row(32, 96, [43, 85]));
+    assert_eq!(row(16), expected_row(128, 192, [139, 181]));
+    assert_eq!(row(31), expected_row(160, 224, [171, 213]));
+    assert_eq!(column(0), expected_column(32, 160, [64, 128]));
+    assert_eq!(column(12), expected_column(85, 213, [117, 181]));
+}
+
+#[test]
+fn jpeg_ls_decoding_buffer_size_limit() {
+    // A JPEG-LS frame of 65535x65535 samples without any scan data.
+    let mut data = vec![0xFF, 0xD8];
+    data.extend_from_slice(&[0xFF, 0xF7, 0, 11, 8, 0xFF, 0xFF, 0xFF, 0xFF, 1, 1, 0x11, 0]);
+    data.extend_from_slice(&[0xFF, 0xDA, 0, 8, 1, 1, 0, 0, 0, 0]);
+    data.extend_from_slice(&[0xFF, 0xD9]);
+
+    let mut decoder = jpeg::Decoder::new(&data[..]);
+    decoder.set_max_decoding_buffer_size(1 << 20);
+    assert!(matches!(decoder.decode(), Err(jpeg::Error::Format(_))));
+}
+
+#[test]
+fn jpeg_ls_point_transform() {
+    // A JPEG-LS scan with a point transform of one bit.
+    let mut data = vec![0xFF, 0xD8];
+    data.extend_from_slice(&[0xFF, 0xF7, 0, 11, 8, 0, 1, 0, 1, 1, 1, 0x11, 0]);
+    data.extend_from_slice(&[0xFF, 0xDA, 0, 8, 1, 1, 0, 0, 0, 1]);
+    data.extend_from_slice(&[0xFF, 0xD9]);
+
+    let mut decoder = jpeg::Decoder::new(&data[..]);
+    assert!(matches!(
+        decoder.decode(),
+        Err(jpeg::Error::Unsupported(jpeg::UnsupportedFeature::PointTransform))
+    ));
+}
+
+#[test]
+fn decode_planar() {
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("components")
+        .join("5-components.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    let data = decoder.decode().unwrap();
+    let info = decoder.info().unwrap();
+    assert_eq!(info.pixel_format, jpeg::PixelFormat::Channels8(5));
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    let planes = decoder.decode_planar().unwrap();
+    assert_eq!(planes.len(), 5);
+
+    for (i, plane) in planes.iter().enumerate() {
+        let channel: Vec<u8> = data.iter().skip(i).step_by(5).copied().collect();
+        assert_eq!(plane, &channel);
+    }
+}
+
+#[test]
+fn read_mpf_images() {
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("mpf")
+        .join("mpf.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    decoder.read_info().unwrap();
+
+    let images = decoder.mpf_images().unwrap().to_vec();
+    assert_eq!(images.len(), 2);
+    assert_eq!(images[0].image_type, jpeg::MpImageType::BaselinePrimary);
+    assert!(images[0].is_representative);
+    assert_eq!(images[0].offset, 0);
+    assert_eq!(images[1].image_type, jpeg::MpImageType::LargeThumbnail(1));
+    assert!(!images[1].is_representative);
+    assert_eq!(images[1].offset, images[0].size as u64);
+
+    let mut thumbnail = decoder.mpf_image_decoder(images[1]).unwrap();
+    let data = thumbnail.decode().unwrap();
+    let info = thumbnail.info().unwrap();
+    assert_eq!((info.width, info.height), (50, 34));
+    assert_eq!(data.len(), 50 * 34 * 3);
+    // Only the primary image has an MP Index.
+    assert!(thumbnail.mpf_images().is_none());
+
+    // The input is back where the primary image continues.
+    let data = decoder.decode().unwrap();
+    assert_eq!(data.len(), 99 * 67 * 3);
+}
+
+#[test]
+fn decode_ultra_hdr_gain_map() {
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("ultrahdr")
+        .join("ultrahdr.jpg");
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    let gain_map = decoder.decode_gain_map().unwrap().unwrap();
+    assert_eq!(gain_map.metadata.gain_map_min, [0.0; 3]);
+    assert_eq!(gain_map.metadata.gain_map_max, [2.0; 3]);
+    assert_eq!(gain_map.metadata.gamma, [1.0; 3]);
+    assert_eq!(gain_map.metadata.hdr_capacity_max, 2.0);
+    assert!(!gain_map.metadata.base_rendition_is_hdr);
+    assert_eq!((gain_map.info.width, gain_map.info.height), (50, 34));
+
+    let sdr = decoder.decode().unwrap();
+    let info = decoder.info().unwrap();
+    let to_f32 = |data: Vec<u8>| -> Vec<f32> {
+        data.chunks_exact(4)
+            .map(|bytes| f32::from_ne_bytes(bytes.try_into().unwrap()))
+            .collect()
+    };
+    let linear = |value: u8| {
+        let value = f32::from(value) / 255.0;
+        if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    };
+
+    // Without headroom the SDR rendition is reconstructed.
+    let hdr = to_f32(gain_map.apply(&sdr, &info, 1.0, jpeg::HdrFormat::F32).unwrap());
+    assert_eq!(hdr.len(), sdr.len());
+    for (&hdr, &sdr) in hdr.iter().zip(&sdr) {
+        assert!((hdr - linear(sdr)).abs() < 1e-5);
+    }
+
+    // The display boost must be a positive number.
+    for display_boost in [0.0, -1.0, f32::NAN] {
+        assert!(matches!(
+            gain_map.apply(&sdr, &info, display_boost, jpeg::HdrFormat::F32),
+            Err(jpeg::Error::Format(_))
+        ));
+    }
+
+    // The left half of the gain map boosts by two stops, the right half keeps the SDR values.
+    let hdr = to_f32(gain_map.apply(&sdr, &info, 4.0, jpeg::HdrFormat::F32).unwrap());
+    let offset = 1.0 / 64.0;
+    for y in 0..67 {
+        for x in (0..40).chain(71..99) {
+            for c in 0..3 {
+                let index = (y * 99 + x) * 3 + c;
+                let gain = if x < 40 { 4.0 } else { 1.0 };
+                let expected = (linear(sdr[index]) + offset) * gain - offset;
+                assert!((hdr[index] - expected).abs() < 1e-4);
+            }
+        }
+    }
+
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    let half = decoder
+        .decode_hdr(4.0, jpeg::HdrFormat::F16)
+        .unwrap()
+        .unwrap();
+    assert_eq!(half.len(), hdr.len() * 2);
+    for (half, &hdr) in half.chunks_exact(2).zip(&hdr) {
+        let half = u16::from_ne_bytes(half.try_into().unwrap());
+        let exponent = i32::from(half >> 10) - 15;
+        let value = (1.0 + f32::from(half & 0x3FF) / 1024.0) * 2f32.powi(exponent);
+        let value = if half >> 10 == 0 { f32::from(half) / 16_777_216.0 } else { value };
+        assert!((value - hdr).abs() <= hdr * 1e-3 + 1e-7);
+    }
+
+    // Images without a gain map are decoded as before.
+    let path = Path::new("tests")
+        .join("reftest")
+        .join("images")
+        .join("mpf")
+        .join("mpf.jpg");
+    let mut decoder = jpeg::Decoder::new(File::open(&path).unwrap());
+    assert!(decoder.decode_gain_map().unwrap().is_none());
+}
+
+#[test]
+fn read_gain_map_metadata_prefix() {
+    // The prefix is taken from the namespace declaration.
+    let xmp = br#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+  <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+    <rdf:Description xmlns:gm="http://ns.adobe.com/hdr-gain-map/1.0/"
+        gm:Version="1.0" gm:HDRCapacityMax="2.5">
+      <gm:GainMapMax>
+        <rdf:Seq><rdf:li>1</rdf:li><rdf:li>2</rdf:li><rdf:li>3</rdf:li></rdf:Seq>
+      </gm:GainMapMax>
+    </rdf:Description>
+  </rdf:RDF>
+</x:xmpmeta>"#;
+    let metadata = jpeg::GainMapMetadata::from_xmp(xmp).unwrap();
+    assert_eq!(metadata.gain_map_max, [1.0, 2.0, 3.0]);
+    assert_eq!(metadata.hdr_capacity_max, 2.5);
+
+    // The usual prefix bound to another namespace is not the gain map metadata.
+    let xmp = br#"<rdf:Description xmlns:hdrgm="http://example.com/other/"
+    hdrgm:Version="1.0" hdrgm:GainMapMax="2" hdrgm:HDRCapacityMax="2"/>"#;
+    assert!(jpeg::GainMapMetadata::from_xmp(xmp).is_none());
+}
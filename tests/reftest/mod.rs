@@ -51,14 +51,21 @@ fn reftest_decoder<T: std::io::Read>(mut decoder: jpeg::Decoder<T>, path: &Path,
     let ref_file = File::open(ref_path).unwrap();
     let mut decoder = png::Decoder::new(ref_file);
 
-    if pixel_format == jpeg::PixelFormat::L16 {
+    if pixel_format == jpeg::PixelFormat::L16 || pixel_format == jpeg::PixelFormat::RGB48 {
         // disable the default 8bit output of png v0.16.8 (fixed in master branch of png)
         decoder.set_transformations(png::Transformations::EXPAND);
     }
     
     let (ref_info, mut ref_reader) = decoder.read_info().expect("png failed to read info");
 
-    assert_eq!(ref_info.width, info.width as u32);
+    // Images with other numbers of channels are compared to a grayscale image with a column for
+    // each channel of a pixel.
+    let channels = match pixel_format {
+        jpeg::PixelFormat::Channels8(channels) => channels as u32,
+        _ => 1,
+    };
+
+    assert_eq!(ref_info.width, info.width as u32 * channels);
     assert_eq!(ref_info.height, info.height as u32);
 
     let mut ref_data = vec![0; ref_info.buffer_size()];
@@ -87,6 +94,17 @@ fn reftest_decoder<T: std::io::Read>(mut decoder: jpeg::Decoder<T>, path: &Path,
             assert_eq!(ref_info.bit_depth, png::BitDepth::Eight);
             (ref_data.iter().map(|x| *x as u16).collect(), data.iter().map(|x| *x as u16).collect())
         },
+        jpeg::PixelFormat::Channels8(_) => {
+            assert_eq!(ref_pixel_format, png::ColorType::Grayscale);
+            assert_eq!(ref_info.bit_depth, png::BitDepth::Eight);
+            (ref_data.iter().map(|x| *x as u16).collect(), data.iter().map(|x| *x as u16).collect())
+        },
+        jpeg::PixelFormat::RGB48 => {
+            assert_eq!(ref_pixel_format, png::ColorType::RGB);
+            assert_eq!(ref_info.bit_depth, png::BitDepth::Sixteen);
+            (ref_data.chunks_exact(2).map(|a| u16::from_be_bytes([a[0],a[1]])).collect(),
+            data.chunks_exact(2).map(|a| u16::from_ne_bytes([a[0],a[1]])).collect())
+        },
         _ => panic!(),
     };
 
@@ -111,7 +129,7 @@ fn reftest_decoder<T: std::io::Read>(mut decoder: jpeg::Decoder<T>, path: &Path,
     if pixels.iter().any(|&a| a < 255) {
         let output_path = path.with_file_name(format!("{}-diff.png", path.file_stem().unwrap().to_str().unwrap()));
         let output = File::create(&output_path).unwrap();
-        let mut encoder = png::Encoder::new(output, info.width as u32, info.height as u32);
+        let mut encoder = png::Encoder::new(output, ref_info.width, ref_info.height);
         encoder.set_depth(png::BitDepth::Eight);
         encoder.set_color(ref_pixel_format);
         encoder.write_header().expect("png failed to write header").write_image_data(&pixels).expect("png failed to write data");